@@ -0,0 +1,27 @@
+//! Embed build information reported by `envsh env`
+
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+fn main() {
+    let git_hash = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=ENVSH_GIT_HASH={git_hash}");
+    println!(
+        "cargo:rustc-env=ENVSH_TARGET={}",
+        env::var("TARGET").unwrap()
+    );
+
+    // Published crates have no .git, and watching a missing path would rebuild every time
+    if Path::new(".git/HEAD").exists() {
+        println!("cargo:rerun-if-changed=.git/HEAD");
+        println!("cargo:rerun-if-changed=.git/refs");
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+}
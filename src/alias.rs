@@ -0,0 +1,138 @@
+//! Local names for uploads in the history, kept in `$XDG_DATA_HOME/envsh/aliases.json`
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::PathBuf;
+
+use crate::error::{Context, EnvshError, Result};
+use crate::history::{self, History};
+use crate::{ManageTarget, json, paths};
+
+/// Alias names and the history IDs they point at
+#[derive(Debug, Default)]
+struct Aliases {
+    /// History ID for each alias
+    names: BTreeMap<String, u64>,
+}
+
+impl Aliases {
+    /// Read the aliases, which are empty if none were added yet
+    fn load() -> Result<Self> {
+        match fs::read(path()?) {
+            Ok(contents) => Ok(Self {
+                names: serde_json::from_slice(&contents)
+                    .map_err(io::Error::from)
+                    .context("failed to read aliases")?,
+            }),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).context("failed to read aliases"),
+        }
+    }
+
+    /// Write the aliases back
+    fn save(&self) -> Result<()> {
+        history::write_private(&path()?, &self.names).context("failed to save aliases")
+    }
+}
+
+/// Location of the alias file
+fn path() -> Result<PathBuf> {
+    Ok(paths::data_dir()?.join("aliases.json"))
+}
+
+/// History ID that `name` points at, or why there isn't one
+pub(crate) fn resolve(name: &str) -> Result<u64> {
+    let aliases = Aliases::load()?;
+    aliases
+        .names
+        .get(name)
+        .copied()
+        .ok_or_else(|| missing(name))
+}
+
+/// Drop the aliases pointing at `ids`, once their uploads are forgotten
+pub(crate) fn forget(ids: &[u64]) -> Result<()> {
+    let mut aliases = Aliases::load()?;
+    let before = aliases.names.len();
+    aliases.names.retain(|_, id| !ids.contains(id));
+    if aliases.names.len() == before {
+        return Ok(());
+    }
+    aliases.save()
+}
+
+/// Error for an alias that doesn't exist
+fn missing(name: &str) -> EnvshError {
+    EnvshError::Usage(format!("no alias named @{name}, see `envsh alias list`"))
+}
+
+/// Point `name` at the upload `target` refers to
+pub(crate) fn add(name: String, target: &ManageTarget) -> Result<()> {
+    history::check_writable("alias add")?;
+    let entry = history::find(target)?;
+    let mut aliases = Aliases::load()?;
+    match aliases.names.insert(name.clone(), entry.id) {
+        Some(old) if old != entry.id => {
+            println!(
+                "@{name} now points at @{} ({}), was @{old}",
+                entry.id, entry.url
+            )
+        }
+        _ => println!("@{name} points at @{} ({})", entry.id, entry.url),
+    }
+    aliases.save()
+}
+
+/// Remove the alias `name`
+pub(crate) fn remove(name: &str) -> Result<()> {
+    history::check_writable("alias rm")?;
+    let mut aliases = Aliases::load()?;
+    if aliases.names.remove(name).is_none() {
+        return Err(missing(name));
+    }
+    aliases.save()?;
+    println!("Removed @{name}");
+    Ok(())
+}
+
+/// Print each alias with the upload it points at
+pub(crate) fn list(json: bool) -> Result<()> {
+    let aliases = Aliases::load()?;
+    let history = History::load()?;
+    let url = |id: u64| {
+        history
+            .entries
+            .iter()
+            .find(|e| e.id == id)
+            .map(|e| e.url.clone())
+    };
+
+    if json {
+        for (name, &id) in &aliases.names {
+            json::print(&json::Alias {
+                name: name.clone(),
+                id,
+                url: url(id),
+            });
+        }
+    } else if aliases.names.is_empty() {
+        println!("No aliases yet, add one with `envsh alias add NAME @ID`");
+    } else {
+        let width = aliases
+            .names
+            .keys()
+            .map(|n| n.chars().count())
+            .max()
+            .unwrap()
+            + 1;
+        for (name, &id) in &aliases.names {
+            let at_name = format!("@{name}");
+            match url(id) {
+                Some(url) => println!("{at_name:width$}  @{id}  {url}"),
+                None => println!("{at_name:width$}  @{id}  (no longer in the history)"),
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,127 @@
+//! Packing directories into archives so they can be uploaded as a single file
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+use crate::temp::TempFile;
+
+/// Archive formats a directory can be packed into
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum Format {
+    /// Gzip-compressed tarball
+    #[value(name = "tar.gz")]
+    TarGz,
+    /// Zstandard-compressed tarball
+    #[value(name = "tar.zst")]
+    TarZst,
+}
+
+impl Format {
+    /// File extension, without the leading dot
+    fn extension(self) -> &'static str {
+        match self {
+            Self::TarGz => "tar.gz",
+            Self::TarZst => "tar.zst",
+        }
+    }
+}
+
+/// Pack `dir` into a temporary archive, returning it and the name it should be uploaded as
+///
+/// Symlinks are archived as links unless `follow_symlinks` is set, in which case their targets
+/// are archived instead, as long as they stay inside `dir`.
+pub(crate) fn pack(
+    dir: &Path,
+    format: Format,
+    follow_symlinks: bool,
+) -> io::Result<(TempFile, String)> {
+    let canonical = dir.canonicalize()?;
+    if follow_symlinks {
+        check_links(dir, &canonical, &mut vec![canonical.clone()])?;
+    }
+    let root = canonical
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "archive".to_string());
+    let name = format!("{root}.{}", format.extension());
+    let (temp, file) = TempFile::create(&format!(".{}", format.extension()))?;
+
+    match format {
+        Format::TarGz => {
+            let encoder = GzEncoder::new(file, Compression::default());
+            write_tar(encoder, dir, &root, follow_symlinks)?
+                .finish()?
+                .sync_all()?;
+        }
+        Format::TarZst => {
+            let encoder = zstd::Encoder::new(file, 0)?;
+            write_tar(encoder, dir, &root, follow_symlinks)?
+                .finish()?
+                .sync_all()?;
+        }
+    }
+    Ok((temp, name))
+}
+
+/// Write a tarball of `dir` to `out`, with entries under `root/`
+fn write_tar<W: Write>(out: W, dir: &Path, root: &str, follow_symlinks: bool) -> io::Result<W> {
+    let mut builder = tar::Builder::new(out);
+    builder.follow_symlinks(follow_symlinks);
+    builder.append_dir_all(root, dir)?;
+    builder.into_inner()
+}
+
+/// Ensure every symlink under `dir` resolves inside `root`, and that linked directories don't loop
+///
+/// `walked` holds the resolved directories between `root` and `dir`, inclusive.
+fn check_links(dir: &Path, root: &Path, walked: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let meta = fs::symlink_metadata(&path)?;
+
+        let resolved = if meta.is_symlink() {
+            let resolved = path.canonicalize().map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("{} is a broken link: {e}", path.display()),
+                )
+            })?;
+            if !resolved.starts_with(root) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{} points to {}, outside {}; use --no-follow-symlinks to archive it as a link",
+                        path.display(),
+                        resolved.display(),
+                        root.display()
+                    ),
+                ));
+            }
+            if walked.contains(&resolved) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{} loops back to {}; use --no-follow-symlinks to archive it as a link",
+                        path.display(),
+                        resolved.display()
+                    ),
+                ));
+            }
+            resolved
+        } else {
+            path.clone()
+        };
+
+        if resolved.is_dir() {
+            walked.push(resolved.canonicalize()?);
+            check_links(&path, root, walked)?;
+            walked.pop();
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,110 @@
+//! Upload throughput benchmark
+
+use std::io::{self, Read};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use envsh::{Change, Content, EnvsClient, Expiry, Upload};
+use reqwest::blocking::multipart::Part;
+
+use crate::config;
+use crate::error::{Context, Result};
+
+/// Pseudo-random bytes, so neither the host nor any proxy can deduplicate or compress them
+struct Noise {
+    /// xorshift64 state
+    state: u64,
+    /// Bytes left to produce
+    remaining: u64,
+}
+
+impl Read for Noise {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf
+            .len()
+            .min(self.remaining.try_into().unwrap_or(usize::MAX));
+        for chunk in buf[..n].chunks_mut(8) {
+            self.state ^= self.state << 13;
+            self.state ^= self.state >> 7;
+            self.state ^= self.state << 17;
+            chunk.copy_from_slice(&self.state.to_le_bytes()[..chunk.len()]);
+        }
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+/// Upload `size` bytes of generated data `runs` times, then delete each upload
+pub(crate) fn run(size: u64, runs: u32) -> Result<()> {
+    let envs = EnvsClient::with_http_client(config::get().client()).with_host(config::get().host());
+
+    let latency_start = Instant::now();
+    envs.http()
+        .head(envs.host().clone())
+        .send()
+        .context(format!("failed to reach {}", envs.host()))?;
+    println!("Latency: {}", format_duration(latency_start.elapsed()));
+
+    for run in 1..=runs {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64
+            | 1;
+        let noise = Noise {
+            state: seed ^ u64::from(run).rotate_left(32),
+            remaining: size,
+        };
+        let part = Part::reader_with_length(noise, size)
+            .file_name("envsh-bench.bin")
+            .mime_str("application/octet-stream")
+            .unwrap();
+
+        let start = Instant::now();
+        let created = envs
+            .upload(Upload {
+                content: Content::File(part),
+                secret: true,
+                expires: Some(Expiry::Hours(1)),
+            })
+            .context(format!("run {run} failed"))?;
+        let elapsed = start.elapsed();
+
+        let status = created.status;
+        let url = created.body.trim().to_string();
+        if !status.is_success() {
+            println!("Run {run}: [{}] {url}", status.as_u16());
+            continue;
+        }
+
+        println!(
+            "Run {run}: {size} bytes in {} ({}/s)",
+            format_duration(elapsed),
+            format_bytes(size as f64 / elapsed.as_secs_f64())
+        );
+
+        match (created.url(), created.token) {
+            (Some(parsed), Some(token)) => {
+                if !envs
+                    .change(parsed, &token, &Change::Delete)
+                    .is_ok_and(|changed| changed.is_success())
+                {
+                    println!("Could not delete {url}, it will expire in an hour")
+                }
+            }
+            _ => println!("No X-Token returned, {url} will expire in an hour"),
+        }
+    }
+    Ok(())
+}
+
+/// Format a duration in milliseconds
+fn format_duration(duration: Duration) -> String {
+    format!("{:.1} ms", duration.as_secs_f64() * 1000.0)
+}
+
+/// Format a byte count with a binary unit
+pub(crate) fn format_bytes(bytes: f64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB"];
+    let exp = (bytes.max(1.0).log2() / 10.0).floor().min(3.0) as i32;
+    format!("{:.2} {}", bytes / 1024f64.powi(exp), units[exp as usize])
+}
@@ -0,0 +1,50 @@
+//! Opening resulting URLs in the default browser, for `--open`
+//!
+//! Uses `$BROWSER` if set, and otherwise `open` on macOS, the URL protocol handler on Windows and
+//! `xdg-open` elsewhere.
+
+use std::env;
+use std::io;
+use std::process::{Command, Stdio};
+
+/// Open `url` in the user's browser
+pub(crate) fn open(url: &str) -> io::Result<()> {
+    if let Some(browser) = env::var("BROWSER").ok().filter(|b| !b.trim().is_empty()) {
+        let mut args = browser.split_whitespace();
+        let program = args.next().unwrap();
+        // A browser started here may run until it is closed, so it isn't waited for
+        return Command::new(program)
+            .args(args)
+            .arg(url)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map(drop)
+            .map_err(|e| io::Error::new(e.kind(), format!("failed to run {program}: {e}")));
+    }
+
+    let mut command = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(windows) {
+        // Not `cmd /C start`, which would run anything after a `&` in the URL as a command
+        let mut command = Command::new("rundll32");
+        command.arg("url.dll,FileProtocolHandler");
+        command
+    } else {
+        Command::new("xdg-open")
+    };
+    let program = command.get_program().to_string_lossy().into_owned();
+    let status = command
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_err(|e| io::Error::new(e.kind(), format!("failed to run {program}: {e}")))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("{program} failed ({status})")))
+    }
+}
@@ -2,17 +2,23 @@
 
 use std::ffi::OsStr;
 use std::path::PathBuf;
-use std::str::FromStr;
+use std::time::Duration;
 
 use clap::builder::{NonEmptyStringValueParser, TypedValueParser};
-use clap::error::{ContextKind, ContextValue, ErrorKind};
-use clap::{Arg, Command, Error, value_parser};
-use jiff::Timestamp;
+use clap::error::ErrorKind;
+use clap::{Arg, Command, Error};
+use jiff::{Timestamp, Zoned};
 use reqwest::Url;
+use reqwest::blocking::multipart::Part;
 
-use crate::{Expiry, Target};
+use envsh::Token;
 
-/// Validates that either a valid file path or remote URL was provided
+use crate::parse::{self, EnvsUrlError};
+use crate::paths;
+use crate::template::Template;
+use crate::{Expiry, ManageTarget, Target};
+
+/// Validates that either a valid file path, matching glob or remote URL was provided
 #[derive(Clone)]
 pub(crate) struct TargetValueParser;
 
@@ -26,17 +32,27 @@ impl TypedValueParser for TargetValueParser {
         value: &OsStr,
     ) -> Result<Self::Value, Error> {
         let target = NonEmptyStringValueParser::new().parse_ref(cmd, arg, value)?;
-        if let Some(path) = PathBuf::from_str(&target).ok().filter(|p| p.is_file()) {
-            Ok(Target::File(path))
-        } else {
-            Ok(Target::Url(
-                Url::from_str(&target).map_err(|e| Error::raw(ErrorKind::ValueValidation, e))?,
-            ))
-        }
+        parse::target(&target).map_err(|e| {
+            // Without storage access, shared files look like they don't exist at all
+            let path = paths::expand_termux(&target).unwrap_or_else(|| PathBuf::from(&*target));
+            let hint = if paths::is_termux() && paths::is_shared_storage(&path) {
+                "\n\ntip: run `termux-setup-storage` to let Termux read shared storage"
+            } else {
+                ""
+            };
+            Error::raw(
+                ErrorKind::ValueValidation,
+                format!(
+                    "'{target}' is not an existing path, a glob matching one, or a URL: {e}{hint}\n"
+                ),
+            )
+            .with_cmd(cmd)
+        })
     }
 }
 
-/// Validates that the provided value is expiry time in hours, or a timestamp
+/// Validates that the provided value is expiry time in hours, a timestamp, a duration such as
+/// `3d`, or a date such as `2025-07-01`
 #[derive(Clone)]
 pub(crate) struct ExpiryValueParser;
 
@@ -49,19 +65,328 @@ impl TypedValueParser for ExpiryValueParser {
         arg: Option<&Arg>,
         value: &OsStr,
     ) -> Result<Self::Value, Error> {
-        let expiry = value_parser!(i64).parse_ref(cmd, arg, value)?;
+        let expiry = NonEmptyStringValueParser::new().parse_ref(cmd, arg, value)?;
+        let parsed = match (expiry.parse::<i64>(), parse::duration(&expiry)) {
+            (Ok(n), _) => parse::expiry(n),
+            (_, Some(duration)) => parse::expiry_in(duration, Timestamp::now())
+                .map_err(|e| format!("invalid expiry '{expiry}': {e}")),
+            // Dates start with a year, which no other form does
+            _ if expiry.len() > 4 && expiry.as_bytes()[..4].iter().all(u8::is_ascii_digit) => {
+                parse::expiry_date(&expiry, &Zoned::now())
+            }
+            _ => Err(format!(
+                "invalid expiry '{expiry}', expected hours, epoch milliseconds, a duration such \
+                 as 90m, 12h, 3d or 2w, or a date such as 2025-07-01"
+            )),
+        };
+        parsed.map_err(|e| Error::raw(ErrorKind::ValueValidation, format!("{e}\n")).with_cmd(cmd))
+    }
+}
+
+/// Resolves a local weekday and/or time of day (e.g. "monday 09:00") to its next occurrence
+#[derive(Clone)]
+pub(crate) struct ExpiresAtValueParser;
+
+impl TypedValueParser for ExpiresAtValueParser {
+    type Value = Expiry;
+
+    fn parse_ref(
+        &self,
+        cmd: &Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, Error> {
+        let when = NonEmptyStringValueParser::new().parse_ref(cmd, arg, value)?;
+        parse::expires_at(&when, &Zoned::now())
+            .map(|at| Expiry::Timestamp(at.timestamp()))
+            .map_err(|e| Error::raw(ErrorKind::ValueValidation, format!("{e}\n")).with_cmd(cmd))
+    }
+}
+
+/// Parses a size in bytes, with an optional binary `K`/`M`/`G` suffix (e.g. `64K`, `1MiB`)
+#[derive(Clone)]
+pub(crate) struct ByteSizeValueParser;
+
+impl TypedValueParser for ByteSizeValueParser {
+    type Value = u64;
+
+    fn parse_ref(
+        &self,
+        cmd: &Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, Error> {
+        let size = NonEmptyStringValueParser::new().parse_ref(cmd, arg, value)?;
+        parse::byte_size(&size).ok_or_else(|| {
+            Error::raw(
+                ErrorKind::ValueValidation,
+                format!("invalid size '{size}', expected e.g. 512, 64K, or 8M\n"),
+            )
+            .with_cmd(cmd)
+        })
+    }
+}
+
+/// Parses a non-negative number of seconds, e.g. `2` or `0.5`
+#[derive(Clone)]
+pub(crate) struct SecondsValueParser;
+
+impl TypedValueParser for SecondsValueParser {
+    type Value = Duration;
+
+    fn parse_ref(
+        &self,
+        cmd: &Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, Error> {
+        let secs = NonEmptyStringValueParser::new().parse_ref(cmd, arg, value)?;
+        parse::seconds(&secs).ok_or_else(|| {
+            Error::raw(
+                ErrorKind::ValueValidation,
+                format!("invalid duration '{secs}', expected seconds, e.g. 2 or 0.5\n"),
+            )
+            .with_cmd(cmd)
+        })
+    }
+}
+
+/// Parses a duration with a unit, e.g. `90m`, `12h`, `3d` or `2w`
+#[derive(Clone)]
+pub(crate) struct DurationValueParser;
+
+impl TypedValueParser for DurationValueParser {
+    type Value = Duration;
+
+    fn parse_ref(
+        &self,
+        cmd: &Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, Error> {
+        let duration = NonEmptyStringValueParser::new().parse_ref(cmd, arg, value)?;
+        parse::duration(&duration).ok_or_else(|| {
+            Error::raw(
+                ErrorKind::ValueValidation,
+                format!("invalid duration '{duration}', expected e.g. 90m, 12h, 3d or 2w\n"),
+            )
+            .with_cmd(cmd)
+        })
+    }
+}
+
+/// Validates a curl-style `HOST:PORT:ADDR` address override, keeping it as given
+#[derive(Clone)]
+pub(crate) struct ResolveValueParser;
+
+impl TypedValueParser for ResolveValueParser {
+    type Value = String;
+
+    fn parse_ref(
+        &self,
+        cmd: &Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, Error> {
+        let resolve = NonEmptyStringValueParser::new().parse_ref(cmd, arg, value)?;
+        parse::resolve(&resolve)
+            .map(|_| resolve)
+            .map_err(|e| Error::raw(ErrorKind::ValueValidation, format!("{e}\n")).with_cmd(cmd))
+    }
+}
+
+/// Validates a MIME type, such as `text/plain; charset=utf-8`
+#[derive(Clone)]
+pub(crate) struct ContentTypeValueParser;
+
+impl TypedValueParser for ContentTypeValueParser {
+    type Value = String;
+
+    fn parse_ref(
+        &self,
+        cmd: &Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, Error> {
+        let content_type = NonEmptyStringValueParser::new().parse_ref(cmd, arg, value)?;
+        // Checked the way the part will parse it
+        match Part::text("").mime_str(&content_type) {
+            Ok(_) => Ok(content_type),
+            Err(_) => Err(Error::raw(
+                ErrorKind::ValueValidation,
+                format!("invalid content type '{content_type}', expected TYPE/SUBTYPE\n"),
+            )
+            .with_cmd(cmd)),
+        }
+    }
+}
+
+/// Validates a file extension, stripping its leading dot
+#[derive(Clone)]
+pub(crate) struct ExtensionValueParser;
+
+impl TypedValueParser for ExtensionValueParser {
+    type Value = String;
+
+    fn parse_ref(
+        &self,
+        cmd: &Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, Error> {
+        let ext = NonEmptyStringValueParser::new().parse_ref(cmd, arg, value)?;
+        parse::extension(&ext)
+            .map_err(|e| Error::raw(ErrorKind::ValueValidation, format!("{e}\n")).with_cmd(cmd))
+    }
+}
+
+/// Validates a `--format` template
+#[derive(Clone)]
+pub(crate) struct TemplateValueParser;
+
+impl TypedValueParser for TemplateValueParser {
+    type Value = Template;
+
+    fn parse_ref(
+        &self,
+        cmd: &Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, Error> {
+        let template = NonEmptyStringValueParser::new().parse_ref(cmd, arg, value)?;
+        Template::parse(&template)
+            .map_err(|e| Error::raw(ErrorKind::ValueValidation, format!("{e}\n")).with_cmd(cmd))
+    }
+}
+
+/// Validates a `KEY=VALUE` configuration override
+#[derive(Clone)]
+pub(crate) struct WithValueParser;
+
+impl TypedValueParser for WithValueParser {
+    type Value = String;
+
+    fn parse_ref(
+        &self,
+        cmd: &Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, Error> {
+        let with = NonEmptyStringValueParser::new().parse_ref(cmd, arg, value)?;
+        parse::with(&with)
+            .map(|_| with)
+            .map_err(|e| Error::raw(ErrorKind::ValueValidation, format!("{e}\n")).with_cmd(cmd))
+    }
+}
 
-        if expiry <= Expiry::MAX_EXPIRY_HOURS {
-            Ok(Expiry::Hours(expiry))
-        } else {
-            Ok(Expiry::Timestamp(
-                Timestamp::from_millisecond(expiry).expect("invalid timestamp"),
-            ))
+/// Validates that a management token looks like an X-Token (URL-safe base64)
+#[derive(Clone)]
+pub(crate) struct TokenValueParser;
+
+impl TypedValueParser for TokenValueParser {
+    type Value = Token;
+
+    fn parse_ref(
+        &self,
+        cmd: &Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, Error> {
+        let token = NonEmptyStringValueParser::new().parse_ref(cmd, arg, value)?;
+        match parse::token(&token) {
+            Ok(()) => Ok(Token::new(token)),
+            Err(problem) => Err(Error::raw(
+                ErrorKind::ValueValidation,
+                format!(
+                    "invalid token: {problem}\n\n\
+                    tip: tokens are the X-Token printed by `envsh --display-secret`\n"
+                ),
+            )
+            .with_cmd(cmd)),
         }
     }
 }
 
-/// Validates that the URL to modify is for [`crate::ENVS`]
+/// Validates an alias name for `envsh alias`
+#[derive(Clone)]
+pub(crate) struct AliasValueParser;
+
+impl TypedValueParser for AliasValueParser {
+    type Value = String;
+
+    fn parse_ref(
+        &self,
+        cmd: &Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, Error> {
+        let name = NonEmptyStringValueParser::new().parse_ref(cmd, arg, value)?;
+        parse::alias(&name)
+            .map(|()| name.clone())
+            .map_err(|problem| {
+                Error::raw(
+                    ErrorKind::ValueValidation,
+                    format!("invalid alias '{name}': {problem}\n"),
+                )
+                .with_cmd(cmd)
+            })
+    }
+}
+
+/// Accepts an upload's URL, or its `@ID` or `@alias` in the local history
+#[derive(Clone)]
+pub(crate) struct ManageTargetValueParser;
+
+impl TypedValueParser for ManageTargetValueParser {
+    type Value = ManageTarget;
+
+    fn parse_ref(
+        &self,
+        cmd: &Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, Error> {
+        let target = NonEmptyStringValueParser::new().parse_ref(cmd, arg, value)?;
+        match parse::history_ref(&target) {
+            Some(Ok(target)) => Ok(target),
+            Some(Err(e)) => Err(Error::raw(
+                ErrorKind::ValueValidation,
+                format!("invalid history reference '{target}': {e}\n"),
+            )
+            .with_cmd(cmd)),
+            None => EnvsUrlValueParser
+                .parse_ref(cmd, arg, value)
+                .map(ManageTarget::Url),
+        }
+    }
+}
+
+/// Accepts an `@ID` in the local history
+#[derive(Clone)]
+pub(crate) struct HistoryIdValueParser;
+
+impl TypedValueParser for HistoryIdValueParser {
+    type Value = u64;
+
+    fn parse_ref(
+        &self,
+        cmd: &Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, Error> {
+        let target = NonEmptyStringValueParser::new().parse_ref(cmd, arg, value)?;
+        match parse::history_ref(&target) {
+            Some(Ok(ManageTarget::Id(id))) => Ok(id),
+            _ => Err(Error::raw(
+                ErrorKind::ValueValidation,
+                format!("invalid ID '{target}': expected @ID, e.g. @3\n"),
+            )
+            .with_cmd(cmd)),
+        }
+    }
+}
+
+/// Validates that the URL to modify is a URL; see [`check_host`] for its host
 #[derive(Clone)]
 pub(crate) struct EnvsUrlValueParser;
 
@@ -75,21 +400,27 @@ impl TypedValueParser for EnvsUrlValueParser {
         value: &OsStr,
     ) -> Result<Self::Value, Error> {
         let url_str = NonEmptyStringValueParser::new().parse_ref(cmd, arg, value)?;
-        let url = Url::from_str(&url_str)
-            .map_err(|e| Error::raw(ErrorKind::ValueValidation, e).with_cmd(cmd))?;
-        if url.scheme() == "https" && url.domain() == Some("envs.sh") {
-            Ok(url)
-        } else {
-            let mut err = Error::new(ErrorKind::ValueValidation);
-            err.insert(
-                ContextKind::InvalidValue,
-                ContextValue::String(url.to_string()),
-            );
-            err.insert(
-                ContextKind::Usage,
-                ContextValue::String("url must start with \"https://envs.sh\"".to_string()),
-            );
-            Err(err.with_cmd(cmd))
+        Url::parse(&url_str)
+            .map_err(|e| Error::raw(ErrorKind::ValueValidation, format!("{e}\n")).with_cmd(cmd))
+    }
+}
+
+/// Check that a URL accepted by [`EnvsUrlValueParser`] belongs to `host`
+///
+/// Done after parsing, since `--host` may come after the URL.
+pub(crate) fn check_host(url: &Url, host: &Url, cmd: &Command) -> Result<(), Error> {
+    match parse::envs_url(url.as_str(), host) {
+        Ok(_) => Ok(()),
+        Err(EnvsUrlError::Invalid(e)) => {
+            Err(Error::raw(ErrorKind::ValueValidation, format!("{e}\n")).with_cmd(cmd))
         }
+        Err(EnvsUrlError::NotEnvs(url)) => Err(Error::raw(
+            ErrorKind::ValueValidation,
+            format!(
+                "invalid value '{url}' for '<URL>': url must start with \"{}\"\n",
+                host.as_str().trim_end_matches('/')
+            ),
+        )
+        .with_cmd(cmd)),
     }
 }
@@ -0,0 +1,252 @@
+//! Putting text on the system clipboard, and taking text or images off it
+//!
+//! Uses `pbcopy`/`pbpaste` (and `pngpaste` for images) on macOS, `clip` and PowerShell on
+//! Windows, `termux-clipboard-set`/`termux-clipboard-get` from Termux:API on Android, and otherwise
+//! `wl-copy`/`wl-paste` under Wayland or `xclip` or `xsel` under X11, whichever is installed.
+
+use std::io::{self, ErrorKind, Write};
+use std::process::{Command, Stdio};
+
+use crate::paths;
+
+/// Image types taken from the clipboard, in order of preference, with their file extensions
+const IMAGE_TYPES: &[(&str, &str)] = &[
+    ("image/png", "png"),
+    ("image/jpeg", "jpg"),
+    ("image/gif", "gif"),
+    ("image/webp", "webp"),
+    ("image/bmp", "bmp"),
+];
+
+/// PowerShell script writing the clipboard's image to stdout as PNG, if it holds one
+const POWERSHELL_IMAGE: &str = "Add-Type -AssemblyName System.Windows.Forms, System.Drawing; \
+    $image = [Windows.Forms.Clipboard]::GetImage(); \
+    if ($image) { $png = New-Object IO.MemoryStream; \
+    $image.Save($png, [Drawing.Imaging.ImageFormat]::Png); \
+    [Console]::OpenStandardOutput().Write($png.ToArray(), 0, $png.Length) }";
+
+/// PowerShell script writing the clipboard's text to stdout as UTF-8
+const POWERSHELL_TEXT: &str =
+    "[Console]::OutputEncoding = [Text.Encoding]::UTF8; Get-Clipboard -Raw";
+
+/// Contents taken off the clipboard
+#[derive(Debug)]
+pub(crate) struct Pasted {
+    /// The text or image
+    pub(crate) data: Vec<u8>,
+    /// File extension for its type, without the leading dot
+    pub(crate) extension: &'static str,
+}
+
+/// Tools that read the clipboard, each knowing how to ask for an image or text
+#[derive(Clone, Copy, Debug)]
+enum PasteTool {
+    /// `wl-paste`, for Wayland
+    WlPaste,
+    /// `xclip`, for X11
+    Xclip,
+    /// `xsel`, for X11, which only reads text
+    Xsel,
+    /// `pbpaste` for text and `pngpaste` for images, for macOS
+    Pbpaste,
+    /// PowerShell, for Windows
+    PowerShell,
+    /// `termux-clipboard-get`, for Termux, which only reads text
+    Termux,
+}
+
+/// Tools that copy their stdin to the clipboard, with their arguments, in the order they are tried
+fn copy_tools() -> Vec<(&'static str, &'static [&'static str])> {
+    if cfg!(target_os = "macos") {
+        vec![("pbcopy", &[])]
+    } else if cfg!(windows) {
+        vec![("clip", &[])]
+    } else if paths::is_termux() {
+        vec![("termux-clipboard-set", &[])]
+    } else {
+        let x11: [(&str, &[&str]); 2] = [
+            ("xclip", &["-selection", "clipboard"]),
+            ("xsel", &["--clipboard", "--input"]),
+        ];
+        let wayland = ("wl-copy", &[][..]);
+        // XWayland also makes X11 tools work, but only for X11 apps
+        if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            [wayland].into_iter().chain(x11).collect()
+        } else {
+            x11.into_iter().chain([wayland]).collect()
+        }
+    }
+}
+
+/// Tools that read the clipboard, in the order they are tried
+fn paste_tools() -> Vec<PasteTool> {
+    if cfg!(target_os = "macos") {
+        vec![PasteTool::Pbpaste]
+    } else if cfg!(windows) {
+        vec![PasteTool::PowerShell]
+    } else if paths::is_termux() {
+        vec![PasteTool::Termux]
+    } else if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        vec![PasteTool::WlPaste, PasteTool::Xclip, PasteTool::Xsel]
+    } else {
+        vec![PasteTool::Xclip, PasteTool::Xsel, PasteTool::WlPaste]
+    }
+}
+
+impl PasteTool {
+    /// Name of the program, for messages
+    fn program(self) -> &'static str {
+        match self {
+            Self::WlPaste => "wl-paste",
+            Self::Xclip => "xclip",
+            Self::Xsel => "xsel",
+            Self::Pbpaste => "pbpaste",
+            Self::PowerShell => "powershell",
+            Self::Termux => "termux-clipboard-get",
+        }
+    }
+
+    /// Read the clipboard, preferring an image, or `None` if it holds neither an image nor text
+    ///
+    /// Fails with [`ErrorKind::NotFound`] if the tool isn't installed.
+    fn paste(self) -> io::Result<Option<Pasted>> {
+        let image = match self {
+            Self::WlPaste => {
+                let types = output("wl-paste", &["--list-types"])?.unwrap_or_default();
+                match image_type(&types) {
+                    Some((mime, extension)) => output("wl-paste", &["--type", mime])?
+                        .map(|data| Pasted { data, extension }),
+                    None => None,
+                }
+            }
+            Self::Xclip => {
+                let types = output("xclip", &["-selection", "clipboard", "-t", "TARGETS", "-o"])?
+                    .unwrap_or_default();
+                match image_type(&types) {
+                    Some((mime, extension)) => {
+                        output("xclip", &["-selection", "clipboard", "-t", mime, "-o"])?
+                            .map(|data| Pasted { data, extension })
+                    }
+                    None => None,
+                }
+            }
+            Self::Xsel | Self::Termux => None,
+            // pngpaste is optional, so its absence only means no images
+            Self::Pbpaste => match output("pngpaste", &["-"]) {
+                Err(e) if e.kind() == ErrorKind::NotFound => None,
+                result => result?.map(|data| Pasted {
+                    data,
+                    extension: "png",
+                }),
+            },
+            Self::PowerShell => {
+                output("powershell", &["-NoProfile", "-Command", POWERSHELL_IMAGE])?.map(|data| {
+                    Pasted {
+                        data,
+                        extension: "png",
+                    }
+                })
+            }
+        };
+        if image.is_some() {
+            return Ok(image);
+        }
+
+        let text = match self {
+            Self::WlPaste => output("wl-paste", &["--no-newline", "--type", "text"])?,
+            Self::Xclip => output(
+                "xclip",
+                &["-selection", "clipboard", "-t", "UTF8_STRING", "-o"],
+            )?,
+            Self::Xsel => output("xsel", &["--clipboard", "--output"])?,
+            Self::Pbpaste => output("pbpaste", &[])?,
+            Self::PowerShell => output("powershell", &["-NoProfile", "-Command", POWERSHELL_TEXT])?,
+            Self::Termux => output("termux-clipboard-get", &[])?,
+        };
+        Ok(text.map(|data| Pasted {
+            data,
+            extension: "txt",
+        }))
+    }
+}
+
+/// The preferred image type among the clipboard's `types`, one per line, with its extension
+fn image_type(types: &[u8]) -> Option<(&'static str, &'static str)> {
+    let types = String::from_utf8_lossy(types);
+    IMAGE_TYPES
+        .iter()
+        .copied()
+        .find(|(mime, _)| types.lines().any(|line| line.trim() == *mime))
+}
+
+/// Run `program` and collect its stdout, or `None` if it failed or printed nothing
+///
+/// Tools fail when the clipboard is empty or lacks the requested type, so failures aren't errors.
+fn output(program: &str, args: &[&str]) -> io::Result<Option<Vec<u8>>> {
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()?;
+    Ok(Some(output.stdout).filter(|data| output.status.success() && !data.is_empty()))
+}
+
+/// Take the clipboard's image, or else its text, or `None` if it is empty
+pub(crate) fn paste() -> io::Result<Option<Pasted>> {
+    let tools = paste_tools();
+    for &tool in &tools {
+        match tool.paste() {
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => {
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!("failed to run {}: {e}", tool.program()),
+                ));
+            }
+            result => return result,
+        }
+    }
+
+    let names: Vec<_> = tools.iter().map(|tool| tool.program()).collect();
+    Err(io::Error::new(
+        ErrorKind::NotFound,
+        format!("no clipboard tool found (tried {})", names.join(", ")),
+    ))
+}
+
+/// Replace the clipboard's contents with `text`
+pub(crate) fn copy(text: &str) -> io::Result<()> {
+    let tools = copy_tools();
+    for &(program, args) in &tools {
+        let child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            // Some tools stay in the background to serve the clipboard, and would hold a pipe open
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => {
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!("failed to run {program}: {e}"),
+                ));
+            }
+        };
+        child.stdin.take().unwrap().write_all(text.as_bytes())?;
+        let status = child.wait()?;
+        return if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!("{program} failed ({status})")))
+        };
+    }
+
+    let names: Vec<_> = tools.iter().map(|(program, _)| *program).collect();
+    Err(io::Error::new(
+        ErrorKind::NotFound,
+        format!("no clipboard tool found (tried {})", names.join(", ")),
+    ))
+}
@@ -0,0 +1,437 @@
+//! Defaults loaded from `$XDG_CONFIG_HOME/envsh/config.toml`
+//!
+//! Command-line flags take precedence over anything set here.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use jiff::Zoned;
+use reqwest::blocking::{Client, ClientBuilder};
+use reqwest::{Certificate, NoProxy, Proxy, Url, tls};
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+
+use crate::error::{Context, EnvshError, Result};
+use crate::humanize::Locale;
+use crate::sensitive::{self, Action};
+use crate::{ENVS, Expiry, TIME_FORMAT, parse, paths};
+
+/// Configuration loaded on first use
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Contents of the configuration file
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Config {
+    /// Host that uploads are sent to and managed URLs must belong to
+    pub(crate) host: String,
+    /// Make resulting URLs difficult to guess
+    pub(crate) secret: bool,
+    /// Keep X-Tokens in the OS keyring instead of the history file, like `--keyring`
+    pub(crate) keyring: bool,
+    /// Copy resulting URLs to the clipboard, like `--copy`
+    pub(crate) copy: bool,
+    /// Default expiry for uploaded files, in hours or epoch milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) expires: Option<i64>,
+    /// `HOST:PORT:ADDR` address overrides, like `--resolve`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) resolve: Vec<String>,
+    /// Unix socket to reach the host through, like `--unix-socket`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) unix_socket: Option<PathBuf>,
+    /// Proxy for every request, instead of the one from `$HTTPS_PROXY` and the like
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) proxy: Option<String>,
+    /// TLS settings for every connection
+    pub(crate) tls: Tls,
+    /// How results are printed
+    pub(crate) output: Output,
+    /// Sounds played when uploads and changes finish
+    pub(crate) sound: Sound,
+    /// Signed receipts written for each upload
+    pub(crate) receipts: Receipts,
+    /// Keep nothing on disk, like `--read-only-state`
+    pub(crate) read_only_state: bool,
+    /// Extra rules for sensitive files, checked before the built-in ones
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) sensitive: Vec<SensitiveRule>,
+}
+
+/// `[output]` table
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Output {
+    /// Print results as JSON, like `--json`
+    pub(crate) json: bool,
+    /// strftime format used wherever a time is displayed
+    pub(crate) time_format: String,
+    /// Language for durations like "in 3 days", instead of the one from `$LANG`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) locale: Option<String>,
+    /// Mention links in the history that expire within a day, at most every few hours
+    pub(crate) expiry_notice: bool,
+}
+
+/// `[tls]` table
+///
+/// Cipher suites come from the system TLS library and its own configuration (e.g.
+/// `CipherString` in `openssl.cnf`), as it offers no way to choose them per connection.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Tls {
+    /// Oldest TLS version to connect with, up to `"1.2"`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) min_version: Option<String>,
+    /// Newest TLS version to connect with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) max_version: Option<String>,
+    /// PEM files of extra root certificates to trust, e.g. for an instance behind a private CA
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) ca_certs: Vec<PathBuf>,
+}
+
+/// `[sound]` table, each entry being `"bell"` or the path of a sound file
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Sound {
+    /// Played when everything succeeded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) success: Option<String>,
+    /// Played when anything failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) failure: Option<String>,
+}
+
+/// `[receipts]` table
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Receipts {
+    /// Directory to write receipts to, enabling them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) dir: Option<PathBuf>,
+    /// minisign secret key that signs them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) key: Option<PathBuf>,
+}
+
+/// `[[sensitive]]` entry, matching files by every key given
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SensitiveRule {
+    /// Glob for the file name, matched case-insensitively, e.g. `"*.csv"`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) pattern: Option<String>,
+    /// MIME type guessed from the file name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) mime: Option<String>,
+    /// Text the contents start with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) magic: Option<String>,
+    /// Whether matching files get `--secret` or are refused
+    pub(crate) action: Action,
+    /// What matching files are, shown as "FILE looks like REASON"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) reason: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            host: ENVS.to_string(),
+            secret: false,
+            keyring: false,
+            copy: false,
+            expires: None,
+            resolve: vec![],
+            unix_socket: None,
+            proxy: None,
+            tls: Tls::default(),
+            output: Output::default(),
+            sound: Sound::default(),
+            receipts: Receipts::default(),
+            read_only_state: false,
+            sensitive: vec![],
+        }
+    }
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Self {
+            json: false,
+            time_format: TIME_FORMAT.to_string(),
+            locale: None,
+            expiry_notice: false,
+        }
+    }
+}
+
+impl Tls {
+    /// Apply these settings to `builder`, describing the problem if any is invalid
+    fn apply(&self, mut builder: ClientBuilder) -> Result<ClientBuilder, String> {
+        let min = self
+            .min_version
+            .as_deref()
+            .map(parse::tls_version)
+            .transpose()?;
+        let max = self
+            .max_version
+            .as_deref()
+            .map(parse::tls_version)
+            .transpose()?;
+        if let (Some(min), Some(max)) = (min, max)
+            && min > max
+        {
+            return Err("tls.min_version is newer than tls.max_version".to_string());
+        }
+        if let Some(min) = min {
+            // The system TLS library can't be told to refuse TLS 1.2
+            if min == tls::Version::TLS_1_3 {
+                return Err("tls.min_version can be at most 1.2".to_string());
+            }
+            builder = builder.min_tls_version(min);
+        }
+        // 1.3 is the newest version, so it needs no cap (and the system TLS library can't set one)
+        if let Some(max) = max.filter(|&max| max != tls::Version::TLS_1_3) {
+            builder = builder.max_tls_version(max);
+        }
+        for path in &self.ca_certs {
+            let pem =
+                fs::read(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+            let certs = Certificate::from_pem_bundle(&pem)
+                .map_err(|e| format!("invalid certificate in {}: {e}", path.display()))?;
+            if certs.is_empty() {
+                return Err(format!("no certificates in {}", path.display()));
+            }
+            builder = certs
+                .into_iter()
+                .fold(builder, ClientBuilder::add_root_certificate);
+        }
+        Ok(builder)
+    }
+}
+
+impl Config {
+    /// Parsed [`Config::host`]
+    pub(crate) fn host(&self) -> Url {
+        Url::parse(&self.host).unwrap()
+    }
+
+    /// Parsed [`Config::expires`]
+    pub(crate) fn expires(&self) -> Option<Expiry> {
+        self.expires.map(|exp| parse::expiry(exp).unwrap())
+    }
+
+    /// HTTP client builder with the [`Config::proxy`], the [`Config::tls`] settings and the address
+    /// overrides from [`Config::resolve`] applied
+    fn base_builder(&self) -> ClientBuilder {
+        let mut builder = self
+            .tls
+            .apply(Client::builder())
+            .expect("TLS settings were checked when loading");
+        if let Some(proxy) = &self.proxy {
+            // `$NO_PROXY` still applies, as it does to proxies from the environment
+            builder = builder.proxy(Proxy::all(proxy).unwrap().no_proxy(NoProxy::from_env()));
+        }
+        self.resolve
+            .iter()
+            .map(|resolve| parse::resolve(resolve).unwrap())
+            .fold(builder, |builder, (host, addr)| {
+                builder.resolve(&host, addr)
+            })
+    }
+
+    /// HTTP client builder for the host, connecting through [`Config::unix_socket`] if set
+    pub(crate) fn client_builder(&self) -> ClientBuilder {
+        let builder = self.base_builder();
+        #[cfg(unix)]
+        if let Some(socket) = &self.unix_socket {
+            return builder.unix_socket(socket.as_path());
+        }
+        builder
+    }
+
+    /// HTTP client for the host, connecting through [`Config::unix_socket`] if set
+    pub(crate) fn client(&self) -> Client {
+        self.client_builder()
+            .build()
+            .expect("failed to build HTTP client")
+    }
+
+    /// HTTP client for servers other than the host, e.g. for `--fetch`
+    pub(crate) fn remote_client(&self) -> Client {
+        self.base_builder()
+            .build()
+            .expect("failed to build HTTP client")
+    }
+
+    /// Check values that the file format alone can't, so later uses can't fail
+    fn validate(self) -> Result<Self, String> {
+        Url::parse(&self.host).map_err(|e| format!("invalid host {:?}: {e}", self.host))?;
+        for resolve in &self.resolve {
+            parse::resolve(resolve)?;
+        }
+        if let Some(proxy) = &self.proxy {
+            Proxy::all(proxy).map_err(|e| format!("invalid proxy {proxy:?}: {e}"))?;
+        }
+        self.tls
+            .apply(Client::builder())?
+            .build()
+            .map_err(|e| format!("invalid TLS settings: {e}"))?;
+        if let Some(exp) = self.expires {
+            parse::expiry(exp)?;
+        }
+        jiff::fmt::strtime::format(&self.output.time_format, &Zoned::now())
+            .map_err(|e| format!("invalid time_format {:?}: {e}", self.output.time_format))?;
+        if let Some(locale) = &self.output.locale
+            && Locale::from_tag(locale).is_none()
+        {
+            return Err(format!(
+                "unsupported locale {locale:?}, expected one of en, de, es, fr"
+            ));
+        }
+        match (&self.receipts.dir, &self.receipts.key) {
+            (Some(_), None) => {
+                return Err("receipts need a key to sign them with".to_string());
+            }
+            (_, Some(key)) if !key.is_file() => {
+                return Err(format!("receipt key {} does not exist", key.display()));
+            }
+            _ => {}
+        }
+        for (i, rule) in self.sensitive.iter().enumerate() {
+            let n = i + 1;
+            if rule.pattern.is_none() && rule.mime.is_none() && rule.magic.is_none() {
+                return Err(format!(
+                    "sensitive rule {n} needs a pattern, mime or magic to match files by"
+                ));
+            }
+            if let Some(pattern) = &rule.pattern {
+                glob::Pattern::new(pattern).map_err(|e| {
+                    format!("invalid pattern {pattern:?} in sensitive rule {n}: {e}")
+                })?;
+            }
+            if rule
+                .magic
+                .as_ref()
+                .is_some_and(|magic| magic.is_empty() || magic.len() as u64 > sensitive::MAGIC_LEN)
+            {
+                return Err(format!(
+                    "magic in sensitive rule {n} must be 1 to {} bytes long",
+                    sensitive::MAGIC_LEN
+                ));
+            }
+        }
+        Ok(self)
+    }
+}
+
+/// Location of the configuration file
+pub(crate) fn path() -> Result<PathBuf> {
+    Ok(paths::config_dir()?.join("config.toml"))
+}
+
+/// Read the configuration file, with the `KEY=VALUE` settings in `overrides` (from `--with`)
+/// replacing its own
+///
+/// A missing file means all defaults; an invalid one is an error.
+fn read(overrides: &[String]) -> Result<Config> {
+    let path = path()?;
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).context(format!("failed to read {}", path.display())),
+    };
+    let config = toml::from_str::<Config>(&contents).map_err(|e| e.to_string());
+    if overrides.is_empty() {
+        return config
+            .and_then(Config::validate)
+            .map_err(|e| EnvshError::Data(format!("failed to load {}: {e}", path.display())));
+    }
+
+    // Problems in the file itself are reported as such, before the overrides can hide them
+    config.map_err(|e| EnvshError::Data(format!("failed to load {}: {e}", path.display())))?;
+    let mut table: Table = toml::from_str(&contents).unwrap();
+    for with in overrides {
+        let (key, value) = parse::with(with).unwrap();
+        set(&mut table, &key, value).map_err(EnvshError::Usage)?;
+    }
+    table
+        .try_into::<Config>()
+        .map_err(|e| e.to_string())
+        .and_then(Config::validate)
+        .map_err(|e| {
+            EnvshError::Usage(format!(
+                "invalid --with settings: {}",
+                e.trim().replace('\n', " ")
+            ))
+        })
+}
+
+/// Set the setting at the dotted `key` in `table` to `value`, adding tables along the way
+fn set(table: &mut Table, key: &[String], value: Value) -> Result<(), String> {
+    let (name, parents) = key.split_last().unwrap();
+    let mut table = table;
+    for (i, parent) in parents.iter().enumerate() {
+        table = match table
+            .entry(parent.as_str())
+            .or_insert_with(|| Value::Table(Table::new()))
+        {
+            Value::Table(table) => table,
+            _ => return Err(format!("{} is not a table", parents[..=i].join("."))),
+        };
+    }
+    table.insert(name.clone(), value);
+    Ok(())
+}
+
+/// Load the configuration, applying the `KEY=VALUE` settings in `overrides` (from `--with`),
+/// then replacing its host with `host` (from `--host`) and its socket with `unix_socket` (from
+/// `--unix-socket`) if given, and adding the address overrides in `resolve` (from `--resolve`)
+pub(crate) fn load(
+    host: Option<Url>,
+    resolve: Vec<String>,
+    unix_socket: Option<PathBuf>,
+    read_only_state: bool,
+    overrides: &[String],
+) -> Result<()> {
+    let mut config = read(overrides)?;
+    if let Some(host) = host {
+        config.host = host.to_string();
+    }
+    config.resolve.extend(resolve);
+    config.unix_socket = unix_socket.or(config.unix_socket);
+    config.read_only_state |= read_only_state;
+    if cfg!(not(unix)) && config.unix_socket.is_some() {
+        return Err(EnvshError::Usage(
+            "Unix sockets are not supported on this platform".to_string(),
+        ));
+    }
+    CONFIG
+        .set(config)
+        .expect("configuration was already loaded");
+    Ok(())
+}
+
+/// The configuration, reading the file on first use if [`load`] wasn't called
+pub(crate) fn get() -> &'static Config {
+    CONFIG.get_or_init(|| read(&[]).unwrap_or_else(|e| e.exit(false)))
+}
+
+/// Print the effective configuration as TOML
+pub(crate) fn print_effective() -> Result<()> {
+    let path = path()?;
+    if path.is_file() {
+        println!("# {}", path.display());
+    } else {
+        println!("# {} (not found, using defaults)", path.display());
+    }
+    print!(
+        "{}",
+        toml::to_string(get()).expect("failed to serialize configuration")
+    );
+    Ok(())
+}
@@ -0,0 +1,296 @@
+//! Client-side encryption for `--encrypt`, so the host only ever stores ciphertext
+//!
+//! Files are split into 64 KiB chunks, each sealed with AES-256-GCM under a random key. Nonces
+//! count the chunks and mark the last one, so reordered, dropped or truncated chunks fail to
+//! decrypt. Both directions stream, and only authenticated chunks are ever returned.
+
+use std::fmt::{Debug, Display, Formatter};
+use std::io::{self, ErrorKind, Read};
+use std::str::FromStr;
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use ring::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::rand::{SecureRandom, SystemRandom};
+
+/// Identifies the format, and its version, at the start of every encrypted file
+const MAGIC: &[u8] = b"envsh-enc1\n";
+
+/// Plaintext bytes per chunk
+const CHUNK: usize = 64 * 1024;
+
+/// Authentication tag added to each chunk
+const TAG: usize = 16;
+
+/// A 256-bit key, written as unpadded URL-safe base64
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) struct Key([u8; 32]);
+
+impl Key {
+    /// A new random key
+    pub(crate) fn generate() -> Self {
+        let mut key = [0; 32];
+        SystemRandom::new()
+            .fill(&mut key)
+            .expect("the system random number generator failed");
+        Self(key)
+    }
+
+    /// The AEAD key for sealing or opening chunks
+    fn aead(&self) -> LessSafeKey {
+        LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &self.0).unwrap())
+    }
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", URL_SAFE_NO_PAD.encode(self.0))
+    }
+}
+
+impl Debug for Key {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // Keep keys out of debug output
+        write!(f, "Key(..)")
+    }
+}
+
+impl FromStr for Key {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        URL_SAFE_NO_PAD
+            .decode(s)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .map(Self)
+            .ok_or("not a 43-character base64url key")
+    }
+}
+
+/// Size of `len` plaintext bytes once encrypted
+pub(crate) fn encrypted_len(len: u64) -> u64 {
+    // Empty files still get one (empty) chunk
+    let chunks = len.div_ceil(CHUNK as u64).max(1);
+    MAGIC.len() as u64 + len + chunks * TAG as u64
+}
+
+/// Nonce for chunk `counter`, which is `last` if nothing follows it
+fn nonce(counter: u64, last: bool) -> Nonce {
+    let mut nonce = [0; NONCE_LEN];
+    nonce[3..11].copy_from_slice(&counter.to_be_bytes());
+    nonce[11] = last.into();
+    Nonce::assume_unique_for_key(nonce)
+}
+
+/// Read from `reader` until `buf` holds `want` bytes or the reader ends
+fn fill(reader: &mut impl Read, buf: &mut Vec<u8>, want: usize) -> io::Result<()> {
+    while buf.len() < want {
+        let start = buf.len();
+        buf.resize(want, 0);
+        match reader.read(&mut buf[start..]) {
+            Ok(0) => {
+                buf.truncate(start);
+                break;
+            }
+            Ok(n) => buf.truncate(start + n),
+            Err(e) if e.kind() == ErrorKind::Interrupted => buf.truncate(start),
+            Err(e) => {
+                buf.truncate(start);
+                return Err(e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Chunks produced by [`Encrypt`] or [`Decrypt`], handed out as they are read
+struct Chunks {
+    /// Key for every chunk
+    key: LessSafeKey,
+    /// Index of the next chunk
+    counter: u64,
+    /// Input read ahead, to tell whether the current chunk is the last
+    input: Vec<u8>,
+    /// The current output chunk
+    output: Vec<u8>,
+    /// Bytes of `output` already returned
+    pos: usize,
+    /// Whether the last chunk was produced
+    done: bool,
+}
+
+impl Chunks {
+    /// Start with `key`, with `output` returned before the first chunk
+    fn new(key: &Key, output: Vec<u8>) -> Self {
+        Self {
+            key: key.aead(),
+            counter: 0,
+            input: vec![],
+            output,
+            pos: 0,
+            done: false,
+        }
+    }
+
+    /// Copy produced bytes into `buf`, making more with `next` when they run out
+    fn read(
+        &mut self,
+        buf: &mut [u8],
+        mut next: impl FnMut(&mut Self) -> io::Result<()>,
+    ) -> io::Result<usize> {
+        while self.pos == self.output.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.output.clear();
+            self.pos = 0;
+            next(self)?;
+            self.counter += 1;
+        }
+        let n = buf.len().min(self.output.len() - self.pos);
+        buf[..n].copy_from_slice(&self.output[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Encrypts everything read from the wrapped reader
+pub(crate) struct Encrypt<R> {
+    /// Plaintext source
+    inner: R,
+    /// Chunking state
+    chunks: Chunks,
+}
+
+impl<R: Read> Encrypt<R> {
+    /// Encrypt `inner` with `key`
+    pub(crate) fn new(inner: R, key: &Key) -> Self {
+        Self {
+            inner,
+            chunks: Chunks::new(key, MAGIC.to_vec()),
+        }
+    }
+}
+
+impl<R: Read> Read for Encrypt<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let inner = &mut self.inner;
+        self.chunks.read(buf, |chunks| {
+            // One byte past a full chunk shows whether another follows
+            fill(inner, &mut chunks.input, CHUNK + 1)?;
+            let last = chunks.input.len() <= CHUNK;
+            let len = chunks.input.len().min(CHUNK);
+            chunks.output.extend(chunks.input.drain(..len));
+            chunks
+                .key
+                .seal_in_place_append_tag(
+                    nonce(chunks.counter, last),
+                    Aad::empty(),
+                    &mut chunks.output,
+                )
+                .map_err(|_| io::Error::other("encryption failed"))?;
+            chunks.done = last;
+            Ok(())
+        })
+    }
+}
+
+/// Decrypts everything read from the wrapped reader, failing on a wrong key or damaged data
+pub(crate) struct Decrypt<R> {
+    /// Ciphertext source
+    inner: R,
+    /// Chunking state
+    chunks: Chunks,
+    /// Whether the header was checked
+    started: bool,
+}
+
+impl<R: Read> Decrypt<R> {
+    /// Decrypt `inner` with `key`
+    pub(crate) fn new(inner: R, key: &Key) -> Self {
+        Self {
+            inner,
+            chunks: Chunks::new(key, vec![]),
+            started: false,
+        }
+    }
+}
+
+impl<R: Read> Read for Decrypt<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let invalid = |message| io::Error::new(ErrorKind::InvalidData, message);
+        if !self.started {
+            let mut magic = vec![];
+            fill(&mut self.inner, &mut magic, MAGIC.len())?;
+            if magic != MAGIC {
+                return Err(invalid("not encrypted with --encrypt"));
+            }
+            self.started = true;
+        }
+
+        let inner = &mut self.inner;
+        self.chunks.read(buf, |chunks| {
+            fill(inner, &mut chunks.input, CHUNK + TAG + 1)?;
+            let last = chunks.input.len() <= CHUNK + TAG;
+            if chunks.input.len() < TAG {
+                return Err(invalid("file is truncated"));
+            }
+            let len = chunks.input.len().min(CHUNK + TAG);
+            let mut sealed: Vec<u8> = chunks.input.drain(..len).collect();
+            let plain = chunks
+                .key
+                .open_in_place(nonce(chunks.counter, last), Aad::empty(), &mut sealed)
+                .map_err(|_| invalid("wrong key, or the file is damaged or truncated"))?;
+            chunks.output.extend_from_slice(plain);
+            chunks.done = last;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encrypt `plain` with `key`
+    fn encrypt(plain: &[u8], key: &Key) -> Vec<u8> {
+        let mut sealed = vec![];
+        Encrypt::new(plain, key).read_to_end(&mut sealed).unwrap();
+        sealed
+    }
+
+    #[test]
+    fn round_trips() {
+        let key = Key::generate();
+        for len in [0, 1, CHUNK - 1, CHUNK, CHUNK + 1, 3 * CHUNK + 7] {
+            let plain: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let sealed = encrypt(&plain, &key);
+            assert_eq!(sealed.len() as u64, encrypted_len(len as u64));
+
+            let mut opened = vec![];
+            Decrypt::new(&sealed[..], &key)
+                .read_to_end(&mut opened)
+                .unwrap();
+            assert_eq!(opened, plain);
+        }
+        assert_eq!(key.to_string().parse::<Key>(), Ok(key));
+    }
+
+    #[test]
+    fn rejects_wrong_key_and_truncation() {
+        let key = Key::generate();
+        let sealed = encrypt(&[7; 3 * CHUNK], &key);
+
+        let mut opened = vec![];
+        let wrong = Decrypt::new(&sealed[..], &Key::generate()).read_to_end(&mut opened);
+        assert_eq!(wrong.unwrap_err().kind(), ErrorKind::InvalidData);
+
+        // Dropping the last chunk leaves a complete but non-final one at the end
+        let truncated = &sealed[..MAGIC.len() + 2 * (CHUNK + TAG)];
+        let mut opened = vec![];
+        let cut = Decrypt::new(truncated, &key).read_to_end(&mut opened);
+        assert_eq!(cut.unwrap_err().kind(), ErrorKind::InvalidData);
+        assert_eq!(opened.len(), CHUNK);
+    }
+}
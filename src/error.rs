@@ -0,0 +1,129 @@
+//! Errors that end the program, and the exit codes they map to
+
+use std::io;
+use std::process;
+
+use reqwest::StatusCode;
+use thiserror::Error;
+
+use crate::json;
+
+/// Shorthand for results that fail with [`EnvshError`]
+pub(crate) type Result<T, E = EnvshError> = std::result::Result<T, E>;
+
+/// Why envsh gave up
+#[derive(Debug, Error)]
+pub(crate) enum EnvshError {
+    /// Arguments or inputs that can't be used together
+    #[error("{0}")]
+    Usage(String),
+    /// Reading or writing a local file failed
+    #[error("{context}: {source}")]
+    Io {
+        /// What was being done
+        context: String,
+        /// Underlying error
+        source: io::Error,
+    },
+    /// A request couldn't be sent, or its response couldn't be read
+    #[error("{context}: {source}")]
+    Http {
+        /// What was being done
+        context: String,
+        /// Underlying error
+        source: reqwest::Error,
+    },
+    /// A file envsh reads (config, history, signature, ...) has invalid contents
+    #[error("{0}")]
+    Data(String),
+    /// One or more uploads or changes were rejected, and already reported
+    #[error("{0}")]
+    Failed(String),
+}
+
+impl EnvshError {
+    /// Exit code for this error, following `sysexits.h` where it has a match
+    pub(crate) fn exit_code(&self) -> i32 {
+        match self {
+            Self::Failed(_) => 1,
+            Self::Usage(_) => 64,
+            Self::Data(_) => 65,
+            Self::Http { .. } => 69,
+            Self::Io { .. } => 74,
+        }
+    }
+
+    /// Stable code for this error in `--json` output, for scripts to branch on
+    pub(crate) fn code(&self) -> &'static str {
+        match self {
+            Self::Usage(_) => "E_USAGE",
+            Self::Io { .. } => "E_IO",
+            Self::Http { source, .. } => request_code(source),
+            Self::Data(_) => "E_DATA",
+            Self::Failed(_) => "E_FAILED",
+        }
+    }
+
+    /// Print the error, as JSON with `json`, and exit with its code
+    pub(crate) fn exit(self, json: bool) -> ! {
+        match &self {
+            Self::Failed(message) if message.is_empty() => {}
+            error if json => json::print(&json::Error {
+                error: error.to_string(),
+                code: error.code(),
+            }),
+            error => eprintln!("error: {error}"),
+        }
+        process::exit(self.exit_code())
+    }
+}
+
+/// Stable code for a response the host rejected with `status`
+pub(crate) fn status_code(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::PAYLOAD_TOO_LARGE => "E_TOO_LARGE",
+        StatusCode::TOO_MANY_REQUESTS => "E_RATE_LIMIT",
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => "E_UNAUTHORIZED",
+        StatusCode::NOT_FOUND | StatusCode::GONE => "E_NOT_FOUND",
+        _ if status.is_client_error() => "E_REJECTED",
+        _ if status.is_server_error() => "E_SERVER",
+        _ => "E_HTTP",
+    }
+}
+
+/// Stable code for a request that failed, or whose response was an error status
+pub(crate) fn request_code(error: &reqwest::Error) -> &'static str {
+    if let Some(status) = error.status() {
+        status_code(status)
+    } else if error.is_timeout() {
+        "E_TIMEOUT"
+    } else if error.is_connect() {
+        "E_CONNECT"
+    } else {
+        "E_HTTP"
+    }
+}
+
+/// Attach a description of what was being done to IO and HTTP errors
+pub(crate) trait Context<T> {
+    /// Wrap the error with `context`
+    fn context(self, context: impl Into<String>) -> Result<T>;
+}
+
+impl<T> Context<T> for io::Result<T> {
+    fn context(self, context: impl Into<String>) -> Result<T> {
+        self.map_err(|source| EnvshError::Io {
+            context: context.into(),
+            source,
+        })
+    }
+}
+
+impl<T> Context<T> for reqwest::Result<T> {
+    fn context(self, context: impl Into<String>) -> Result<T> {
+        self.map_err(|source| EnvshError::Http {
+            context: context.into(),
+            source,
+        })
+    }
+}
@@ -0,0 +1,368 @@
+//! Local record of past uploads, kept in `$XDG_DATA_HOME/envsh/history.json`
+
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use jiff::Timestamp;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::{Context, EnvshError, Result};
+use crate::{ManageTarget, alias, config, parse, paths};
+
+/// A successful upload or URL submission
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Entry {
+    /// Short ID for referring to this upload, unique within the history
+    pub(crate) id: u64,
+    /// Resulting URL
+    pub(crate) url: String,
+    /// X-Token for managing the URL, if the host issued one and it isn't in the keyring
+    pub(crate) token: Option<String>,
+    /// Whether the X-Token is kept in the OS keyring instead
+    #[serde(default)]
+    pub(crate) keyring: bool,
+    /// Expiry time, if the host reported one
+    pub(crate) expires: Option<Timestamp>,
+    /// Target as given on the command line
+    pub(crate) input: String,
+    /// Whether `url` is a short link to `input`
+    #[serde(default)]
+    pub(crate) shortened: bool,
+    /// Bytes sent, for file uploads
+    pub(crate) size: Option<u64>,
+    /// Hex SHA-256 of the bytes sent, for file uploads
+    pub(crate) sha256: Option<String>,
+    /// Labels given with `--tag`, for picking uploads to `envsh delete`
+    #[serde(default)]
+    pub(crate) tags: Vec<String>,
+    /// When the upload was made
+    pub(crate) created: Timestamp,
+    /// Response headers of the upload worth keeping, see [`envsh::RECORDED_HEADERS`]
+    #[serde(default)]
+    pub(crate) headers: BTreeMap<String, String>,
+}
+
+/// An entry taken out of the history when its upload was deleted, kept until purged
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Trashed {
+    /// The entry as it was recorded
+    #[serde(flatten)]
+    pub(crate) entry: Entry,
+    /// When the upload was deleted
+    pub(crate) deleted: Timestamp,
+}
+
+/// All recorded uploads, oldest first
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct History {
+    /// ID for the next upload, so IDs of forgotten uploads aren't handed out again
+    #[serde(default)]
+    next_id: u64,
+    /// Recorded uploads
+    pub(crate) entries: Vec<Entry>,
+    /// Entries of deleted uploads, for `envsh trash`
+    #[serde(default)]
+    pub(crate) trash: Vec<Trashed>,
+}
+
+/// Layouts of the history file
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Stored {
+    /// Current layout
+    History(History),
+    /// Bare list of entries, written before IDs were tracked separately
+    Entries(Vec<Entry>),
+}
+
+impl History {
+    /// Read the history, which is empty if nothing was recorded yet
+    pub(crate) fn load() -> Result<Self> {
+        match fs::read(path()?) {
+            Ok(contents) => Self::parse(&contents)
+                .map_err(io::Error::from)
+                .context("failed to read upload history"),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).context("failed to read upload history"),
+        }
+    }
+
+    /// Parse the contents of a history file in either layout
+    fn parse(contents: &[u8]) -> serde_json::Result<Self> {
+        Ok(match serde_json::from_slice(contents)? {
+            Stored::History(history) => history,
+            Stored::Entries(entries) => Self {
+                next_id: 0,
+                entries,
+                trash: vec![],
+            },
+        })
+    }
+
+    /// Add `entry` under the next free ID, returning that ID
+    pub(crate) fn add(&mut self, entry: Entry) -> u64 {
+        // Older files don't store the next ID, so never go below what is in use
+        let id = self
+            .entries
+            .iter()
+            .chain(self.trash.iter().map(|t| &t.entry))
+            .map(|e| e.id + 1)
+            .max()
+            .unwrap_or(1)
+            .max(self.next_id);
+        self.next_id = id + 1;
+        self.entries.push(Entry { id, ..entry });
+        id
+    }
+
+    /// Move the entries for `url` to the trash, returning their IDs
+    fn discard(&mut self, url: &str, deleted: Timestamp) -> Vec<u64> {
+        let (gone, kept): (Vec<_>, _) = std::mem::take(&mut self.entries)
+            .into_iter()
+            .partition(|e| e.url == url);
+        self.entries = kept;
+        let ids = gone.iter().map(|e| e.id).collect();
+        self.trash
+            .extend(gone.into_iter().map(|entry| Trashed { entry, deleted }));
+        ids
+    }
+
+    /// Move the entry with `id` back from the trash, where it was by ID, returning it
+    pub(crate) fn restore(&mut self, id: u64) -> Option<&Entry> {
+        let trashed = self
+            .trash
+            .remove(self.trash.iter().position(|t| t.entry.id == id)?);
+        let at = self.entries.partition_point(|e| e.id < id);
+        self.entries.insert(at, trashed.entry);
+        Some(&self.entries[at])
+    }
+
+    /// Write the history back
+    pub(crate) fn save(&self) -> Result<()> {
+        write_private(&path()?, self).context("failed to save upload history")
+    }
+}
+
+/// Write `value` as JSON to `path`, readable only by the current user since it may hold tokens
+pub(crate) fn write_private(path: &Path, value: &impl Serialize) -> io::Result<()> {
+    fs::create_dir_all(path.parent().unwrap())?;
+
+    // Write next to the real file and rename over it, so an interrupted save can't truncate it
+    let partial = path.with_extension("json.partial");
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(&partial)?;
+    serde_json::to_writer_pretty(&mut file, value)?;
+    file.write_all(b"\n")?;
+    file.sync_all()?;
+    fs::rename(partial, path)
+}
+
+/// Location of the history file
+pub(crate) fn path() -> Result<PathBuf> {
+    Ok(paths::data_dir()?.join("history.json"))
+}
+
+/// The recorded entry `target` refers to, or why there isn't one
+pub(crate) fn find(target: &ManageTarget) -> Result<Entry> {
+    let history = History::load()?;
+    let id = match target {
+        ManageTarget::Url(url) => {
+            return history
+                .entries
+                .into_iter()
+                .rev()
+                .find(|e| e.url == url.as_str())
+                .ok_or_else(|| EnvshError::Usage(format!("{url} is not in the upload history")));
+        }
+        ManageTarget::Id(id) => *id,
+        ManageTarget::Alias(name) => alias::resolve(name)?,
+    };
+    history
+        .entries
+        .into_iter()
+        .find(|e| e.id == id)
+        .ok_or_else(|| {
+            EnvshError::Usage(format!(
+                "no upload with ID @{id} in the history, see `envsh list`"
+            ))
+        })
+}
+
+/// A still-valid short link to `destination` on `host` created earlier, if any
+pub(crate) fn find_short(destination: &Url, host: &Url) -> Option<Entry> {
+    let now = Timestamp::now();
+    History::load().ok()?.entries.into_iter().rev().find(|e| {
+        e.shortened
+            && e.input == destination.as_str()
+            && e.expires.is_none_or(|exp| exp > now)
+            && parse::envs_url(&e.url, host).is_ok()
+    })
+}
+
+/// Fail if `--read-only-state` is on, for `command`s that only change local state
+pub(crate) fn check_writable(command: &str) -> Result<()> {
+    if config::get().read_only_state {
+        return Err(EnvshError::Usage(format!(
+            "`envsh {command}` can't be used with --read-only-state"
+        )));
+    }
+    Ok(())
+}
+
+/// Record a new entry, returning its ID, or 0 with `--read-only-state`
+pub(crate) fn record(entry: Entry) -> Result<u64> {
+    if config::get().read_only_state {
+        return Ok(0);
+    }
+    let mut history = History::load()?;
+    let id = history.add(entry);
+    history.save()?;
+    Ok(id)
+}
+
+/// Move the entries for `url` to the trash, once it has been deleted
+///
+/// Their X-Tokens stay where they were until the trash is purged.
+pub(crate) fn forget(url: &str) -> Result<()> {
+    if config::get().read_only_state {
+        return Ok(());
+    }
+    let mut history = History::load()?;
+    let gone = history.discard(url, Timestamp::now());
+    if gone.is_empty() {
+        return Ok(());
+    }
+    history.save()?;
+    alias::forget(&gone)
+}
+
+/// Note that `url` now expires at `expires`
+pub(crate) fn set_expiry(url: &str, expires: Timestamp) -> Result<()> {
+    if config::get().read_only_state {
+        return Ok(());
+    }
+    let mut history = History::load()?;
+    let mut found = false;
+    for entry in history.entries.iter_mut().filter(|e| e.url == url) {
+        entry.expires = Some(expires);
+        found = true;
+    }
+    if found { history.save() } else { Ok(()) }
+}
+
+/// Counts and hashes everything read through readers it wraps
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Tally(Arc<Mutex<Option<(u64, Sha256)>>>);
+
+impl Tally {
+    /// Wrap `reader` so the bytes read from it are tallied
+    pub(crate) fn wrap(&self, reader: Box<dyn Read + Send>) -> Box<dyn Read + Send> {
+        *self.0.lock().unwrap() = Some((0, Sha256::new()));
+        Box::new(Tallied {
+            inner: reader,
+            tally: self.clone(),
+        })
+    }
+
+    /// Size and hex SHA-256 of the bytes read, if a reader was wrapped
+    pub(crate) fn finish(&self) -> Option<(u64, String)> {
+        let state = self.0.lock().unwrap();
+        state
+            .as_ref()
+            .map(|(size, hasher)| (*size, format!("{:x}", hasher.clone().finalize())))
+    }
+}
+
+/// Reader feeding a [`Tally`]
+struct Tallied {
+    /// Wrapped reader
+    inner: Box<dyn Read + Send>,
+    /// Where read bytes are counted
+    tally: Tally,
+}
+
+impl Read for Tallied {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some((size, hasher)) = self.tally.0.lock().unwrap().as_mut() {
+            *size += n as u64;
+            hasher.update(&buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An entry for `url` with nothing else recorded
+    fn entry(url: &str) -> Entry {
+        Entry {
+            id: 0,
+            url: url.to_string(),
+            token: None,
+            keyring: false,
+            expires: None,
+            input: "notes.txt".to_string(),
+            shortened: false,
+            size: None,
+            sha256: None,
+            tags: vec![],
+            created: Timestamp::UNIX_EPOCH,
+            headers: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn never_reuses_ids() {
+        let mut history = History::default();
+        assert_eq!(history.add(entry("https://envs.sh/a.txt")), 1);
+        assert_eq!(history.add(entry("https://envs.sh/b.txt")), 2);
+        assert_eq!(
+            history.discard("https://envs.sh/b.txt", Timestamp::UNIX_EPOCH),
+            [2]
+        );
+        assert_eq!(history.add(entry("https://envs.sh/c.txt")), 3);
+
+        // The next ID survives a save even when its upload is gone
+        history.discard("https://envs.sh/c.txt", Timestamp::UNIX_EPOCH);
+        let mut reloaded = History::parse(&serde_json::to_vec(&history).unwrap()).unwrap();
+        assert_eq!(reloaded.add(entry("https://envs.sh/d.txt")), 4);
+
+        // Files from before the next ID was stored continue after the highest ID
+        let old = serde_json::to_vec(&reloaded.entries).unwrap();
+        let mut upgraded = History::parse(&old).unwrap();
+        assert_eq!(upgraded.add(entry("https://envs.sh/e.txt")), 5);
+    }
+
+    #[test]
+    fn restores_trashed_entries_in_place() {
+        let mut history = History::default();
+        for url in [
+            "https://envs.sh/a.txt",
+            "https://envs.sh/b.txt",
+            "https://envs.sh/c.txt",
+        ] {
+            history.add(entry(url));
+        }
+        history.discard("https://envs.sh/b.txt", Timestamp::UNIX_EPOCH);
+        assert_eq!(history.trash.len(), 1);
+        // Trashed IDs aren't handed out again either
+        assert_eq!(history.add(entry("https://envs.sh/d.txt")), 4);
+
+        assert_eq!(history.restore(2).unwrap().url, "https://envs.sh/b.txt");
+        assert!(history.restore(2).is_none());
+        let ids: Vec<_> = history.entries.iter().map(|e| e.id).collect();
+        assert_eq!(ids, [1, 2, 3, 4]);
+        assert!(history.trash.is_empty());
+    }
+}
@@ -0,0 +1,176 @@
+//! Durations worded for people, in the user's language
+//!
+//! The language comes from `locale` in the `[output]` config table, or else `LC_ALL`,
+//! `LC_MESSAGES` or `LANG`, falling back to English.
+
+use std::env;
+
+use jiff::{Span, SpanRound, Unit, Zoned};
+
+use crate::config;
+
+/// Languages durations can be worded in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Locale {
+    /// English
+    En,
+    /// German
+    De,
+    /// Spanish
+    Es,
+    /// French
+    Fr,
+}
+
+impl Locale {
+    /// Locale from the config file or environment
+    pub(crate) fn current() -> Self {
+        config::get()
+            .output
+            .locale
+            .as_deref()
+            .and_then(Self::from_tag)
+            .or_else(|| {
+                ["LC_ALL", "LC_MESSAGES", "LANG"]
+                    .into_iter()
+                    .filter_map(|var| env::var(var).ok())
+                    .find(|value| !value.is_empty())
+                    .and_then(|value| Self::from_tag(&value))
+            })
+            .unwrap_or(Self::En)
+    }
+
+    /// Locale for a tag such as `de`, `fr-CA` or `es_ES.UTF-8`, if supported
+    pub(crate) fn from_tag(tag: &str) -> Option<Self> {
+        let language = tag.split(['_', '-', '.', '@']).next()?;
+        match language.to_ascii_lowercase().as_str() {
+            "en" | "c" | "posix" => Some(Self::En),
+            "de" => Some(Self::De),
+            "es" => Some(Self::Es),
+            "fr" => Some(Self::Fr),
+            _ => None,
+        }
+    }
+
+    /// Singular and plural names for days, hours and minutes
+    ///
+    /// German uses the dative plural, which is what follows "in" and "vor".
+    fn units(self) -> [(&'static str, &'static str); 3] {
+        match self {
+            Self::En => [("day", "days"), ("hour", "hours"), ("minute", "minutes")],
+            Self::De => [
+                ("Tag", "Tagen"),
+                ("Stunde", "Stunden"),
+                ("Minute", "Minuten"),
+            ],
+            Self::Es => [("día", "días"), ("hora", "horas"), ("minuto", "minutos")],
+            Self::Fr => [
+                ("jour", "jours"),
+                ("heure", "heures"),
+                ("minute", "minutes"),
+            ],
+        }
+    }
+
+    /// Whether `n` of something takes the plural form
+    fn is_plural(self, n: i64) -> bool {
+        match self {
+            // French treats zero as singular
+            Self::Fr => n > 1,
+            Self::En | Self::De | Self::Es => n != 1,
+        }
+    }
+}
+
+/// Word the length of `span` with its two largest units, e.g. "2 days 3 hours"
+///
+/// The sign is ignored. Units smaller than minutes are dropped.
+pub(crate) fn duration(span: &Span, locale: Locale) -> String {
+    let span = span.abs();
+    let parts: Vec<_> = [
+        i64::from(span.get_days()),
+        span.get_hours().into(),
+        span.get_minutes(),
+    ]
+    .into_iter()
+    .zip(locale.units())
+    .filter(|&(n, _)| n != 0)
+    .take(2)
+    .map(|(n, (one, many))| {
+        let unit = if locale.is_plural(n) { many } else { one };
+        format!("{n} {unit}")
+    })
+    .collect();
+
+    if parts.is_empty() {
+        let (one, many) = locale.units()[2];
+        format!("0 {}", if locale.is_plural(0) { many } else { one })
+    } else {
+        parts.join(" ")
+    }
+}
+
+/// Word `span` as a time from now, e.g. "in 3 days" or "2 hours ago"
+pub(crate) fn relative(span: &Span, locale: Locale) -> String {
+    let length = duration(span, locale);
+    match (locale, span.is_negative()) {
+        (Locale::En, false) | (Locale::De, false) => format!("in {length}"),
+        (Locale::En, true) => format!("{length} ago"),
+        (Locale::De, true) => format!("vor {length}"),
+        (Locale::Es, false) => format!("en {length}"),
+        (Locale::Es, true) => format!("hace {length}"),
+        (Locale::Fr, false) => format!("dans {length}"),
+        (Locale::Fr, true) => format!("il y a {length}"),
+    }
+}
+
+/// Describe `time` relative to `now` in the current locale, to the minute
+pub(crate) fn relative_time(now: &Zoned, time: &Zoned) -> String {
+    let span = now
+        .until(time)
+        .and_then(|span| {
+            span.round(
+                SpanRound::new()
+                    .largest(Unit::Day)
+                    .smallest(Unit::Minute)
+                    .relative(now),
+            )
+        })
+        .unwrap();
+    relative(&span, Locale::current())
+}
+
+#[cfg(test)]
+mod tests {
+    use jiff::ToSpan;
+
+    use super::*;
+
+    #[test]
+    fn locale_tags() {
+        assert_eq!(Locale::from_tag("de_DE.UTF-8"), Some(Locale::De));
+        assert_eq!(Locale::from_tag("fr-CA"), Some(Locale::Fr));
+        assert_eq!(Locale::from_tag("C.UTF-8"), Some(Locale::En));
+        assert_eq!(Locale::from_tag("ja_JP"), None);
+    }
+
+    #[test]
+    fn durations_pluralize() {
+        let span = 1.day().hours(2).minutes(5);
+        assert_eq!(duration(&span, Locale::En), "1 day 2 hours");
+        assert_eq!(
+            duration(&1.hour().minutes(1), Locale::Es),
+            "1 hora 1 minuto"
+        );
+        assert_eq!(duration(&Span::new(), Locale::En), "0 minutes");
+        assert_eq!(duration(&Span::new(), Locale::Fr), "0 minute");
+    }
+
+    #[test]
+    fn relative_wording() {
+        assert_eq!(relative(&3.days(), Locale::En), "in 3 days");
+        assert_eq!(relative(&(-2).hours(), Locale::En), "2 hours ago");
+        assert_eq!(relative(&(-1).day(), Locale::De), "vor 1 Tag");
+        assert_eq!(relative(&5.minutes(), Locale::Fr), "dans 5 minutes");
+    }
+}
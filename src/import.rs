@@ -0,0 +1,174 @@
+//! Seeding the upload history with X-Tokens from elsewhere, for `envsh import-tokens`
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use envsh::Token;
+use jiff::Timestamp;
+use reqwest::Url;
+
+use crate::error::{Context, EnvshError, Result};
+use crate::history::{self, Entry, History};
+use crate::parse::{self, EnvsUrlError};
+use crate::{config, keyring};
+
+/// Read `url,token` lines from `file` into the history, storing tokens in the keyring with
+/// `use_keyring`
+pub(crate) fn import_tokens(file: &Path, use_keyring: bool, quiet: bool) -> Result<()> {
+    history::check_writable("import-tokens")?;
+    let contents =
+        fs::read_to_string(file).context(format!("failed to read {}", file.display()))?;
+    let pairs = parse_csv(&contents, &config::get().host())
+        .map_err(|e| EnvshError::Data(format!("{}: {e}", file.display())))?;
+
+    let mut history = History::load()?;
+    let (mut added, mut updated) = (0, 0);
+    let now = Timestamp::now();
+    for (url, token) in pairs {
+        let stored = if use_keyring {
+            keyring::store(url.as_str(), &Token::new(token))
+                .context(format!("failed to store X-Token for {url} in the keyring"))?;
+            None
+        } else {
+            Some(token)
+        };
+
+        let existing: Vec<_> = history
+            .entries
+            .iter_mut()
+            .filter(|e| e.url == url.as_str())
+            .collect();
+        if existing.is_empty() {
+            history.add(Entry {
+                id: 0,
+                url: url.to_string(),
+                token: stored,
+                keyring: use_keyring,
+                expires: None,
+                input: url.to_string(),
+                shortened: false,
+                size: None,
+                sha256: None,
+                tags: vec![],
+                created: now,
+                headers: BTreeMap::new(),
+            });
+            added += 1;
+        } else {
+            for entry in existing {
+                entry.token = stored.clone();
+                entry.keyring = use_keyring;
+            }
+            updated += 1;
+        }
+    }
+    history.save()?;
+
+    if !quiet {
+        eprintln!("Imported {added} new and {updated} already recorded uploads");
+    }
+    Ok(())
+}
+
+/// Parse `url,token` lines, skipping blank lines, `#` comments and a `url,token` header
+///
+/// Fields may be quoted, as spreadsheets export them. URLs must belong to `host`.
+fn parse_csv(contents: &str, host: &Url) -> Result<Vec<(Url, String)>, String> {
+    let mut pairs = vec![];
+    for (n, line) in contents
+        .lines()
+        .enumerate()
+        .map(|(n, line)| (n + 1, line.trim()))
+    {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((url, token)) = line.split_once(',') else {
+            return Err(format!("line {n}: expected URL,TOKEN"));
+        };
+        let (url, token) = (unquote(url), unquote(token));
+        if pairs.is_empty() && url.eq_ignore_ascii_case("url") {
+            continue;
+        }
+
+        let url = match parse::envs_url(url, host) {
+            Ok(url) => url,
+            Err(EnvsUrlError::Invalid(e)) => return Err(format!("line {n}: invalid URL: {e}")),
+            Err(EnvsUrlError::NotEnvs(url)) => {
+                return Err(format!("line {n}: {url} is not on {host}"));
+            }
+        };
+        parse::token(token).map_err(|problem| format!("line {n}: invalid token: {problem}"))?;
+        pairs.push((url, token.to_string()));
+    }
+    Ok(pairs)
+}
+
+/// `field` without surrounding whitespace and double quotes
+fn unquote(field: &str) -> &str {
+    let field = field.trim();
+    field
+        .strip_prefix('"')
+        .and_then(|field| field.strip_suffix('"'))
+        .unwrap_or(field)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The default host
+    fn envs() -> Url {
+        Url::parse(crate::ENVS).unwrap()
+    }
+
+    #[test]
+    fn parses_token_csv() {
+        let csv = "url,token\n\
+            # from the old upload script\n\
+            https://envs.sh/aB3.txt,tokenAAAAAAAAAAAAAAAAAA\n\
+            \n\
+            \"https://envs.sh/s1x\", \"tokenBBBBBBBBBBBBBBBBBB\"\n";
+        let pairs = parse_csv(csv, &envs()).unwrap();
+        assert_eq!(
+            pairs,
+            [
+                (
+                    Url::parse("https://envs.sh/aB3.txt").unwrap(),
+                    "tokenAAAAAAAAAAAAAAAAAA".to_string()
+                ),
+                (
+                    Url::parse("https://envs.sh/s1x").unwrap(),
+                    "tokenBBBBBBBBBBBBBBBBBB".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_lines() {
+        let host = envs();
+        assert!(
+            parse_csv("https://envs.sh/a.txt", &host)
+                .unwrap_err()
+                .starts_with("line 1:")
+        );
+        let other_host = "https://envs.sh/a.txt,tokenAAAAAAAAAAAAAAAAAA\n\
+            https://0x0.st/b,tokenAAAAAAAAAAAAAAAAAA";
+        assert!(
+            parse_csv(other_host, &host)
+                .unwrap_err()
+                .starts_with("line 2:")
+        );
+        assert!(parse_csv("https://envs.sh/a.txt,to$ken", &host).is_err());
+        // Only a first line can be a header
+        assert!(
+            parse_csv(
+                "https://envs.sh/a.txt,tokenAAAAAAAAAAAAAAAAAA\nurl,token",
+                &host
+            )
+            .is_err()
+        );
+    }
+}
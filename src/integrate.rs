@@ -0,0 +1,192 @@
+//! Desktop "Send to envsh" integration
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Context, EnvshError, Result};
+use crate::paths;
+
+/// Name shown in file manager menus
+const ACTION_NAME: &str = "Send to envsh";
+
+/// Desktop environments with their own file manager integration
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Desktop {
+    /// GNOME (Nautilus scripts)
+    Gnome,
+    /// KDE Plasma (Dolphin service menus)
+    Kde,
+    /// Termux on Android (the share sheet's `termux-file-editor` hook)
+    Termux,
+    /// Anything else (a generic `.desktop` entry)
+    Other,
+}
+
+impl Desktop {
+    /// Detect the running desktop from `XDG_CURRENT_DESKTOP`, or Termux from its environment
+    fn detect() -> Self {
+        if paths::is_termux() {
+            return Self::Termux;
+        }
+
+        let current = env::var("XDG_CURRENT_DESKTOP").unwrap_or_default();
+        current
+            .split(':')
+            .find_map(|de| match de.to_ascii_lowercase().as_str() {
+                "gnome" | "unity" | "ubuntu" => Some(Self::Gnome),
+                "kde" => Some(Self::Kde),
+                _ => None,
+            })
+            .unwrap_or(Self::Other)
+    }
+}
+
+/// Write file manager integration for the detected desktop environment
+pub(crate) fn install_desktop_integration() -> Result<()> {
+    let data_home = paths::data_home()?;
+    let envsh = env::current_exe().context("could not locate envsh executable")?;
+
+    let script = send_script(&envsh);
+    match Desktop::detect() {
+        Desktop::Gnome => {
+            write_file(
+                &data_home.join("nautilus/scripts").join(ACTION_NAME),
+                &script,
+                true,
+            )?;
+        }
+        Desktop::Kde => {
+            let script_path = paths::data_dir()?.join("send-to-envsh");
+            write_file(&script_path, &script, true)?;
+            write_file(
+                &data_home.join("kio/servicemenus/envsh.desktop"),
+                &format!(
+                    "[Desktop Entry]\n\
+                    Type=Service\n\
+                    MimeType=application/octet-stream;\n\
+                    Actions=sendToEnvsh;\n\
+                    X-KDE-Priority=TopLevel\n\
+                    \n\
+                    [Desktop Action sendToEnvsh]\n\
+                    Name={ACTION_NAME}\n\
+                    Icon=document-send\n\
+                    Exec={} %F\n",
+                    exec_quote(&script_path)
+                ),
+                true,
+            )?;
+        }
+        Desktop::Termux => {
+            let home = PathBuf::from(
+                env::var_os("HOME")
+                    .ok_or_else(|| EnvshError::Usage("$HOME is not set".to_string()))?,
+            );
+            write_file(
+                &home.join("bin/termux-file-editor"),
+                &termux_script(&envsh),
+                true,
+            )?;
+        }
+        Desktop::Other => {
+            let script_path = paths::data_dir()?.join("send-to-envsh");
+            write_file(&script_path, &script, true)?;
+            write_file(
+                &data_home.join("applications/envsh.desktop"),
+                &format!(
+                    "[Desktop Entry]\n\
+                    Type=Application\n\
+                    Name={ACTION_NAME}\n\
+                    Icon=document-send\n\
+                    NoDisplay=true\n\
+                    MimeType=application/octet-stream;text/plain;\n\
+                    Exec={} %F\n",
+                    exec_quote(&script_path)
+                ),
+                false,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Shell script uploading each argument, copying its URL and showing it as a notification
+///
+/// Only the URL is shown after a successful upload, and only the error otherwise.
+fn send_script(envsh: &Path) -> String {
+    format!(
+        "#!/bin/sh\n\
+        # Generated by `envsh integrate --desktop`\n\
+        errors=$(mktemp)\n\
+        for f in \"$@\"; do\n    \
+            result=$({} --quiet --copy \"$f\" 2>\"$errors\") || result=$(cat \"$errors\")\n    \
+            if command -v notify-send >/dev/null; then\n        \
+                notify-send envsh \"$result\"\n    \
+            else\n        \
+                echo \"$result\"\n    \
+            fi\n\
+        done\n\
+        rm -f \"$errors\"\n",
+        shell_quote(envsh)
+    )
+}
+
+/// Termux share hook, copying the URL (with `termux-clipboard-set`) and notifying with
+/// `termux-notification` when the Termux:API tools are installed
+fn termux_script(envsh: &Path) -> String {
+    format!(
+        "#!/data/data/com.termux/files/usr/bin/sh\n\
+        # Generated by `envsh integrate --desktop`\n\
+        errors=$(mktemp)\n\
+        result=$({} --quiet --copy \"$1\" 2>\"$errors\") || result=$(cat \"$errors\")\n\
+        rm -f \"$errors\"\n\
+        if command -v termux-notification >/dev/null; then\n    \
+            termux-notification --title envsh --content \"$result\"\n\
+        else\n    \
+            echo \"$result\"\n\
+        fi\n",
+        shell_quote(envsh)
+    )
+}
+
+/// `path` as a single-quoted shell word
+fn shell_quote(path: &Path) -> String {
+    format!("'{}'", path.display().to_string().replace('\'', r"'\''"))
+}
+
+/// `path` as a quoted argument of a desktop entry's `Exec` key
+///
+/// The spec unescapes `\\` in values before splitting arguments, so characters escaped inside
+/// quotes need a doubled backslash.
+fn exec_quote(path: &Path) -> String {
+    let mut quoted = String::from("\"");
+    for c in path.display().to_string().chars() {
+        match c {
+            '"' | '`' | '$' => {
+                quoted.push_str(r"\\");
+                quoted.push(c);
+            }
+            '\\' => quoted.push_str(r"\\\\"),
+            '%' => quoted.push_str("%%"),
+            '\n' => quoted.push_str(r"\n"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Write an integration file, creating parent directories as needed
+fn write_file(path: &Path, contents: &str, executable: bool) -> Result<()> {
+    let failed = || format!("failed to write {}", path.display());
+    fs::create_dir_all(path.parent().unwrap()).context(failed())?;
+    fs::write(path, contents).context(failed())?;
+    #[cfg(unix)]
+    if executable {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+            .context(format!("failed to mark {} as executable", path.display()))?;
+    }
+    println!("Wrote {}", path.display());
+    Ok(())
+}
@@ -0,0 +1,181 @@
+//! Machine-readable results for `--json`, printed as one object per line
+
+use std::collections::BTreeMap;
+
+use jiff::Zoned;
+use serde::Serialize;
+
+/// Result of uploading a file or submitting a URL
+#[derive(Debug, Serialize)]
+pub(crate) struct Upload {
+    /// Target as given on the command line
+    pub(crate) input: String,
+    /// HTTP status, absent if the request couldn't be sent or a previous short link was reused
+    pub(crate) status: Option<u16>,
+    /// Resulting URL
+    pub(crate) url: Option<String>,
+    /// Error body or message
+    pub(crate) error: Option<String>,
+    /// Stable error code, e.g. `"E_TOO_LARGE"` or `"E_RATE_LIMIT"`
+    pub(crate) code: Option<&'static str>,
+    /// X-Token, only with `--display-secret`
+    pub(crate) token: Option<String>,
+    /// Expiry time, as RFC 3339
+    pub(crate) expires: Option<String>,
+    /// Request quota, if the host reported one
+    pub(crate) rate_limit: Option<RateLimit>,
+    /// Problems that didn't stop the upload, e.g. a sensitive file sent with `--allow-sensitive`
+    pub(crate) warnings: Vec<String>,
+}
+
+/// Request quota reported in the host's rate-limit headers
+#[derive(Debug, Serialize)]
+pub(crate) struct RateLimit {
+    /// Requests allowed per window
+    pub(crate) limit: Option<u64>,
+    /// Requests left in the current window
+    pub(crate) remaining: Option<u64>,
+    /// Seconds until the window resets
+    pub(crate) reset: Option<u64>,
+}
+
+impl From<envsh::RateLimit> for RateLimit {
+    fn from(rate_limit: envsh::RateLimit) -> Self {
+        Self {
+            limit: rate_limit.limit,
+            remaining: rate_limit.remaining,
+            reset: rate_limit.reset.map(|reset| reset.as_secs()),
+        }
+    }
+}
+
+/// Result of changing or deleting an existing URL
+#[derive(Debug, Serialize)]
+pub(crate) struct Manage {
+    /// URL being managed
+    pub(crate) url: String,
+    /// `"delete"` or `"expires"`
+    pub(crate) action: &'static str,
+    /// HTTP status, absent if the request couldn't be sent
+    pub(crate) status: Option<u16>,
+    /// Requested expiry time, as RFC 3339
+    pub(crate) expires: Option<String>,
+    /// Error body or message
+    pub(crate) error: Option<String>,
+    /// Stable error code, e.g. `"E_TOO_LARGE"` or `"E_RATE_LIMIT"`
+    pub(crate) code: Option<&'static str>,
+    /// Problems that didn't stop the change, e.g. an expiry earlier than the current one
+    pub(crate) warnings: Vec<String>,
+}
+
+/// A change `manage --dry-run` would have sent
+#[derive(Debug, Serialize)]
+pub(crate) struct DryRun {
+    /// URL that would be changed
+    pub(crate) url: String,
+    /// `"delete"` or `"expires"`
+    pub(crate) action: &'static str,
+    /// Form fields that would be sent, besides the token
+    pub(crate) fields: BTreeMap<&'static str, String>,
+    /// Where the token came from: `"argument"`, `"history"` or `"keyring"`
+    pub(crate) token_source: &'static str,
+    /// Requested expiry time, as RFC 3339
+    pub(crate) expires: Option<String>,
+    /// Problems the change would have, e.g. an expiry earlier than the current one
+    pub(crate) warnings: Vec<String>,
+}
+
+/// An upload recorded in the local history
+#[derive(Debug, Serialize)]
+pub(crate) struct Listed {
+    /// History ID
+    pub(crate) id: u64,
+    /// Resulting URL
+    pub(crate) url: String,
+    /// Target as given when uploading
+    pub(crate) input: String,
+    /// Expiry time, as RFC 3339
+    pub(crate) expires: Option<String>,
+    /// Whether the X-Token is stored
+    pub(crate) token: bool,
+    /// Stored X-Token, only with `--tokens`
+    pub(crate) x_token: Option<String>,
+    /// Labels given with `--tag`
+    pub(crate) tags: Vec<String>,
+    /// When the upload was made, as RFC 3339
+    pub(crate) created: String,
+    /// Bytes sent, for file uploads
+    pub(crate) size: Option<u64>,
+    /// Hex SHA-256 of the bytes sent, for file uploads
+    pub(crate) sha256: Option<String>,
+    /// Response headers recorded with the upload, by lowercase name
+    pub(crate) headers: BTreeMap<String, String>,
+}
+
+/// An entry in the trash of the local history
+#[derive(Debug, Serialize)]
+pub(crate) struct Trashed {
+    /// History ID
+    pub(crate) id: u64,
+    /// Deleted URL
+    pub(crate) url: String,
+    /// Target as given when uploading
+    pub(crate) input: String,
+    /// Whether the X-Token is still stored
+    pub(crate) token: bool,
+    /// When the upload was deleted, as RFC 3339
+    pub(crate) deleted: String,
+}
+
+/// A local alias for an upload
+#[derive(Debug, Serialize)]
+pub(crate) struct Alias {
+    /// Alias name, without the leading `@`
+    pub(crate) name: String,
+    /// History ID it points at
+    pub(crate) id: u64,
+    /// URL of that upload, absent if it is no longer in the history
+    pub(crate) url: Option<String>,
+}
+
+/// Line ending the results of a batch, as `{"summary":{...}}` so it can't pass for an upload
+#[derive(Debug, Serialize)]
+pub(crate) struct Batch {
+    /// What became of the batch's targets
+    pub(crate) summary: Summary,
+}
+
+/// Counts of what became of a batch's targets
+#[derive(Debug, Serialize)]
+pub(crate) struct Summary {
+    /// Sent and accepted as new uploads
+    pub(crate) uploaded: usize,
+    /// Not sent, as repeats of earlier targets or reused short links
+    pub(crate) local_duplicates: usize,
+    /// Accepted, but already on the host
+    pub(crate) server_duplicates: usize,
+    /// Rejected, or couldn't be sent
+    pub(crate) failed: usize,
+}
+
+/// An error that ended the program
+#[derive(Debug, Serialize)]
+pub(crate) struct Error {
+    /// What went wrong
+    pub(crate) error: String,
+    /// Stable error code, e.g. `"E_USAGE"` or `"E_CONNECT"`
+    pub(crate) code: &'static str,
+}
+
+/// Format a time as RFC 3339 in UTC
+pub(crate) fn rfc3339(time: &Zoned) -> String {
+    time.timestamp().to_string()
+}
+
+/// Print `value` as a single line of JSON
+pub(crate) fn print(value: &impl Serialize) {
+    println!(
+        "{}",
+        serde_json::to_string(value).expect("failed to serialize output")
+    );
+}
@@ -0,0 +1,239 @@
+//! Keeping X-Tokens in the OS keyring instead of the plaintext history
+//!
+//! Uses `secret-tool` (Secret Service) on Linux and the BSDs, `security` (Keychain) on macOS and
+//! the Credential Manager through PowerShell on Windows. Tokens are filed under the service
+//! `envsh`, keyed by upload URL, and always passed on stdin so other users can't see them in `ps`.
+
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+use envsh::Token;
+
+/// Service name tokens are stored under
+const SERVICE: &str = "envsh";
+
+/// PowerShell definitions of `[EnvshCredential]::Write`, `Read` and `Delete`, which wrap the
+/// Credential Manager API for generic credentials named `envsh:URL`
+const POWERSHELL_CREDENTIALS: &str = r#"$ErrorActionPreference = 'Stop'
+Add-Type -TypeDefinition @'
+using System;
+using System.ComponentModel;
+using System.Runtime.InteropServices;
+using System.Text;
+
+public static class EnvshCredential {
+    [StructLayout(LayoutKind.Sequential, CharSet = CharSet.Unicode)]
+    struct CREDENTIAL {
+        public int Flags;
+        public int Type;
+        public string TargetName;
+        public string Comment;
+        public System.Runtime.InteropServices.ComTypes.FILETIME LastWritten;
+        public int CredentialBlobSize;
+        public IntPtr CredentialBlob;
+        public int Persist;
+        public int AttributeCount;
+        public IntPtr Attributes;
+        public string TargetAlias;
+        public string UserName;
+    }
+
+    const int CRED_TYPE_GENERIC = 1;
+    const int CRED_PERSIST_LOCAL_MACHINE = 2;
+
+    [DllImport("advapi32.dll", CharSet = CharSet.Unicode, SetLastError = true)]
+    static extern bool CredWrite(ref CREDENTIAL credential, int flags);
+    [DllImport("advapi32.dll", CharSet = CharSet.Unicode, SetLastError = true)]
+    static extern bool CredRead(string target, int type, int flags, out IntPtr credential);
+    [DllImport("advapi32.dll", CharSet = CharSet.Unicode, SetLastError = true)]
+    static extern bool CredDelete(string target, int type, int flags);
+    [DllImport("advapi32.dll")]
+    static extern void CredFree(IntPtr buffer);
+
+    public static void Write(string url, string token) {
+        byte[] blob = Encoding.Unicode.GetBytes(token);
+        CREDENTIAL credential = new CREDENTIAL();
+        credential.Type = CRED_TYPE_GENERIC;
+        credential.TargetName = "envsh:" + url;
+        credential.UserName = "envsh";
+        credential.Persist = CRED_PERSIST_LOCAL_MACHINE;
+        credential.CredentialBlobSize = blob.Length;
+        credential.CredentialBlob = Marshal.AllocHGlobal(blob.Length);
+        try {
+            Marshal.Copy(blob, 0, credential.CredentialBlob, blob.Length);
+            if (!CredWrite(ref credential, 0)) throw new Win32Exception();
+        } finally {
+            Marshal.FreeHGlobal(credential.CredentialBlob);
+        }
+    }
+
+    public static string Read(string url) {
+        IntPtr buffer;
+        if (!CredRead("envsh:" + url, CRED_TYPE_GENERIC, 0, out buffer)) throw new Win32Exception();
+        try {
+            CREDENTIAL credential = (CREDENTIAL)Marshal.PtrToStructure(buffer, typeof(CREDENTIAL));
+            return Marshal.PtrToStringUni(credential.CredentialBlob, credential.CredentialBlobSize / 2);
+        } finally {
+            CredFree(buffer);
+        }
+    }
+
+    public static void Delete(string url) {
+        if (!CredDelete("envsh:" + url, CRED_TYPE_GENERIC, 0)) throw new Win32Exception();
+    }
+}
+'@
+$lines = [Console]::In.ReadToEnd() -split "\r?\n"
+"#;
+
+/// Store `token` for the upload at `url`
+pub(crate) fn store(url: &str, token: &Token) -> io::Result<()> {
+    if cfg!(target_os = "macos") {
+        // A trailing `-w` without a value makes `security` prompt for the password, and its retype
+        run(
+            Command::new("security").args([
+                "add-generic-password",
+                "-U",
+                "-s",
+                SERVICE,
+                "-a",
+                url,
+                "-w",
+            ]),
+            Some(&format!("{0}\n{0}\n", token.expose())),
+        )
+        .map(drop)
+    } else if cfg!(windows) {
+        powershell(
+            "[EnvshCredential]::Write($lines[0], $lines[1])",
+            url,
+            Some(token),
+        )
+        .map(drop)
+    } else if cfg!(unix) {
+        run(
+            Command::new("secret-tool").args([
+                "store",
+                &format!("--label=envsh token for {url}"),
+                "service",
+                SERVICE,
+                "url",
+                url,
+            ]),
+            Some(token.expose()),
+        )
+        .map(drop)
+    } else {
+        Err(unsupported())
+    }
+}
+
+/// The token stored for the upload at `url`
+pub(crate) fn get(url: &str) -> io::Result<Token> {
+    let output = if cfg!(target_os = "macos") {
+        run(
+            Command::new("security").args([
+                "find-generic-password",
+                "-s",
+                SERVICE,
+                "-a",
+                url,
+                "-w",
+            ]),
+            None,
+        )
+    } else if cfg!(windows) {
+        powershell(
+            "[Console]::Out.Write([EnvshCredential]::Read($lines[0]))",
+            url,
+            None,
+        )
+    } else if cfg!(unix) {
+        run(
+            Command::new("secret-tool").args(["lookup", "service", SERVICE, "url", url]),
+            None,
+        )
+    } else {
+        Err(unsupported())
+    }?;
+    Ok(Token::new(output.trim_end_matches('\n')))
+}
+
+/// Remove the token stored for the upload at `url`
+pub(crate) fn delete(url: &str) -> io::Result<()> {
+    if cfg!(target_os = "macos") {
+        run(
+            Command::new("security").args(["delete-generic-password", "-s", SERVICE, "-a", url]),
+            None,
+        )
+        .map(drop)
+    } else if cfg!(windows) {
+        powershell("[EnvshCredential]::Delete($lines[0])", url, None).map(drop)
+    } else if cfg!(unix) {
+        run(
+            Command::new("secret-tool").args(["clear", "service", SERVICE, "url", url]),
+            None,
+        )
+        .map(drop)
+    } else {
+        Err(unsupported())
+    }
+}
+
+/// Error for platforms without a supported keyring tool
+fn unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "no supported keyring on this platform",
+    )
+}
+
+/// Run `script` after [`POWERSHELL_CREDENTIALS`], with `url` and `token` as the first two `$lines`
+fn powershell(script: &str, url: &str, token: Option<&Token>) -> io::Result<String> {
+    let input = match token {
+        Some(token) => format!("{url}\n{}\n", token.expose()),
+        None => format!("{url}\n"),
+    };
+    run(
+        Command::new("powershell").args([
+            "-NoProfile",
+            "-NonInteractive",
+            "-Command",
+            &format!("{POWERSHELL_CREDENTIALS}{script}"),
+        ]),
+        Some(&input),
+    )
+}
+
+/// Run a keyring tool, feeding it `stdin`, and return its stdout
+fn run(command: &mut Command, stdin: Option<&str>) -> io::Result<String> {
+    let mut child = command
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("failed to run {:?}: {e}", command.get_program()),
+            )
+        })?;
+    if let Some(input) = stdin {
+        child.stdin.take().unwrap().write_all(input.as_bytes())?;
+    }
+
+    let output = child.wait_with_output()?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(io::Error::other(format!(
+            "{:?} failed: {}",
+            command.get_program(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
@@ -0,0 +1,425 @@
+//! Send and shorten stuff with envs.sh
+//!
+//! [`EnvsClient`] uploads files, submits and shortens URLs, and changes or deletes existing
+//! uploads. The `envsh` binary is a command-line interface on top of it.
+//!
+//! ```no_run
+//! use envsh::{Content, EnvsClient, Expiry, Upload};
+//!
+//! let envs = EnvsClient::new();
+//! let created = envs.upload(Upload {
+//!     expires: Some(Expiry::Hours(24)),
+//!     ..Upload::new(Content::file("notes.txt".as_ref())?)
+//! })?;
+//! println!("{}", created.url().expect("upload failed"));
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! With the `async` feature, [`nonblocking::EnvsClient`] offers the same requests for async code.
+#![deny(missing_docs)]
+#![deny(clippy::missing_docs_in_private_items)]
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use jiff::{Timestamp, ToSpan};
+use reqwest::blocking::{
+    Client,
+    multipart::{Form, Part},
+};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{StatusCode, Url};
+
+#[cfg(feature = "async")]
+pub mod nonblocking;
+
+/// File host/URL shortener
+pub const ENVS: &str = "https://envs.sh";
+
+/// Upload response headers kept in [`Created::headers`], for debugging and auditing
+pub const RECORDED_HEADERS: &[&str] = &[
+    "X-Expires",
+    "X-Token",
+    "Content-Length",
+    "RateLimit-Limit",
+    "RateLimit-Remaining",
+    "RateLimit-Reset",
+    "X-RateLimit-Limit",
+    "X-RateLimit-Remaining",
+    "X-RateLimit-Reset",
+    "Retry-After",
+];
+
+/// The time at which a URL will expire
+#[derive(Clone, Debug)]
+pub enum Expiry {
+    /// Delete in X hours
+    Hours(i64),
+    /// Delete at provided [`Timestamp`]
+    Timestamp(Timestamp),
+}
+
+impl Expiry {
+    /// Maximum number of hours a file will be hosted
+    pub const MAX_EXPIRY_HOURS: i64 = 24 * 30;
+
+    /// The instant this expiry refers to, with hours counted from `now`
+    pub fn resolve(&self, now: Timestamp) -> Timestamp {
+        match self {
+            Self::Hours(h) => now.checked_add(h.hours()).unwrap(),
+            Self::Timestamp(ts) => *ts,
+        }
+    }
+}
+
+impl Display for Expiry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Hours(h) => h.fmt(f),
+            Self::Timestamp(ts) => ts.as_millisecond().fmt(f),
+        }
+    }
+}
+
+/// The X-Token needed to manage an upload
+///
+/// Its `Debug` output is redacted so tokens don't end up in logs or debug dumps; use
+/// [`Token::expose`] to get at the value.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Token(String);
+
+impl Token {
+    /// Wrap a token value
+    pub fn new(token: impl Into<String>) -> Self {
+        Self(token.into())
+    }
+
+    /// The token value
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Token(<redacted>)")
+    }
+}
+
+/// What to send to the host
+#[derive(Debug)]
+pub enum Content {
+    /// File contents, as a named multipart part
+    File(Part),
+    /// A URL for the host to fetch and store
+    Url(Url),
+    /// A URL to shorten
+    Shorten(Url),
+}
+
+impl Content {
+    /// Stream a local file, named after it and with a MIME type guessed from its extension
+    pub fn file(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let mime = mime_guess::from_path(path).first_or_octet_stream();
+
+        let part = Part::reader_with_length(file, len)
+            .mime_str(mime.as_ref())
+            .unwrap();
+        Ok(Self::File(match path.file_name() {
+            Some(name) => part.file_name(name.to_string_lossy().into_owned()),
+            None => part,
+        }))
+    }
+}
+
+/// A new upload or URL submission
+#[derive(Debug)]
+pub struct Upload {
+    /// What to send
+    pub content: Content,
+    /// Make the resulting URL difficult to guess
+    pub secret: bool,
+    /// When the upload should expire
+    pub expires: Option<Expiry>,
+}
+
+impl Upload {
+    /// Send `content` with the host's default options
+    pub fn new(content: Content) -> Self {
+        Self {
+            content,
+            secret: false,
+            expires: None,
+        }
+    }
+}
+
+/// The host's response to an [`Upload`]
+#[derive(Clone, Debug)]
+pub struct Created {
+    /// HTTP status
+    pub status: StatusCode,
+    /// Response body: the new URL on success, otherwise an error message
+    pub body: String,
+    /// X-Token needed to manage the upload
+    pub token: Option<Token>,
+    /// When the upload expires
+    pub expires: Option<Timestamp>,
+    /// Remaining request quota, if the host reported one
+    pub rate_limit: Option<RateLimit>,
+    /// Those of the [`RECORDED_HEADERS`] the host sent, by lowercase name
+    ///
+    /// The X-Token's value is left out, only recording that it was sent.
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Created {
+    /// Whether the host accepted the upload
+    pub fn is_success(&self) -> bool {
+        self.status.is_success()
+    }
+
+    /// The new URL, if the host accepted the upload
+    pub fn url(&self) -> Option<Url> {
+        self.is_success()
+            .then(|| Url::parse(self.body.trim()).ok())
+            .flatten()
+    }
+}
+
+/// Request quota reported in a host's rate-limit headers
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Requests allowed per window
+    pub limit: Option<u64>,
+    /// Requests left in the current window
+    pub remaining: Option<u64>,
+    /// Time until the window resets, or until the host accepts requests again
+    pub reset: Option<Duration>,
+}
+
+impl RateLimit {
+    /// Whether the host won't accept more requests until [`RateLimit::reset`]
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining == Some(0)
+    }
+}
+
+impl Display for RateLimit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match (self.remaining, self.limit) {
+            (Some(remaining), Some(limit)) => write!(f, "{remaining} of {limit} requests left")?,
+            (Some(remaining), None) => write!(f, "{remaining} requests left")?,
+            (None, Some(limit)) => write!(f, "{limit} requests allowed")?,
+            (None, None) => write!(f, "rate limited")?,
+        }
+        match self.reset {
+            Some(reset) => write!(f, ", resets in {}s", reset.as_secs()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A change to an existing upload
+#[derive(Clone, Debug)]
+pub enum Change {
+    /// Set a new expiry time
+    Expires(Expiry),
+    /// Delete the upload immediately
+    Delete,
+}
+
+impl Change {
+    /// Name and value of the form field sent for this change, alongside the token
+    pub fn field(&self) -> (&'static str, String) {
+        match self {
+            Self::Expires(expiry) => ("expires", expiry.to_string()),
+            Self::Delete => ("delete", String::new()),
+        }
+    }
+}
+
+/// The host's response to a [`Change`]
+#[derive(Clone, Debug)]
+pub struct Changed {
+    /// HTTP status
+    pub status: StatusCode,
+    /// Response body, usually empty on success
+    pub body: String,
+}
+
+impl Changed {
+    /// Whether the host accepted the change
+    pub fn is_success(&self) -> bool {
+        self.status.is_success()
+    }
+}
+
+/// Client for an envs.sh host
+#[derive(Clone, Debug)]
+pub struct EnvsClient {
+    /// Underlying HTTP client, shared between requests so connections are reused
+    http: Client,
+    /// Host that uploads are sent to
+    host: Url,
+}
+
+impl Default for EnvsClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EnvsClient {
+    /// Client for [`ENVS`]
+    pub fn new() -> Self {
+        Self::with_http_client(Client::new())
+    }
+
+    /// Client for [`ENVS`], sending requests through `http`
+    pub fn with_http_client(http: Client) -> Self {
+        Self {
+            http,
+            host: Url::parse(ENVS).unwrap(),
+        }
+    }
+
+    /// Send uploads to `host` instead of [`ENVS`]
+    pub fn with_host(self, host: Url) -> Self {
+        Self { host, ..self }
+    }
+
+    /// Host that uploads are sent to
+    pub fn host(&self) -> &Url {
+        &self.host
+    }
+
+    /// Underlying HTTP client
+    pub fn http(&self) -> &Client {
+        &self.http
+    }
+
+    /// Upload a file, or submit or shorten a URL
+    pub fn upload(&self, upload: Upload) -> reqwest::Result<Created> {
+        let form = [
+            Some(match upload.content {
+                Content::File(part) => ("file", part),
+                Content::Url(url) => ("url", Part::text(url.to_string())),
+                Content::Shorten(url) => ("shorten", Part::text(url.to_string())),
+            }),
+            upload.secret.then_some(("secret", Part::text(""))),
+            upload
+                .expires
+                .map(|time| ("expires", Part::text(time.to_string()))),
+        ]
+        .into_iter()
+        .flatten()
+        .fold(Form::new(), |form, (name, value)| form.part(name, value));
+
+        let resp = self.http.post(self.host.clone()).multipart(form).send()?;
+        let status = resp.status();
+        let token = resp
+            .headers()
+            .get("X-Token")
+            .and_then(|t| t.to_str().map(Token::new).ok());
+        let expires = expires_header(resp.headers());
+        let rate_limit = rate_limit_headers(resp.headers());
+        let headers = recorded_headers(resp.headers());
+
+        Ok(Created {
+            status,
+            body: resp.text()?,
+            token,
+            expires,
+            rate_limit,
+            headers,
+        })
+    }
+
+    /// Change the expiry of, or delete, the upload at `url`
+    pub fn change(&self, url: Url, token: &Token, change: &Change) -> reqwest::Result<Changed> {
+        let (name, value) = change.field();
+        let form = Form::new()
+            .text("token", token.expose().to_string())
+            .text(name, value);
+
+        let resp = self.http.post(url).multipart(form).send()?;
+        Ok(Changed {
+            status: resp.status(),
+            body: resp.text()?,
+        })
+    }
+}
+
+/// Parse `RateLimit-*` / `X-RateLimit-*` and `Retry-After` headers of a host response
+///
+/// Resets may be given in seconds or, as some hosts do for `X-RateLimit-Reset`, as an epoch
+/// time in seconds. A `Retry-After` means no requests are left until it passes.
+pub fn rate_limit_headers(headers: &HeaderMap) -> Option<RateLimit> {
+    let number = |name: &str| {
+        [name.to_string(), format!("X-{name}")]
+            .iter()
+            .find_map(|name| headers.get(name.as_str()))
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| u64::from_str(value.trim()).ok())
+    };
+    let now = Timestamp::now().as_second().max(0) as u64;
+    // No window is longer than 30 years, so anything bigger must be an epoch time
+    let reset = number("RateLimit-Reset").map(|secs| {
+        if secs > 1_000_000_000 {
+            secs.saturating_sub(now)
+        } else {
+            secs
+        }
+    });
+    let retry_after = headers
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| match u64::from_str(value.trim()) {
+            Ok(secs) => Some(secs),
+            Err(_) => {
+                let at = jiff::fmt::rfc2822::parse(value).ok()?.timestamp();
+                Some((at.as_second().max(0) as u64).saturating_sub(now))
+            }
+        });
+
+    let rate_limit = RateLimit {
+        limit: number("RateLimit-Limit"),
+        remaining: number("RateLimit-Remaining").or(retry_after.map(|_| 0)),
+        reset: retry_after.or(reset).map(Duration::from_secs),
+    };
+    (rate_limit != RateLimit::default()).then_some(rate_limit)
+}
+
+/// The [`RECORDED_HEADERS`] in `headers`, for [`Created::headers`]
+pub fn recorded_headers(headers: &HeaderMap) -> BTreeMap<String, String> {
+    RECORDED_HEADERS
+        .iter()
+        .filter_map(|&name| {
+            let value = headers.get(name)?;
+            let value = if name == "X-Token" {
+                "(sent)".to_string()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).into_owned()
+            };
+            Some((name.to_ascii_lowercase(), value))
+        })
+        .collect()
+}
+
+/// Parse the X-Expires header (epoch milliseconds) of a host response
+pub fn expires_header(headers: &HeaderMap) -> Option<Timestamp> {
+    headers.get("X-Expires").and_then(|exp| {
+        exp.to_str()
+            .ok()
+            .and_then(|exp| f64::from_str(exp).ok())
+            .and_then(|ms| Timestamp::from_millisecond(ms as i64).ok())
+    })
+}
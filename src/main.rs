@@ -2,85 +2,484 @@
 #![deny(missing_docs)]
 #![deny(clippy::missing_docs_in_private_items)]
 
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsString;
 use std::fmt::{Display, Formatter};
+use std::fs::{self, File};
 use std::io;
-use std::io::Write;
-use std::path::PathBuf;
-use std::str::FromStr;
+use std::io::{BufReader, IsTerminal, Write};
+use std::mem;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, mpsc};
+use std::thread;
+use std::time::Duration;
+use std::{env, process};
 
 use clap::builder::ValueHint;
-use clap::{Args, CommandFactory, Parser, Subcommand};
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
 use clap_complete::Shell;
-use jiff::{Timestamp, tz::TimeZone};
-use reqwest::Url;
-use reqwest::blocking::{
-    Client,
-    multipart::{Form, Part},
-};
+use jiff::{SpanRound, Timestamp, ToSpan, Unit, Zoned, tz::TimeZone};
+use percent_encoding::percent_decode_str;
+use reqwest::blocking::{Client, Response, multipart::Part};
+use reqwest::header::{CONTENT_DISPOSITION, CONTENT_TYPE, LOCATION};
+use reqwest::redirect::Policy;
+use reqwest::{StatusCode, Url};
+use url::Host;
+
+use envsh::{Change, Changed, Content, Created, ENVS, EnvsClient, Expiry, Token, Upload};
 
-use cli::{EnvsUrlValueParser, ExpiryValueParser, TargetValueParser};
+use cli::{
+    AliasValueParser, ByteSizeValueParser, ContentTypeValueParser, DurationValueParser,
+    EnvsUrlValueParser, ExpiresAtValueParser, ExpiryValueParser, ExtensionValueParser,
+    HistoryIdValueParser, ManageTargetValueParser, ResolveValueParser, SecondsValueParser,
+    TargetValueParser, TemplateValueParser, TokenValueParser, WithValueParser,
+};
+use error::{Context, EnvshError, Result};
+use history::{History, Tally};
+use humanize::Locale;
+use progress::Progress;
+use qr::QrCode;
+use retry::Retry;
+use sign::{Signed, Signer};
+use temp::TempFile;
+use template::Template;
+use throttle::Throttle;
+use transform::{Pipeline, Stream, Transform};
 
+mod alias;
+mod archive;
+mod bench;
+mod browser;
 mod cli;
+mod clipboard;
+mod config;
+mod crypt;
+mod error;
+mod history;
+mod humanize;
+mod import;
+mod integrate;
+mod json;
+mod keyring;
+mod notice;
+mod parse;
+mod paths;
+mod preview;
+mod progress;
+mod qr;
+mod receipt;
+mod report;
+mod retry;
+mod reveal;
+mod sensitive;
+mod sheet;
+mod sign;
+mod sound;
+mod temp;
+mod template;
+mod throttle;
+mod transform;
+mod trash;
+
+/// Chunk size for reading files being uploaded
+const DEFAULT_BUFFER_SIZE: u64 = 256 * 1024;
 
-/// File host/URL shortener
-const ENVS: &str = "https://envs.sh";
+/// Default strftime format used wherever a time is displayed
+const TIME_FORMAT: &str = "%F (%A), %T%.f [%:Q]";
+
+/// Query parameters removed by `--clean-url`, in addition to any starting with `utm_`
+const TRACKING_PARAMS: &[&str] = &[
+    "fbclid", "gclid", "dclid", "gbraid", "wbraid", "msclkid", "yclid", "igshid", "mc_cid",
+    "mc_eid", "_hsenc", "_hsmi", "mkt_tok",
+];
 
 /// Root command options
-#[derive(Debug, Parser)]
-#[command(about, version)]
+#[derive(Debug, Default, Parser)]
+#[command(
+    about,
+    version,
+    subcommand_negates_reqs = true,
+    override_usage = "envsh [OPTIONS] <FILE|URL>...\n       envsh [OPTIONS] <COMMAND>"
+)]
 struct Cli {
-    /// A file or URL to send to the URL host/shortener
-    #[arg(required = true, value_name = "FILE|URL", value_parser = TargetValueParser)]
-    target: Option<Target>,
+    /// Files or URLs to send to the URL host/shortener, or `-` to read from stdin
+    #[arg(
+        required_unless_present = "files_from",
+        value_name = "FILE|URL",
+        value_parser = TargetValueParser
+    )]
+    target: Vec<Target>,
+
+    /// Read additional targets from a file, one per line, or `-` for stdin
+    #[arg(long, value_name = "LIST", value_hint = ValueHint::FilePath)]
+    files_from: Option<PathBuf>,
+
+    /// Separate --files-from entries with NUL instead of newlines, as from `find -print0`
+    #[arg(short = '0', long, requires = "files_from")]
+    null: bool,
+
+    /// Wait a random time up to SECONDS between uploads in a batch, to avoid bursts of traffic
+    #[arg(long, value_parser = SecondsValueParser, value_name = "SECONDS")]
+    spread: Option<Duration>,
+
+    /// Upload up to N targets of a batch at once, still printing results in order [default: 1]
+    ///
+    /// Progress bars are only shown for one upload at a time
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<NonZeroUsize>,
 
     /// Print X-Token (and expiry date)
     #[arg(short, long, conflicts_with = "shorten")]
     display_secret: bool,
 
+    /// Erase the printed X-Token from the terminal after SECONDS, or once Enter is pressed
+    ///
+    /// Keeps tokens out of scrollback on shared screens. Has no effect unless stdout and stdin are
+    /// terminals
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "30",
+        requires = "display_secret"
+    )]
+    ephemeral_token: Option<u64>,
+
     /// Shorten a URL instead of sending the file it points to
     ///
     /// Will fail if used on a path
     #[arg(short, long)]
     shorten: bool,
 
+    /// Download a URL locally and upload its contents as a file
+    ///
+    /// Useful when the URL is behind authentication or otherwise unreachable from envs.sh
+    #[arg(short, long, conflicts_with = "shorten")]
+    fetch: bool,
+
+    /// Name the fetched file after Content-Disposition or the URL path
+    #[arg(short = 'O', long, requires = "fetch")]
+    remote_name: bool,
+
+    /// Allow submitting URLs on private networks (localhost, RFC 1918, .internal, ...)
+    ///
+    /// envs.sh cannot reach these, and the link would leak internal hostnames
+    #[arg(long)]
+    allow_private: bool,
+
+    /// Strip tracking parameters (utm_*, fbclid, ...) from a URL before submitting it
+    #[arg(short, long)]
+    clean_url: bool,
+
+    /// Store the X-Token in the OS keyring instead of the plaintext upload history
+    ///
+    /// Uses `secret-tool` on Linux, `security` on macOS and the Credential Manager on Windows;
+    /// `manage` reads it back from there
+    #[arg(long)]
+    keyring: bool,
+
     /// Make the resulting URL difficult to guess
+    ///
+    /// Applied automatically to sensitive-looking files (.har, .pem, databases, ...)
     #[arg(short = 'S', long)]
     secret: bool,
 
-    /// Specify when the URL should expire, in hours or epoch milliseconds
+    /// Upload files that look like private keys or password databases
+    #[arg(long)]
+    allow_sensitive: bool,
+
+    /// Size of the chunks read from files while uploading (e.g. 64K, 4M) [default: 256K]
+    #[arg(long, value_parser = ByteSizeValueParser, value_name = "SIZE")]
+    buffer_size: Option<u64>,
+
+    /// Remove EXIF, XMP and text metadata from JPEG and PNG images
+    #[arg(long)]
+    strip_metadata: bool,
+
+    /// Replace the values of passwords, tokens and other secret-looking `key=value` pairs
+    #[arg(long)]
+    redact: bool,
+
+    /// Gzip the file before uploading it, adding ".gz" to its name
+    #[arg(long)]
+    compress: bool,
+
+    /// Encrypt the file before uploading it, and add the key to the printed URL's #fragment
+    ///
+    /// The host only sees ciphertext. Read the file back with `envsh cat --decrypt URL#KEY`
+    #[arg(long, conflicts_with_all = ["shorten", "sign"])]
+    encrypt: bool,
+
+    /// Pack directories into an archive of this format and upload that
+    #[arg(short, long, value_name = "FORMAT")]
+    archive: Option<archive::Format>,
+
+    /// Upload the files symlinks point to, including links inside archived directories [default]
+    #[arg(long, overrides_with = "no_follow_symlinks")]
+    follow_symlinks: bool,
+
+    /// Refuse symlinked file targets, and archive links inside directories as links
+    #[arg(long, overrides_with = "follow_symlinks")]
+    no_follow_symlinks: bool,
+
+    /// File name (and so extension) to upload as, instead of the local one [default: stdin.txt
+    /// for stdin]
+    ///
+    /// Also replaces the archive name for directories and the name of fetched files
+    #[arg(short, long, value_name = "NAME", conflicts_with = "remote_name")]
+    name: Option<String>,
+
+    /// Send the contents as this MIME type instead of the one detected, e.g. text/plain
+    #[arg(long, value_parser = ContentTypeValueParser, value_name = "MIME")]
+    content_type: Option<String>,
+
+    /// Replace the file name's extension, which the host bases the URL's on
+    ///
+    /// Without --content-type, the type sent also becomes the extension's
+    #[arg(long, value_parser = ExtensionValueParser, value_name = "EXT")]
+    ext: Option<String>,
+
+    /// Show the first lines of a file and ask for confirmation before uploading it
+    #[arg(
+        short,
+        long,
+        value_name = "LINES",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "10"
+    )]
+    preview: Option<usize>,
+
+    /// Label the upload in the local history, so `envsh delete --tag` can find it (repeatable)
+    #[arg(long, value_name = "TAG")]
+    tag: Vec<String>,
+
+    /// Sign the file with a minisign secret key and upload the signature alongside it
+    #[arg(long, value_name = "SECKEY", value_hint = ValueHint::FilePath)]
+    sign: Option<PathBuf>,
+
+    /// Specify when the URL should expire: hours, epoch milliseconds, a duration (3d) or a date (2025-07-01)
     #[arg(short, long, value_parser = ExpiryValueParser, value_name = "TIME")]
     expires: Option<Expiry>,
 
+    /// Expire at the next local weekday and/or time, e.g. "monday 09:00" or "tomorrow"
+    #[arg(long, value_parser = ExpiresAtValueParser, value_name = "WHEN", conflicts_with = "expires")]
+    expires_at: Option<Expiry>,
+
+    /// Copy the resulting URLs to the clipboard
+    #[arg(long)]
+    copy: bool,
+
+    /// Print a QR code of each resulting URL, to open it on a phone
+    #[arg(long)]
+    qr: bool,
+
+    /// Open each resulting URL in the default browser
+    #[arg(long)]
+    open: bool,
+
+    /// Print this line for each successful upload instead, e.g. '{url} token={token}'
+    ///
+    /// Placeholders: {url}, {token}, {input} and {expires}, or {expires:%F} for another time
+    /// format. `{{` and `}}` are literal braces, `\n` and `\t` a newline and a tab
+    #[arg(long, value_parser = TemplateValueParser, value_name = "TEMPLATE", conflicts_with = "json")]
+    format: Option<Template>,
+
+    /// End each URL printed with `--quiet` with a NUL byte instead of a newline, for `xargs -0`
+    #[arg(long, requires = "quiet", conflicts_with = "no_newline")]
+    print0: bool,
+
+    /// Don't end the last URL printed with `--quiet` with a newline, to embed it in other text
+    #[arg(long, requires = "quiet")]
+    no_newline: bool,
+
+    /// Print one JSON object per upload or change instead of human-readable text
+    ///
+    /// Other messages (hints, warnings) go to stderr
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Print only the resulting URLs, or nothing for changes, with other messages on stderr
+    ///
+    /// Rejected changes then exit with status 1
+    #[arg(short, long, global = true, conflicts_with = "json")]
+    quiet: bool,
+
+    /// Use another 0x0-compatible instance instead of envs.sh, overriding the config file
+    #[arg(
+        long,
+        global = true,
+        env = "ENVSH_HOST",
+        value_name = "URL",
+        value_hint = ValueHint::Url
+    )]
+    host: Option<Url>,
+
+    /// Send requests for HOST:PORT to ADDR instead of the address DNS gives, like curl (repeatable)
+    ///
+    /// The override applies to every port of HOST
+    #[arg(long, global = true, value_parser = ResolveValueParser, value_name = "HOST:PORT:ADDR")]
+    resolve: Vec<String>,
+
+    /// Talk to the host over this Unix socket instead of TCP, e.g. for a local test instance
+    ///
+    /// URLs still name the host, which is sent as the Host header but never resolved
+    #[arg(long, global = true, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    unix_socket: Option<PathBuf>,
+
+    /// Change a config file setting for this run only, e.g. `expires=24` or `output.json=true`
+    /// (repeatable)
+    ///
+    /// Values are TOML, or else strings. Other flags still take precedence
+    #[arg(long, global = true, value_parser = WithValueParser, value_name = "KEY=VALUE")]
+    with: Vec<String>,
+
+    /// Don't play the success or failure sound set in the config file
+    #[arg(long, global = true)]
+    silent: bool,
+
+    /// Times to re-send a request after a connection error or 5xx response [default: 2]
+    ///
+    /// Uploads from stdin are never re-sent, since their contents can't be read twice
+    #[arg(long, global = true, value_name = "N")]
+    retries: Option<u32>,
+
+    /// Seconds to wait before the first retry, doubling for each after it [default: 1]
+    #[arg(long, global = true, value_parser = SecondsValueParser, value_name = "SECONDS")]
+    retry_delay: Option<Duration>,
+
+    /// Keep nothing on disk: no upload history, aliases, receipts or keyring entries
+    ///
+    /// For shared accounts. Commands that only change local state, like `delete`, refuse to run
+    #[arg(long, global = true)]
+    read_only_state: bool,
+
     /// Subcommands
     #[command(subcommand)]
     subcom: Option<Subcommands>,
 }
 
-// A file or URL to send to the URL host/shortener
+impl Cli {
+    /// Only the global options, for the uploads subcommands like `edit` make
+    fn globals(&self) -> Self {
+        Self {
+            json: self.json,
+            quiet: self.quiet,
+            host: self.host.clone(),
+            resolve: self.resolve.clone(),
+            unix_socket: self.unix_socket.clone(),
+            with: self.with.clone(),
+            silent: self.silent,
+            retries: self.retries,
+            retry_delay: self.retry_delay,
+            read_only_state: self.read_only_state,
+            ..Default::default()
+        }
+    }
+}
+
+/// A file or URL to send to the URL host/shortener
 #[derive(Clone, Debug)]
 enum Target {
     /// A local file path
     File(PathBuf),
     /// An external URL
     Url(Url),
+    /// A local directory, uploaded as an archive
+    Dir(PathBuf),
+    /// Data piped to stdin
+    Stdin,
+    /// A glob pattern and the paths it matched
+    Glob {
+        /// Pattern as given
+        pattern: String,
+        /// Matching files and directories
+        matches: Vec<Target>,
+    },
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::File(path) | Self::Dir(path) => {
+                write!(f, "{}", parse::strip_verbatim(&path.to_string_lossy()))
+            }
+            Self::Url(url) => write!(f, "{url}"),
+            Self::Stdin => write!(f, "-"),
+            Self::Glob { pattern, .. } => write!(f, "{pattern}"),
+        }
+    }
+}
+
+/// An existing upload to manage
+#[derive(Clone, Debug)]
+enum ManageTarget {
+    /// The upload's URL
+    Url(Url),
+    /// The upload's ID in the local history
+    Id(u64),
+    /// An alias for an upload in the local history
+    Alias(String),
+}
+
+/// Where the X-Token for `manage` came from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TokenSource {
+    /// Given on the command line
+    Argument,
+    /// Stored in the upload history
+    History,
+    /// Stored in the OS keyring
+    Keyring,
+}
+
+impl TokenSource {
+    /// Name used in `--json` output
+    fn name(self) -> &'static str {
+        match self {
+            Self::Argument => "argument",
+            Self::History => "history",
+            Self::Keyring => "keyring",
+        }
+    }
+}
+
+impl Display for TokenSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Argument => write!(f, "the command line"),
+            Self::History => write!(f, "the upload history"),
+            Self::Keyring => write!(f, "the keyring"),
+        }
+    }
 }
 
 /// CLI subcommands
 #[derive(Clone, Debug, Subcommand)]
-#[command(args_conflicts_with_subcommands = true)]
+#[allow(clippy::large_enum_variant)]
 enum Subcommands {
     /// Modify an existing submission
     Manage {
-        /// Existing envs.sh URL
-        #[arg(value_parser = EnvsUrlValueParser, value_hint = ValueHint::Url)]
-        url: Url,
+        /// Existing envs.sh URL, or `@ID` from `envsh list` or `@ALIAS` from `envsh alias`
+        #[arg(value_name = "URL|@ID", value_parser = ManageTargetValueParser, value_hint = ValueHint::Url)]
+        target: ManageTarget,
 
-        /// Secret X-Token to manage URL
-        token: String,
+        /// Secret X-Token to manage URL, if it isn't stored in the history
+        #[arg(value_parser = TokenValueParser)]
+        token: Option<Token>,
 
         /// Management options
         #[command(flatten)]
         options: ManageOpts,
+
+        /// Show the request that would be sent, and where its token comes from, without sending it
+        #[arg(long)]
+        dry_run: bool,
     },
     /// Generate shell completions
     ///
@@ -89,166 +488,2529 @@ enum Subcommands {
         /// The shell to generate completions for
         shell: Shell,
     },
+    /// Write a new file in $EDITOR, then upload it
+    Edit {
+        /// Print X-Token (and expiry date)
+        #[arg(short, long)]
+        display_secret: bool,
+
+        /// Make the resulting URL difficult to guess
+        #[arg(short = 'S', long)]
+        secret: bool,
+
+        /// Specify when the URL should expire: hours, epoch milliseconds, a duration (3d) or a date (2025-07-01)
+        #[arg(short, long, value_parser = ExpiryValueParser, value_name = "TIME")]
+        expires: Option<Expiry>,
+
+        /// Extension for the new file, so the editor and envs.sh recognize its type
+        #[arg(long, default_value = "txt")]
+        ext: String,
+
+        /// File name (and so extension) to upload as, instead of the temporary file's
+        #[arg(short, long, value_name = "NAME")]
+        name: Option<String>,
+    },
+    /// Upload the clipboard's image, or else its text
+    Paste {
+        /// Print X-Token (and expiry date)
+        #[arg(short, long)]
+        display_secret: bool,
+
+        /// Make the resulting URL difficult to guess
+        #[arg(short = 'S', long)]
+        secret: bool,
+
+        /// Specify when the URL should expire: hours, epoch milliseconds, a duration (3d) or a date (2025-07-01)
+        #[arg(short, long, value_parser = ExpiryValueParser, value_name = "TIME")]
+        expires: Option<Expiry>,
+
+        /// File name (and so extension) to upload as, instead of a random one with the
+        /// clipboard's extension
+        #[arg(short, long, value_name = "NAME")]
+        name: Option<String>,
+    },
+    /// Delete every upload in the local history matching the filters, after asking
+    ///
+    /// Only uploads with a stored X-Token that haven't expired yet are considered.
+    Delete {
+        /// Only delete uploads with this tag (repeatable, all must match)
+        #[arg(long, value_name = "TAG", required_unless_present = "older_than")]
+        tag: Vec<String>,
+
+        /// Only delete uploads made at least this long ago, e.g. 12h, 7d or 2w
+        #[arg(long, value_parser = DurationValueParser, value_name = "AGE")]
+        older_than: Option<Duration>,
+
+        /// Don't ask before deleting
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Write a printable PDF of QR codes for uploads in the local history, e.g. for handouts
+    ///
+    /// Each code is labelled with its history ID, target and URL. Expired uploads are left out.
+    QrSheet {
+        /// Only include uploads with this tag (repeatable, all must match)
+        #[arg(long, value_name = "TAG")]
+        tag: Vec<String>,
+
+        /// Codes per row; fewer make larger codes
+        #[arg(long, value_name = "N", default_value_t = 3, value_parser = clap::value_parser!(u8).range(1..=6))]
+        columns: u8,
+
+        /// PDF file to write, or `-` for stdout
+        #[arg(short, long, value_name = "FILE", default_value = "qr-sheet.pdf", value_hint = ValueHint::FilePath)]
+        output: PathBuf,
+    },
+    /// Measure upload throughput with generated data
+    ///
+    /// Each upload is secret, expires after an hour, and is deleted once measured.
+    Bench {
+        /// Size of each generated upload (e.g. 512K, 10M)
+        #[arg(short, long, value_parser = ByteSizeValueParser, default_value = "1M")]
+        size: u64,
+
+        /// Number of uploads
+        #[arg(short = 'n', long, default_value_t = 3)]
+        runs: u32,
+    },
+    /// List past uploads recorded in the local history
+    List {
+        /// Show the stored X-Tokens, reading them from the keyring where needed
+        #[arg(long)]
+        tokens: bool,
+    },
+    /// Show everything the local history records about an upload, including response headers
+    Info {
+        /// Upload's URL, `@ID` or `@ALIAS`
+        #[arg(value_name = "URL|@ID", value_parser = ManageTargetValueParser, value_hint = ValueHint::Url)]
+        target: ManageTarget,
+    },
+    /// Add X-Tokens for uploads made elsewhere to the local history, from `URL,TOKEN` lines
+    ///
+    /// Uploads already in the history get the imported token instead of their own. Blank lines,
+    /// `#` comments and a `url,token` header are skipped
+    ImportTokens {
+        /// CSV file with one `URL,TOKEN` per line
+        #[arg(value_hint = ValueHint::FilePath)]
+        file: PathBuf,
+
+        /// Store the tokens in the OS keyring instead of the plaintext upload history
+        #[arg(long)]
+        keyring: bool,
+    },
+    /// List, restore or purge history entries of deleted uploads
+    Trash {
+        /// What to do with the trash
+        #[command(subcommand)]
+        action: TrashAction,
+    },
+    /// Name uploads in the local history, to refer to them as `@NAME`
+    Alias {
+        /// What to do with aliases
+        #[command(subcommand)]
+        action: AliasAction,
+    },
+    /// Print the contents of an envs.sh URL to stdout
+    Cat {
+        /// Existing envs.sh URL
+        #[arg(value_parser = EnvsUrlValueParser, value_hint = ValueHint::Url)]
+        url: Url,
+        /// Decrypt a file uploaded with `--encrypt`, using the key in the URL's #fragment
+        #[arg(short, long)]
+        decrypt: bool,
+    },
+    /// Check a signature created with `--sign` against the file it was uploaded with
+    Verify {
+        /// envs.sh URL of the `.minisig` signature
+        #[arg(value_parser = EnvsUrlValueParser, value_hint = ValueHint::Url)]
+        url: Url,
+
+        /// Base64 minisign public key of the signer
+        #[arg(short = 'P', long, value_name = "PUBKEY")]
+        public_key: String,
+    },
+    /// Print version, build and environment details for bug reports
+    Env,
+    /// Print the effective configuration, from the config file and defaults
+    Config,
+    /// Install "Send to envsh" file manager integration
+    ///
+    /// Writes a Nautilus script, KDE service menu, or generic `.desktop` entry
+    /// depending on the detected desktop environment. Under Termux, installs a
+    /// share-sheet handler instead.
+    Integrate {
+        /// Integrate with the desktop file manager
+        #[arg(long, required = true)]
+        desktop: bool,
+    },
+}
+
+impl Subcommands {
+    /// URL that must belong to the configured host, if the subcommand takes one
+    fn envs_url(&self) -> Option<&Url> {
+        match self {
+            Self::Manage {
+                target: ManageTarget::Url(url),
+                ..
+            }
+            | Self::Info {
+                target: ManageTarget::Url(url),
+            }
+            | Self::Alias {
+                action:
+                    AliasAction::Add {
+                        target: ManageTarget::Url(url),
+                        ..
+                    },
+            }
+            | Self::Cat { url, .. }
+            | Self::Verify { url, .. } => Some(url),
+            _ => None,
+        }
+    }
+}
+
+/// `envsh alias` actions
+#[derive(Clone, Debug, Subcommand)]
+enum AliasAction {
+    /// Point an alias at an upload, replacing where it pointed before
+    Add {
+        /// Name to refer to the upload by
+        #[arg(value_parser = AliasValueParser)]
+        name: String,
+
+        /// Upload's URL, `@ID` or existing `@ALIAS`
+        #[arg(value_name = "URL|@ID", value_parser = ManageTargetValueParser)]
+        target: ManageTarget,
+    },
+    /// Remove an alias, leaving the upload itself alone
+    Rm {
+        /// Alias to remove
+        #[arg(value_parser = AliasValueParser)]
+        name: String,
+    },
+    /// List aliases and the uploads they point at
+    List,
+}
+
+/// `envsh trash` actions
+#[derive(Clone, Debug, Subcommand)]
+enum TrashAction {
+    /// List deleted uploads whose history entries are in the trash
+    List,
+    /// Move an entry back into the history, under its old ID
+    Restore {
+        /// `@ID` of the entry, from `envsh trash list`
+        #[arg(value_name = "@ID", value_parser = HistoryIdValueParser)]
+        id: u64,
+    },
+    /// Drop entries from the trash for good, with their X-Tokens in the keyring
+    Purge {
+        /// `@ID`s of the entries to drop [default: all of them]
+        #[arg(value_name = "@ID", value_parser = HistoryIdValueParser)]
+        ids: Vec<u64>,
+
+        /// Don't ask before purging the whole trash
+        #[arg(short, long)]
+        yes: bool,
+    },
 }
 
 /// Determine management action to be taken
 #[derive(Clone, Debug, Args)]
 #[group(required = true)]
 struct ManageOpts {
-    /// Specify when the URL should expire, in hours or epoch milliseconds
+    /// Specify when the URL should expire: hours, epoch milliseconds, a duration (3d) or a date (2025-07-01)
     #[arg(short, long, value_parser = ExpiryValueParser)]
     expires: Option<Expiry>,
 
+    /// Expire at the next local weekday and/or time, e.g. "monday 09:00" or "tomorrow"
+    #[arg(long, value_parser = ExpiresAtValueParser, value_name = "WHEN")]
+    expires_at: Option<Expiry>,
+
     /// Delete the shared URL immediately (requires `token`)
     #[arg(short, long)]
     delete: bool,
 }
 
-/// The time at which a URL will expire
-#[derive(Clone, Debug)]
-enum Expiry {
-    /// Delete in X hours
-    Hours(i64),
-    /// Delete at provided [`Timestamp`]
-    Timestamp(Timestamp),
-}
-
-impl Expiry {
-    /// Maximum number of hours a file will be hosted
-    const MAX_EXPIRY_HOURS: i64 = 24 * 30;
-}
-
-impl Display for Expiry {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::Hours(h) => h.fmt(f),
-            Self::Timestamp(ts) => ts.as_millisecond().fmt(f),
+/// Parse command-line arguments, rejecting upload options given along with a subcommand
+///
+/// Global options may come before or after the subcommand.
+fn parse_args<I, T>(args: I) -> std::result::Result<Cli, clap::Error>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let mut cmd = Cli::command();
+    let matches = cmd.try_get_matches_from_mut(args)?;
+    if let Some((name, _)) = matches.subcommand() {
+        let upload_arg = cmd.get_arguments().find(|arg| {
+            !arg.is_global_set()
+                && matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine)
+        });
+        if let Some(arg) = upload_arg {
+            return Err(cmd.error(
+                ErrorKind::ArgumentConflict,
+                format!("the argument '{arg}' cannot be used with the '{name}' subcommand"),
+            ));
         }
     }
+    Cli::from_arg_matches(&matches).map_err(|e| e.with_cmd(&cmd))
 }
 
 /// The main program
 fn main() {
-    let args = Cli::parse();
+    // The completion scripts' helper, kept out of the parser so completions don't offer it
+    if env::args_os()
+        .nth(1)
+        .is_some_and(|arg| arg == "complete-stored")
+    {
+        let words: Vec<_> = env::args_os()
+            .skip(2)
+            .skip_while(|arg| arg == "--")
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        return complete_stored(&words);
+    }
+
+    let mut args = parse_args(env::args_os()).unwrap_or_else(|e| e.exit());
+    if let Err(e) = config::load(
+        args.host.take(),
+        mem::take(&mut args.resolve),
+        args.unix_socket.take(),
+        args.read_only_state,
+        &args.with,
+    ) {
+        e.exit(args.json)
+    }
+    let json = (args.json || config::get().output.json) && !args.quiet;
+    if let Some(url) = args.subcom.as_ref().and_then(Subcommands::envs_url) {
+        cli::check_host(url, &config::get().host(), &Cli::command()).unwrap_or_else(|e| e.exit());
+    }
+
+    let chime = !args.silent
+        && matches!(
+            args.subcom,
+            None | Some(
+                Subcommands::Manage { .. }
+                    | Subcommands::Delete { .. }
+                    | Subcommands::Edit { .. }
+                    | Subcommands::Paste { .. }
+            )
+        );
+    let remind = !args.quiet && !matches!(args.subcom, Some(Subcommands::Completion { .. }));
+    let result = run(args);
+    if chime {
+        sound::play(result.is_ok());
+    }
+    if remind {
+        notice::expiring_soon();
+    }
+    if let Err(e) = result {
+        e.exit(json)
+    }
+}
 
+/// Run the chosen subcommand, or upload
+fn run(args: Cli) -> Result<()> {
+    let globals = args.globals();
     match args.subcom {
         Some(Subcommands::Manage {
-            url,
+            target,
             token,
             options,
-        }) => manage_url(url, token, options),
-        Some(Subcommands::Completion { shell }) => generate_shell_completion(shell),
+            dry_run,
+        }) => {
+            let (url, token, source) = resolve_manage_target(target, token)?;
+            let retry = Retry::new(args.retries, args.retry_delay);
+            manage_url(
+                url,
+                token,
+                source,
+                options,
+                retry,
+                dry_run,
+                args.json || config::get().output.json,
+                args.quiet,
+            )
+        }
+        Some(Subcommands::Completion { shell }) => {
+            generate_shell_completion(shell);
+            Ok(())
+        }
+        Some(Subcommands::Edit {
+            display_secret,
+            secret,
+            expires,
+            ext,
+            name,
+        }) => edit_and_upload(&ext, move |file| Cli {
+            target: vec![Target::File(file)],
+            display_secret,
+            secret,
+            expires,
+            name,
+            ..globals
+        }),
+        Some(Subcommands::Paste {
+            display_secret,
+            secret,
+            expires,
+            name,
+        }) => paste_and_upload(move |file| Cli {
+            target: vec![Target::File(file)],
+            display_secret,
+            secret,
+            expires,
+            name,
+            ..globals
+        }),
+        Some(Subcommands::Delete {
+            tag,
+            older_than,
+            yes,
+        }) => delete_uploads(
+            &tag,
+            older_than,
+            yes,
+            Retry::new(args.retries, args.retry_delay),
+            args.json || config::get().output.json,
+            args.quiet,
+        ),
+        Some(Subcommands::QrSheet {
+            tag,
+            columns,
+            output,
+        }) => write_qr_sheet(&tag, columns.into(), &output),
+        Some(Subcommands::Bench { size, runs }) => bench::run(size, runs),
+        Some(Subcommands::Cat { url, decrypt }) => cat_url(url, decrypt),
+        Some(Subcommands::List { tokens }) => {
+            list_uploads(args.json || config::get().output.json, tokens)
+        }
+        Some(Subcommands::Info { target }) => {
+            show_info(&target, args.json || config::get().output.json)
+        }
+        Some(Subcommands::ImportTokens { file, keyring }) => {
+            import::import_tokens(&file, keyring || config::get().keyring, args.quiet)
+        }
+        Some(Subcommands::Trash { action }) => match action {
+            TrashAction::List => trash::list(args.json || config::get().output.json),
+            TrashAction::Restore { id } => trash::restore(id),
+            TrashAction::Purge { ids, yes } => trash::purge(&ids, yes),
+        },
+        Some(Subcommands::Alias { action }) => match action {
+            AliasAction::Add { name, target } => alias::add(name, &target),
+            AliasAction::Rm { name } => alias::remove(&name),
+            AliasAction::List => alias::list(args.json || config::get().output.json),
+        },
+        Some(Subcommands::Verify { url, public_key }) => sign::verify_url(url, &public_key),
+        Some(Subcommands::Env) => {
+            report::print_environment();
+            Ok(())
+        }
+        Some(Subcommands::Config) => config::print_effective(),
+        Some(Subcommands::Integrate { .. }) => integrate::install_desktop_integration(),
         None => create_url(args),
     }
 }
 
-/// Create a new URL
-fn create_url(args: Cli) {
-    let create_form = [
-        // Build parts for form
-        match (args.target.unwrap(), args.shorten, args.expires.is_some()) {
-            (Target::Url(url), false, false) => Some(("url", Part::text(url.to_string()))),
-            (Target::Url(url), true, false) => Some(("shorten", Part::text(url.to_string()))),
-            (Target::File(f), false, _) => {
-                Some(("file", Part::file(f).expect("failed to load file")))
+/// Create a new URL for each target, failing if any upload fails
+fn create_url(mut args: Cli) -> Result<()> {
+    let mut targets = std::mem::take(&mut args.target);
+    if let Some(list) = &args.files_from {
+        if list == Path::new("-") && targets.iter().any(|t| matches!(t, Target::Stdin)) {
+            return Err(EnvshError::Usage(
+                "stdin (-) cannot be both a target and the target list".to_string(),
+            ));
+        }
+        targets.extend(read_target_list(list, args.null)?);
+    }
+    let targets: Vec<_> = targets
+        .into_iter()
+        .flat_map(|target| match target {
+            Target::Glob { matches, .. } => matches,
+            target => vec![target],
+        })
+        .collect();
+    if targets
+        .iter()
+        .filter(|t| matches!(t, Target::Stdin))
+        .count()
+        > 1
+    {
+        return Err(EnvshError::Usage(
+            "stdin (-) can only be uploaded once".to_string(),
+        ));
+    }
+    args.expires = args.expires.take().or(args.expires_at.take());
+    args.json = (args.json || config::get().output.json) && !args.quiet;
+
+    let batch = targets.len() > 1;
+    // Repeats, e.g. from overlapping globs, would only send the same thing again
+    let mut seen = HashSet::new();
+    let given = targets.len();
+    let targets: Vec<_> = targets
+        .into_iter()
+        .filter(|target| {
+            let key = match target {
+                Target::File(path) | Target::Dir(path) => fs::canonicalize(path)
+                    .map_or_else(|_| target.to_string(), |path| path.display().to_string()),
+                target => target.to_string(),
+            };
+            let first = seen.insert(key);
+            if !first {
+                note(
+                    args.json || args.quiet,
+                    format!("{target} → skipped, it was given before"),
+                );
             }
-            (Target::Url(url), _, true) => panic!("--expires cannot be used with URL {url}"),
-            (Target::File(f), true, _) => {
-                panic!("--shorten cannot be used with file path {}", f.display())
+            first
+        })
+        .collect();
+    let repeated = given - targets.len();
+    let total = targets.len();
+    // Shared so a batch of small files reuses one connection instead of reconnecting for each
+    let envs = EnvsClient::with_http_client(config::get().client()).with_host(config::get().host());
+    let throttle = Mutex::new(Throttle::new(args.spread));
+    let receipts = receipt::Writer::load()?;
+    let jobs = args.jobs.map_or(1, NonZeroUsize::get).min(total);
+    let results = if jobs > 1 {
+        upload_parallel(&envs, &args, targets, jobs, &throttle, receipts.as_ref())?
+    } else {
+        let mut results = vec![];
+        for target in targets {
+            let label = batch.then(|| target.to_string());
+            if let Some(job) = prepare_upload(&envs, &args, target, label)? {
+                let sent = send_upload(&envs, &args, &job, &throttle, true)?;
+                results.push(report_upload(&envs, &args, job, sent, receipts.as_ref())?);
             }
-        },
-        args.secret.then_some(("secret", Part::text(""))),
-        args.expires
-            .map(|time| ("expires", Part::text(time.to_string()))),
-    ]
-    .into_iter()
-    .flatten()
-    // Assemble form
-    .fold(Form::new(), |form, (name, value)| form.part(name, value));
-
-    let create_resp = Client::new()
-        .post(ENVS)
-        .multipart(create_form)
-        .send()
-        .unwrap();
+        }
+        results
+    };
+    let urls: Vec<_> = results.iter().filter_map(Outcome::url).collect();
+    let failed = results.len() - urls.len();
+    if batch {
+        let count = |kind: fn(&Outcome) -> bool| results.iter().filter(|o| kind(o)).count();
+        let summary = json::Summary {
+            uploaded: count(|o| matches!(o, Outcome::Uploaded(_))),
+            local_duplicates: repeated + count(|o| matches!(o, Outcome::Reused(_))),
+            server_duplicates: count(|o| matches!(o, Outcome::Duplicate(_))),
+            failed,
+        };
+        if args.json {
+            json::print(&json::Batch { summary });
+        } else {
+            note(
+                args.quiet || args.format.is_some(),
+                format!(
+                    "{} uploaded, {} skipped as local duplicate(s), {} already on the host, {} \
+                    failed",
+                    summary.uploaded,
+                    summary.local_duplicates,
+                    summary.server_duplicates,
+                    summary.failed
+                ),
+            );
+        }
+    }
 
-    let (expires, token) = if args.display_secret {
-        let headers = create_resp.headers();
-        let expires_value = headers.get("X-Expires").and_then(|exp| {
-            Timestamp::from_millisecond(f64::from_str(exp.to_str().unwrap()).unwrap() as i64)
-                .map(|ts| ts.to_zoned(TimeZone::system()))
-                .ok()
-        });
+    if (args.copy || config::get().copy) && !urls.is_empty() {
+        match clipboard::copy(&urls.join("\n")) {
+            Ok(()) if urls.len() == 1 => {
+                note(args.json || args.quiet, "Copied the URL to the clipboard")
+            }
+            Ok(()) => note(
+                args.json || args.quiet,
+                format!("Copied {} URLs to the clipboard", urls.len()),
+            ),
+            Err(e) => eprintln!("Warning: failed to copy to the clipboard: {e}"),
+        }
+    }
+
+    match failed {
+        0 => Ok(()),
+        // Each failure was already reported, so there is nothing more to say about one
+        _ if !batch => Err(EnvshError::Failed(String::new())),
+        _ => Err(EnvshError::Failed(format!(
+            "{failed} of {total} uploads failed"
+        ))),
+    }
+}
+
+/// How URLs printed with `--quiet` end
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Ending {
+    /// Each on its own line
+    Newline,
+    /// Each followed by a NUL byte, with `--print0`
+    Nul,
+    /// Separated by newlines, but without one after the last, with `--no-newline`
+    Separated,
+}
+
+/// `url` as printed with `--quiet`, `first` if no URL was printed before it
+fn quiet_url(url: &str, ending: Ending, first: bool) -> String {
+    match ending {
+        Ending::Newline => format!("{url}\n"),
+        Ending::Nul => format!("{url}\0"),
+        Ending::Separated if first => url.to_string(),
+        Ending::Separated => format!("\n{url}"),
+    }
+}
 
-        let token_value = headers
-            .get("X-Token")
-            .and_then(|t| t.to_str().map(ToString::to_string).ok());
+/// Print `url` to stdout, as the only output of a `--quiet` upload
+fn print_quiet_url(args: &Cli, url: &str) {
+    /// Whether a URL was printed already, which the ones after it are separated from
+    static PRINTED: AtomicBool = AtomicBool::new(false);
 
-        (expires_value, token_value)
+    let ending = if args.print0 {
+        Ending::Nul
+    } else if args.no_newline {
+        Ending::Separated
     } else {
-        (None, None)
+        Ending::Newline
     };
+    let first = !PRINTED.swap(true, Ordering::Relaxed);
+    let mut stdout = io::stdout().lock();
+    // Without a newline the URL would sit in stdout's buffer until exit
+    let _ = stdout
+        .write_all(quiet_url(url, ending, first).as_bytes())
+        .and_then(|()| stdout.flush());
+}
 
-    if create_resp.status().is_success() {
-        print!("Succesful! ")
-    } else {
-        print!("[{}] ", create_resp.status().as_u16())
-    }
-    println!("{}", create_resp.text().unwrap().trim());
-    if let Some(exp) = expires {
-        println!("Expires at {}", exp.strftime("%F (%A), %T%.f [%:Q]"))
-    }
-    if let Some(t) = token {
-        println!("X-Token: {t}")
+/// Upload `targets` on `jobs` threads, reporting results in input order and returning what
+/// became of each
+///
+/// Every target is prepared before any is sent, so preview prompts and usage errors come first.
+fn upload_parallel(
+    envs: &EnvsClient,
+    args: &Cli,
+    targets: Vec<Target>,
+    jobs: usize,
+    throttle: &Mutex<Throttle>,
+    receipts: Option<&receipt::Writer>,
+) -> Result<Vec<Outcome>> {
+    let (queue_tx, queue) = mpsc::channel();
+    for (i, target) in targets.into_iter().enumerate() {
+        let label = Some(target.to_string());
+        queue_tx
+            .send((i, prepare_upload(envs, args, target, label)?))
+            .unwrap();
     }
-}
+    drop(queue_tx);
+    let queue = Mutex::new(queue);
+    let stop = AtomicBool::new(false);
 
-/// Modify an existing URL
-fn manage_url(url: Url, token: String, options: ManageOpts) {
-    let manage_form = [
-        ("token", Part::text(token)),
-        if options.delete {
-            ("delete", Part::text(""))
-        } else {
-            ("expires", Part::text(options.expires.unwrap().to_string()))
-        },
-    ]
-    .into_iter()
-    .fold(Form::new(), |form, (name, value)| form.part(name, value));
+    thread::scope(|scope| {
+        let (done_tx, done) = mpsc::channel();
+        for _ in 0..jobs {
+            let (queue, stop, done_tx) = (&queue, &stop, done_tx.clone());
+            scope.spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let next = queue.lock().unwrap().recv();
+                    let Ok((i, job)) = next else { break };
+                    // Each bar would redraw over the others, so parallel uploads show none
+                    let sent = match &job {
+                        Some(job) => send_upload(envs, args, job, throttle, false),
+                        None => Ok(None),
+                    };
+                    if done_tx.send((i, job, sent)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(done_tx);
 
-    let manage_resp = Client::new()
-        .post(url)
-        .multipart(manage_form)
-        .send()
-        .unwrap();
+        // Uploads finish in any order, so each result waits for the ones before it
+        let mut pending = BTreeMap::new();
+        let mut next = 0;
+        let mut results = vec![];
+        for (i, job, sent) in done {
+            pending.insert(i, (job, sent));
+            while let Some((job, sent)) = pending.remove(&next) {
+                next += 1;
+                let Some(job) = job else { continue };
+                match sent.and_then(|sent| report_upload(envs, args, job, sent, receipts)) {
+                    Ok(outcome) => results.push(outcome),
+                    Err(e) => {
+                        // Uploads already under way are left to finish, but no more are started
+                        stop.store(true, Ordering::Relaxed);
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        Ok(results)
+    })
+}
 
-    if manage_resp.status().is_success() {
-        println!("Change accepted!")
+/// Read and validate the targets listed in `list`, or stdin if it is `-`
+fn read_target_list(list: &Path, null: bool) -> Result<Vec<Target>> {
+    let contents = if list == Path::new("-") {
+        io::read_to_string(io::stdin())
     } else {
-        println!(
-            "[{}] {}",
-            manage_resp.status().as_u16(),
-            manage_resp.text().unwrap()
-        )
+        fs::read_to_string(list)
+    }
+    .context(format!("failed to read target list {}", list.display()))?;
+
+    parse::target_list(&contents, null)
+        .map(|entry| match parse::target(entry) {
+            Ok(Target::Stdin) => Err(EnvshError::Usage(
+                "target lists cannot contain stdin (-)".to_string(),
+            )),
+            Ok(target) => Ok(target),
+            Err(e) => Err(EnvshError::Usage(format!(
+                "invalid target {entry:?} in target list: {e}"
+            ))),
+        })
+        .collect()
+}
+
+/// What became of a target
+#[derive(Debug)]
+enum Outcome {
+    /// Sent and accepted as a new upload, with its URL
+    Uploaded(String),
+    /// Sent and accepted, but the host already had it under this URL
+    Duplicate(String),
+    /// Not sent, reusing this earlier short link from the history
+    Reused(String),
+    /// Rejected, or couldn't be sent
+    Failed,
+}
+
+impl Outcome {
+    /// Resulting URL, unless the upload failed
+    fn url(&self) -> Option<&str> {
+        match self {
+            Self::Uploaded(url) | Self::Duplicate(url) | Self::Reused(url) => Some(url),
+            Self::Failed => None,
+        }
     }
 }
 
-/// Generate shell completions, adding options for Fish
-fn generate_shell_completion(shell: Shell) {
-    let mut completion: Vec<u8> = vec![];
-    clap_complete::generate(shell, &mut Cli::command(), "envsh", &mut completion);
+/// A target that passed every check and is ready to send
+///
+/// Its result lines are prefixed with `label` in a batch.
+struct Job {
+    /// What to upload or submit
+    target: Target,
+    /// Target as given on the command line
+    input: String,
+    /// Name of the target in a batch
+    label: Option<String>,
+    /// Prefix for result lines, empty outside a batch
+    prefix: String,
+    /// Problems found so far
+    warnings: Warnings,
+    /// Whether to ask for a hard-to-guess URL
+    secret: bool,
+    /// Requested expiry
+    expiry: Option<Expiry>,
+    /// Archive of a directory target, and its file name
+    packed: Option<(TempFile, String)>,
+    /// Signs the file once it is uploaded, for `--sign`
+    signer: Option<Signer>,
+    /// Destination, if this shortens a URL
+    shortened: Option<Url>,
+    /// A short link to the same destination created earlier, reused instead of sending anything
+    cached: Option<history::Entry>,
+}
 
-    // Append fish options for generate subcommand
-    if shell == Shell::Fish {
-        completion.extend(
-            b"\
-            complete -c envsh -n \"__fish_envsh_using_subcommand completion\" -f -a 'bash'\n\
-            complete -c envsh -n \"__fish_envsh_using_subcommand completion\" -f -a 'elvish'\n\
-            complete -c envsh -n \"__fish_envsh_using_subcommand completion\" -f -a 'fish'\n\
-            complete -c envsh -n \"__fish_envsh_using_subcommand completion\" -f -a 'powershell'\n\
-            complete -c envsh -n \"__fish_envsh_using_subcommand completion\" -f -a 'zsh'",
-        )
+/// What sending a [`Job`] produced
+struct Sent {
+    /// The host's response, or why there wasn't one
+    result: reqwest::Result<Created>,
+    /// Transforms from the last attempt
+    pipeline: Pipeline,
+    /// Size and hash of the bytes sent
+    tally: Tally,
+}
+
+/// Check a single target and get it ready to send, or `None` if the user cancelled it
+///
+/// Errors are for problems that should stop a batch.
+fn prepare_upload(
+    envs: &EnvsClient,
+    args: &Cli,
+    mut target: Target,
+    label: Option<String>,
+) -> Result<Option<Job>> {
+    let prefix = label
+        .as_deref()
+        .map(|l| format!("{l} → "))
+        .unwrap_or_default();
+    let mut warnings = Warnings::new(args.json, &prefix);
+    let input = target.to_string();
+    let mut secret = args.secret || config::get().secret;
+    // The configured expiry only applies to files, since envs.sh rejects it for URLs
+    let expiry = args.expires.clone().or_else(|| {
+        (args.fetch || !matches!(target, Target::Url(_)))
+            .then(|| config::get().expires())
+            .flatten()
+    });
+
+    if let Target::Url(url) = &mut target
+        && args.clean_url
+    {
+        clean_url(url)
+    }
+    if let Target::Url(url) = &target
+        && args.encrypt
+        && !args.fetch
+    {
+        return Err(EnvshError::Usage(format!(
+            "--encrypt cannot be used with URL {url}, use --fetch to upload its contents"
+        )));
+    }
+    if let Target::Url(url) = &target
+        && !args.fetch
+        && (args.content_type.is_some() || args.ext.is_some())
+    {
+        return Err(EnvshError::Usage(format!(
+            "--content-type and --ext cannot be used with URL {url}, use --fetch to upload its contents"
+        )));
+    }
+    if let Target::Url(url) = &target
+        && !args.fetch
+        && !args.allow_private
+        && is_private_url(url)
+    {
+        return Err(EnvshError::Usage(format!(
+            "{url} is on a private network, use --fetch to upload it or --allow-private to submit it anyway"
+        )));
     }
 
-    io::stdout().write_all(&completion).unwrap()
+    check_combination(&target, args, expiry.is_some())?;
+    match target {
+        Target::Stdin if args.preview.is_some() => {
+            return Err(EnvshError::Usage(
+                "--preview cannot be used with stdin".to_string(),
+            ));
+        }
+        Target::Dir(_) if args.preview.is_some() => {
+            return Err(EnvshError::Usage(
+                "--preview cannot be used with directories".to_string(),
+            ));
+        }
+        _ => {}
+    }
+    let packed = match (&target, args.archive) {
+        (Target::Dir(dir), Some(format)) => {
+            let (archive, name) = archive::pack(dir, format, !args.no_follow_symlinks)
+                .context(format!("failed to pack {}", dir.display()))?;
+            note(
+                args.json || args.quiet,
+                format!("{prefix}Packed {} into {name}", dir.display()),
+            );
+            Some((archive, name))
+        }
+        (Target::Dir(dir), None) => {
+            return Err(EnvshError::Usage(format!(
+                "{} is a directory, use --archive tar.gz or --archive tar.zst to upload it",
+                dir.display()
+            )));
+        }
+        _ => None,
+    };
+    if let Target::File(f) = &target
+        && args.no_follow_symlinks
+        && f.is_symlink()
+    {
+        return Err(EnvshError::Usage(format!(
+            "{} is a symlink, pass --follow-symlinks to upload the file it points to",
+            f.display()
+        )));
+    }
+    if let (Some(lines), Target::File(f)) = (args.preview, &target)
+        && !preview::confirm_upload(f, lines)?
+    {
+        note(args.json || args.quiet, format!("{prefix}Upload cancelled"));
+        return Ok(None);
+    }
+
+    if let Target::File(f) = &target
+        && let Some((action, reason)) = sensitive::check(f)
+    {
+        match action {
+            sensitive::Action::Refuse if !args.allow_sensitive => {
+                return Err(EnvshError::Usage(format!(
+                    "{} looks like {reason}, pass --allow-sensitive to upload it anyway",
+                    f.display()
+                )));
+            }
+            sensitive::Action::Refuse => warnings.warn(format!(
+                "{} looks like {reason}, uploading anyway because of --allow-sensitive",
+                f.display()
+            )),
+            sensitive::Action::Secret if !secret => {
+                warnings.warn(format!(
+                    "{} looks like {reason}, uploading with --secret",
+                    f.display()
+                ));
+                secret = true
+            }
+            sensitive::Action::Secret => {}
+        }
+    }
+
+    let signer = match (&args.sign, &target) {
+        (Some(seckey), Target::File(f)) => Some(Signer::load(seckey, f)?),
+        (Some(_), _) => {
+            return Err(EnvshError::Usage(
+                "--sign can only be used with file paths".to_string(),
+            ));
+        }
+        (None, _) => None,
+    };
+
+    let shortened = match &target {
+        Target::Url(url) if args.shorten => Some(url.clone()),
+        _ => None,
+    };
+    let cached = shortened
+        .as_ref()
+        .and_then(|destination| history::find_short(destination, envs.host()));
+
+    Ok(Some(Job {
+        target,
+        input,
+        label,
+        prefix,
+        warnings,
+        secret,
+        expiry,
+        packed,
+        signer,
+        shortened,
+        cached,
+    }))
+}
+
+/// Check that `target` can be sent the way `args` ask, with an expiry if `expires`
+fn check_combination(target: &Target, args: &Cli, expires: bool) -> Result<()> {
+    match (target, args.shorten, args.fetch, expires) {
+        // Fetched URLs are uploaded as files, which take everything files do
+        (Target::Url(_), _, true, _) => Ok(()),
+        (Target::Url(url), _, _, true) => Err(EnvshError::Usage(format!(
+            "--expires cannot be used with URL {url}"
+        ))),
+        (Target::Stdin, true, _, _) => Err(EnvshError::Usage(
+            "--shorten cannot be used with stdin".to_string(),
+        )),
+        (Target::Stdin, _, true, _) => Err(EnvshError::Usage(
+            "--fetch cannot be used with stdin".to_string(),
+        )),
+        (Target::Dir(d), true, _, _) => Err(EnvshError::Usage(format!(
+            "--shorten cannot be used with directory {}",
+            d.display()
+        ))),
+        (Target::Dir(d), _, true, _) => Err(EnvshError::Usage(format!(
+            "--fetch cannot be used with directory {}",
+            d.display()
+        ))),
+        (Target::File(f), true, _, _) => Err(EnvshError::Usage(format!(
+            "--shorten cannot be used with file path {}",
+            f.display()
+        ))),
+        (Target::File(f), _, true, _) => Err(EnvshError::Usage(format!(
+            "--fetch cannot be used with file path {}",
+            f.display()
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Send `job` once the throttle allows it, retrying as configured, or `None` if it reuses an
+/// earlier short link
+fn send_upload(
+    envs: &EnvsClient,
+    args: &Cli,
+    job: &Job,
+    throttle: &Mutex<Throttle>,
+    show_progress: bool,
+) -> Result<Option<Sent>> {
+    if job.cached.is_some() {
+        return Ok(None);
+    }
+    let Job {
+        target,
+        secret,
+        expiry,
+        packed,
+        ..
+    } = job;
+
+    let transforms = [
+        args.strip_metadata.then_some(Transform::StripMetadata),
+        args.redact.then_some(Transform::Redact),
+        args.compress.then_some(Transform::Compress),
+        args.encrypt.then_some(Transform::Encrypt),
+    ];
+    // Rebuilt for each attempt, so its counts describe what was finally sent
+    let mut pipeline = Pipeline::default();
+    let tally = Tally::default();
+    let progress = Progress::new(show_progress && !args.json && !args.quiet);
+    let retry = Retry::new(args.retries, args.retry_delay);
+    let replayable = !matches!(target, Target::Stdin);
+
+    throttle.lock().unwrap().wait(args.json || args.quiet);
+    let result = retry.send(
+        &format!("{}Upload", job.prefix),
+        replayable,
+        args.json || args.quiet,
+        |_| {
+            pipeline = Pipeline::new(transforms.into_iter().flatten())
+                .retyped(args.content_type.clone(), args.ext.clone());
+            let content = match (target, args.shorten, args.fetch, expiry.is_some()) {
+                (Target::Glob { .. }, ..) => unreachable!("globs are expanded before uploading"),
+                (Target::Url(url), _, true, _) => Content::File(fetch_part(
+                    &config::get().remote_client(),
+                    url.clone(),
+                    args.name.clone(),
+                    args.remote_name,
+                    &pipeline,
+                    &tally,
+                    &progress,
+                )?),
+                (Target::Url(url), false, _, false) => Content::Url(url.clone()),
+                (Target::Url(url), true, _, false) => Content::Shorten(url.clone()),
+                (Target::File(f), false, false, _) => Content::File(file_part(
+                    f,
+                    args.name.clone(),
+                    args.buffer_size.unwrap_or(DEFAULT_BUFFER_SIZE),
+                    &pipeline,
+                    &tally,
+                    &progress,
+                )?),
+                (Target::Dir(_), false, false, _) => {
+                    let (archive, name) = packed.as_ref().unwrap();
+                    Content::File(file_part(
+                        archive.path(),
+                        Some(args.name.as_ref().unwrap_or(name).clone()),
+                        args.buffer_size.unwrap_or(DEFAULT_BUFFER_SIZE),
+                        &pipeline,
+                        &tally,
+                        &progress,
+                    )?)
+                }
+                (Target::Stdin, false, false, _) => Content::File(stdin_part(
+                    args.name.as_deref().unwrap_or("stdin.txt"),
+                    &pipeline,
+                    &tally,
+                    &progress,
+                )?),
+                _ => unreachable!("prepare_upload rejects other combinations"),
+            };
+            let created = envs.upload(Upload {
+                content,
+                secret: *secret,
+                expires: expiry.clone(),
+            });
+            progress.finish();
+            Ok(created)
+        },
+    )?;
+    if let Ok(created) = &result {
+        throttle.lock().unwrap().update(created.rate_limit.as_ref());
+    }
+
+    Ok(Some(Sent {
+        result,
+        pipeline,
+        tally,
+    }))
+}
+
+/// Record and print the result of `job`, returning what became of it
+///
+/// Accepted uploads also get a receipt if `receipts` are enabled.
+///
+/// Rejected and failed uploads are reported here; errors are for problems that should stop a
+/// batch.
+fn report_upload(
+    envs: &EnvsClient,
+    args: &Cli,
+    job: Job,
+    sent: Option<Sent>,
+    receipts: Option<&receipt::Writer>,
+) -> Result<Outcome> {
+    let Job {
+        input,
+        label,
+        prefix,
+        mut warnings,
+        secret,
+        expiry,
+        signer,
+        shortened,
+        cached,
+        ..
+    } = job;
+
+    let Some(Sent {
+        result,
+        pipeline,
+        tally,
+    }) = sent
+    else {
+        let cached = cached.expect("only reused short links are not sent");
+        if args.json {
+            json::print(&json::Upload {
+                input,
+                status: None,
+                url: Some(cached.url.clone()),
+                error: None,
+                code: None,
+                token: None,
+                expires: None,
+                rate_limit: None,
+                warnings: warnings.list,
+            });
+        } else if let Some(format) = &args.format {
+            println!(
+                "{}",
+                format.render(&template::Fields {
+                    url: &cached.url,
+                    token: None,
+                    input: &input,
+                    expires: None,
+                })
+            );
+        } else if args.quiet {
+            print_quiet_url(args, &cached.url);
+        } else {
+            print!("{prefix}");
+            if label.is_none() {
+                print!("Succesful! ")
+            }
+            println!("{} (shortened before, reusing it)", cached.url);
+        }
+        if args.qr && !args.json {
+            print_qr(&cached.url)
+        }
+        if args.open {
+            open_url(&cached.url)
+        }
+        return Ok(Outcome::Reused(cached.url));
+    };
+
+    let created = match result {
+        Ok(created) => created,
+        Err(e) if args.json => {
+            json::print(&json::Upload {
+                input,
+                status: None,
+                url: None,
+                error: Some(e.to_string()),
+                code: Some(error::request_code(&e)),
+                token: None,
+                expires: None,
+                rate_limit: None,
+                warnings: warnings.list,
+            });
+            return Ok(Outcome::Failed);
+        }
+        Err(e) if args.quiet => {
+            eprintln!("{prefix}Upload failed: {e}");
+            return Ok(Outcome::Failed);
+        }
+        Err(e) => {
+            println!("{prefix}Upload failed: {e}");
+            return Ok(Outcome::Failed);
+        }
+    };
+
+    if created.is_success() {
+        let (size, sha256) = tally.finish().unzip();
+        let url = created.body.trim().to_string();
+        let now = Timestamp::now();
+        if let Some(receipts) = receipts {
+            let written = receipts.write(&receipt::Receipt {
+                url: &url,
+                input: &input,
+                host: envs.host().as_str(),
+                size,
+                sha256: sha256.as_deref(),
+                created: now,
+            });
+            if let Err(e) = written {
+                warnings.warn(format!("failed to write receipt: {e}"))
+            }
+        }
+        // With --keyring, a token the keyring couldn't take is dropped rather than kept in plaintext
+        let use_keyring = args.keyring || config::get().keyring;
+        let in_keyring = use_keyring
+            && !config::get().read_only_state
+            && created.token.as_ref().is_some_and(|t| {
+                keyring::store(&url, t)
+                    .inspect_err(|e| {
+                        warnings.warn(format!("failed to store X-Token in the keyring: {e}"))
+                    })
+                    .is_ok()
+            });
+        let recorded = history::record(history::Entry {
+            id: 0,
+            token: created
+                .token
+                .as_ref()
+                .filter(|_| !use_keyring)
+                .map(|t| t.expose().to_string()),
+            keyring: in_keyring,
+            url,
+            expires: created.expires,
+            input: input.clone(),
+            shortened: shortened.is_some(),
+            size,
+            sha256,
+            tags: args.tag.clone(),
+            created: now,
+            headers: created.headers.clone(),
+        });
+        if let Err(e) = recorded {
+            warnings.warn(e.to_string())
+        }
+    }
+
+    // JSON output always includes the expiry, which isn't secret
+    let expires = created
+        .expires
+        .filter(|_| args.display_secret || args.json)
+        .map(|ts| ts.to_zoned(TimeZone::system()));
+    let token = created.token.clone().filter(|_| args.display_secret);
+
+    let success = created.is_success();
+    let status = created.status;
+    let body = created.body;
+    // The key goes in the fragment, which browsers and `envsh cat` never send to the host
+    let shown = match pipeline.key().filter(|_| success) {
+        Some(key) => format!("{}#{key}", body.trim()),
+        None => body.trim().to_string(),
+    };
+
+    // Reported after the result line, or inside it for JSON
+    let late_warnings = [
+        shortened
+            .as_ref()
+            .filter(|_| success)
+            .and_then(|destination| check_short_url(body.trim(), destination)),
+        match pipeline.stripped_metadata() {
+            0 => None,
+            n => Some(format!("stripped {n} metadata segment(s)")),
+        },
+        match pipeline.redacted_lines() {
+            0 => None,
+            n => Some(format!("redacted secrets on {n} line(s)")),
+        },
+    ];
+
+    if args.json {
+        for message in late_warnings.into_iter().flatten() {
+            warnings.warn(message)
+        }
+        json::print(&json::Upload {
+            input,
+            status: Some(status.as_u16()),
+            url: success.then(|| shown.clone()),
+            error: (!success).then(|| body.trim().to_string()),
+            code: (!success).then(|| error::status_code(status)),
+            token: token.as_ref().map(|t| t.expose().to_string()),
+            expires: expires.as_ref().map(json::rfc3339),
+            rate_limit: created.rate_limit.map(json::RateLimit::from),
+            warnings: warnings.list,
+        });
+    } else if let Some(format) = args.format.as_ref().filter(|_| success) {
+        // The template asked for the token and expiry, so they are shown without --display-secret
+        let expires = created.expires.map(|ts| ts.to_zoned(TimeZone::system()));
+        println!(
+            "{}",
+            format.render(&template::Fields {
+                url: &shown,
+                token: created.token.as_ref().map(Token::expose),
+                input: &input,
+                expires: expires.as_ref(),
+            })
+        );
+        if args.qr {
+            print_qr(&shown)
+        }
+        for message in late_warnings.into_iter().flatten() {
+            warnings.warn(message)
+        }
+    } else if args.quiet {
+        // Only bare URLs go to stdout, so scripts can use it as is
+        if success {
+            print_quiet_url(args, &shown);
+        } else {
+            eprintln!("{prefix}[{}] {shown}", status.as_u16());
+        }
+        if args.qr && success {
+            print_qr(&shown)
+        }
+        for message in late_warnings.into_iter().flatten() {
+            warnings.warn(message)
+        }
+    } else {
+        print!("{prefix}");
+        if success {
+            if label.is_none() {
+                print!("Succesful! ")
+            }
+        } else {
+            print!("[{}] ", status.as_u16())
+        }
+        println!("{shown}");
+        if args.qr && success {
+            print_qr(&shown)
+        }
+        for message in late_warnings.into_iter().flatten() {
+            warnings.warn(message)
+        }
+    }
+    if let Some(exp) = expiry.as_ref().filter(|_| status.is_client_error()) {
+        suggest_expiry(exp, &body, args.json || args.quiet)
+    }
+    // A template replaces these lines, taking what it needs of them
+    let templated = success && args.format.is_some();
+    if !args.json && !args.quiet && !templated {
+        if let Some(rate_limit) = created
+            .rate_limit
+            .filter(|rl| rl.is_exhausted() || status == StatusCode::TOO_MANY_REQUESTS)
+        {
+            println!("Rate limit: {rate_limit}")
+        }
+        if let Some(exp) = expires {
+            println!("Expires at {}", format_time(&exp))
+        }
+        if let Some(t) = token {
+            reveal::show_token(&t, args.ephemeral_token.map(Duration::from_secs))
+        }
+    }
+
+    if args.open && success {
+        open_url(&shown)
+    }
+
+    if let Some(signer) = signer
+        && success
+    {
+        let signed = signer.sign(body.trim())?;
+        let retry = Retry::new(args.retries, args.retry_delay);
+        upload_signature(
+            envs,
+            &retry,
+            signed,
+            secret,
+            expiry,
+            args.json || args.quiet,
+        )?
+    }
+
+    Ok(match created.token {
+        _ if !success => Outcome::Failed,
+        // 0x0 only sends a token for new files, and never for short links
+        None if !args.shorten => Outcome::Duplicate(shown),
+        _ => Outcome::Uploaded(shown),
+    })
+}
+
+/// Print a QR code of `url`, in colors that scan on any terminal theme if stdout is a terminal
+fn print_qr(url: &str) {
+    match QrCode::encode(url.as_bytes()) {
+        Some(code) => print!("{}", code.render(io::stdout().is_terminal())),
+        None => eprintln!("Warning: {url} is too long for a QR code"),
+    }
+}
+
+/// Open `url` in the browser, warning if that fails
+fn open_url(url: &str) {
+    if let Err(e) = browser::open(url) {
+        eprintln!("Warning: failed to open {url}: {e}")
+    }
+}
+
+/// Send `change` to `url`, retrying as configured
+fn send_change(
+    envs: &EnvsClient,
+    retry: &Retry,
+    url: &Url,
+    token: &Token,
+    change: &Change,
+    json: bool,
+) -> Result<reqwest::Result<Changed>> {
+    retry.send("Change", true, json, |attempt| {
+        if attempt > 0 && matches!(change, Change::Delete) && already_deleted(envs, url, json) {
+            return Ok(Ok(Changed {
+                status: StatusCode::OK,
+                body: String::new(),
+            }));
+        }
+        Ok(envs.change(url.clone(), token, change))
+    })
+}
+
+/// Whether an earlier attempt at deleting `url` went through, checked before re-sending it
+///
+/// The attempt may have reached the host even though its response didn't make it back, in which
+/// case re-sending it would only report a confusing 404.
+fn already_deleted(envs: &EnvsClient, url: &Url, json: bool) -> bool {
+    let gone = envs
+        .http()
+        .head(url.clone())
+        .send()
+        .is_ok_and(|resp| matches!(resp.status(), StatusCode::NOT_FOUND | StatusCode::GONE));
+    if gone {
+        note(json, "URL is already gone, so an earlier attempt succeeded");
+    }
+    gone
+}
+
+/// Problems with one upload or change that didn't stop it
+///
+/// With `--json` they are kept for the result's `warnings` array, otherwise printed as they occur.
+struct Warnings {
+    /// Whether stdout is reserved for `--json`
+    json: bool,
+    /// Prefix for printed warnings, naming the target in a batch
+    prefix: String,
+    /// Warnings kept for `--json`
+    list: Vec<String>,
+}
+
+impl Warnings {
+    /// Start collecting warnings
+    fn new(json: bool, prefix: &str) -> Self {
+        Self {
+            json,
+            prefix: prefix.to_string(),
+            list: vec![],
+        }
+    }
+
+    /// Report `message`
+    fn warn(&mut self, message: String) {
+        if self.json {
+            self.list.push(message)
+        } else {
+            eprintln!("{}Warning: {message}", self.prefix)
+        }
+    }
+}
+
+/// Print a human-readable message, to stderr if stdout is reserved for `--json`
+fn note(json: bool, message: impl Display) {
+    if json {
+        eprintln!("{message}")
+    } else {
+        println!("{message}")
+    }
+}
+
+/// A warning if a shortened URL doesn't redirect to where it was supposed to
+fn check_short_url(short: &str, destination: &Url) -> Option<String> {
+    let location = config::get()
+        .client_builder()
+        .redirect(Policy::none())
+        .build()
+        .unwrap()
+        .head(short)
+        .send()
+        .ok()
+        .filter(|resp| resp.status().is_redirection())
+        .and_then(|resp| {
+            resp.headers()
+                .get(LOCATION)
+                .and_then(|loc| loc.to_str().ok())
+                .and_then(|loc| Url::parse(loc).ok())
+        });
+
+    match location {
+        Some(location) if location == *destination => None,
+        Some(location) => Some(format!(
+            "{short} redirects to {location}, not {destination}"
+        )),
+        None => Some(format!(
+            "could not confirm that {short} redirects to {destination}"
+        )),
+    }
+}
+
+/// Upload a detached signature with the same options as the signed file, and explain how to verify it
+fn upload_signature(
+    envs: &EnvsClient,
+    retry: &Retry,
+    signed: Signed,
+    secret: bool,
+    expires: Option<Expiry>,
+    json: bool,
+) -> Result<()> {
+    let sig_name = format!("{}.minisig", signed.file_name);
+    let sig = retry
+        .send("Signature upload", true, json, |_| {
+            let sig_part = Part::text(signed.signature.clone())
+                .file_name(sig_name.clone())
+                .mime_str("text/plain")
+                .unwrap();
+            Ok(envs.upload(Upload {
+                content: Content::File(sig_part),
+                secret,
+                expires: expires.clone(),
+            }))
+        })?
+        .context("failed to upload signature")?;
+    if !sig.is_success() {
+        note(
+            json,
+            format!(
+                "[{}] Failed to upload signature: {}",
+                sig.status.as_u16(),
+                sig.body.trim()
+            ),
+        );
+        return Ok(());
+    }
+
+    note(json, format!("Signature: {}", sig.body.trim()));
+    note(
+        json,
+        format!(
+            "Verify with: minisign -Vm {} -x {sig_name} -P {}",
+            signed.file_name, signed.public_key
+        ),
+    );
+    Ok(())
+}
+
+/// Remove tracking parameters from a URL, dropping the query entirely if nothing is left
+fn clean_url(url: &mut Url) {
+    let kept: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| !key.starts_with("utm_") && !TRACKING_PARAMS.contains(&key.as_ref()))
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+
+    if kept.is_empty() {
+        url.set_query(None)
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(kept);
+    }
+}
+
+/// Whether a URL points at a host that is unreachable from the public internet
+fn is_private_url(url: &Url) -> bool {
+    match url.host() {
+        Some(Host::Domain(domain)) => {
+            let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+            domain == "localhost"
+                || [".localhost", ".internal", ".local", ".home.arpa"]
+                    .iter()
+                    .any(|suffix| domain.ends_with(suffix))
+        }
+        Some(Host::Ipv4(ip)) => {
+            ip.is_private() || ip.is_loopback() || ip.is_link_local() || ip.is_unspecified()
+        }
+        Some(Host::Ipv6(ip)) => {
+            ip.is_loopback()
+                || ip.is_unspecified()
+                // Unique local (fc00::/7) and link-local (fe80::/10)
+                || (ip.segments()[0] & 0xfe00) == 0xfc00
+                || (ip.segments()[0] & 0xffc0) == 0xfe80
+                || ip.to_ipv4_mapped().is_some_and(|v4| v4.is_private() || v4.is_loopback())
+        }
+        None => false,
+    }
+}
+
+/// Stream a local file through `pipeline` into a file part, reading it in `buffer_size` chunks
+///
+/// The part is named `file_name`, defaulting to the name of the file itself
+fn file_part(
+    path: &Path,
+    file_name: Option<String>,
+    buffer_size: u64,
+    pipeline: &Pipeline,
+    tally: &Tally,
+    progress: &Progress,
+) -> Result<Part> {
+    let file = File::open(path).context(format!("failed to open {}", path.display()))?;
+    let len = file
+        .metadata()
+        .context(format!("failed to read {}", path.display()))?
+        .len();
+    let file_name = file_name.or_else(|| {
+        path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+    });
+    let mime = mime_guess::from_path(file_name.as_deref().map_or(path, Path::new))
+        .first_or_octet_stream()
+        .to_string();
+
+    stream_part(
+        pipeline.apply(
+            Stream {
+                reader: Box::new(BufReader::with_capacity(
+                    buffer_size.try_into().unwrap_or(usize::MAX),
+                    file,
+                )),
+                len: Some(len),
+                file_name,
+                mime: Some(mime),
+            },
+            progress,
+        ),
+        tally,
+        progress,
+    )
+}
+
+/// Stream stdin through `pipeline` into a file part named `name`
+fn stdin_part(name: &str, pipeline: &Pipeline, tally: &Tally, progress: &Progress) -> Result<Part> {
+    stream_part(
+        pipeline.apply(
+            Stream {
+                reader: Box::new(io::stdin()),
+                len: None,
+                file_name: Some(name.to_string()),
+                mime: Some(
+                    mime_guess::from_path(name)
+                        .first_or_text_plain()
+                        .to_string(),
+                ),
+            },
+            progress,
+        ),
+        tally,
+        progress,
+    )
+}
+
+/// Download a URL, streaming its body through `pipeline` into a file part
+///
+/// The part is named `file_name`, or after the remote file if `remote_name` is set
+fn fetch_part(
+    client: &Client,
+    url: Url,
+    file_name: Option<String>,
+    remote_name: bool,
+    pipeline: &Pipeline,
+    tally: &Tally,
+    progress: &Progress,
+) -> Result<Part> {
+    let fetch_resp = client
+        .get(url.clone())
+        .send()
+        .and_then(Response::error_for_status)
+        .context(format!("failed to fetch {url}"))?;
+
+    let content_type = fetch_resp
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|ct| ct.to_str().map(ToString::to_string).ok());
+    let file_name = file_name
+        .or_else(|| remote_name.then(|| remote_file_name(&fetch_resp)).flatten())
+        .unwrap_or_else(|| "file".to_string());
+
+    let len = fetch_resp.content_length();
+    stream_part(
+        pipeline.apply(
+            Stream {
+                len,
+                reader: Box::new(fetch_resp),
+                file_name: Some(file_name),
+                mime: content_type,
+            },
+            progress,
+        ),
+        tally,
+        progress,
+    )
+}
+
+/// Build a file part from transformed contents, tallying what is sent as the last stage of
+/// `progress`
+fn stream_part(stream: Stream, tally: &Tally, progress: &Progress) -> Result<Part> {
+    let reader = tally.wrap(progress.stage("uploading", stream.reader, stream.len));
+    let part = match stream.len {
+        Some(len) => Part::reader_with_length(reader, len),
+        None => Part::reader(reader),
+    };
+    let part = match stream.file_name {
+        Some(name) => part.file_name(name),
+        None => part,
+    };
+
+    match stream.mime {
+        Some(mime) => part
+            .mime_str(&mime)
+            .map_err(|_| EnvshError::Data(format!("invalid Content-Type {mime:?}"))),
+        None => Ok(part),
+    }
+}
+
+/// Determine a fetched file's name, preferring Content-Disposition over the final URL path segment
+fn remote_file_name(resp: &Response) -> Option<String> {
+    let disposition_name = resp
+        .headers()
+        .get(CONTENT_DISPOSITION)
+        .and_then(|cd| cd.to_str().ok())
+        .and_then(|cd| {
+            let params = cd.split(';').map(str::trim);
+            let (extended, plain): (Vec<_>, Vec<_>) = params
+                .filter_map(|p| p.split_once('='))
+                .filter(|(k, _)| {
+                    k.eq_ignore_ascii_case("filename*") || k.eq_ignore_ascii_case("filename")
+                })
+                .partition(|(k, _)| k.ends_with('*'));
+
+            // RFC 6266: prefer filename* (charset'lang'percent-encoded) when present
+            extended
+                .first()
+                .and_then(|(_, v)| v.split_once("''"))
+                .map(|(_, encoded)| percent_decode_str(encoded).decode_utf8_lossy().into_owned())
+                .or_else(|| plain.first().map(|(_, v)| v.trim_matches('"').to_string()))
+        });
+
+    disposition_name
+        .or_else(|| {
+            resp.url()
+                .path_segments()
+                .and_then(|mut segments| segments.next_back())
+                .map(|segment| percent_decode_str(segment).decode_utf8_lossy().into_owned())
+        })
+        // Never let the server pick a path, only a name
+        .and_then(|name| {
+            Path::new(&name)
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+        })
+        .filter(|name| !name.is_empty())
+}
+
+/// Format a time for display with the configured format, [`TIME_FORMAT`] by default
+fn format_time(time: &Zoned) -> String {
+    time.strftime(&config::get().output.time_format).to_string()
+}
+
+/// Show the URL's current expiry and how a new expiry changes it
+///
+/// The current expiry is only asked from the host with `lookup`, since it takes a request.
+fn show_expiry_change(
+    url: &Url,
+    new_expiry: &Expiry,
+    lookup: bool,
+    json: bool,
+    warnings: &mut Warnings,
+) {
+    let current = lookup
+        .then(|| config::get().client().head(url.clone()).send().ok())
+        .flatten()
+        .and_then(|resp| envsh::expires_header(resp.headers()))
+        .map(|ts| ts.to_zoned(TimeZone::system()));
+    let now = Timestamp::now().round(Unit::Millisecond).unwrap();
+    let new = new_expiry.resolve(now).to_zoned(TimeZone::system());
+
+    match current {
+        Some(current) => {
+            let delta = current
+                .until(&new)
+                .and_then(|span| {
+                    span.round(SpanRound::new().largest(Unit::Hour).smallest(Unit::Minute))
+                })
+                .unwrap();
+            note(json, format!("Current expiry: {}", format_time(&current)));
+            let sign = match delta.signum() {
+                1 => "+",
+                -1 => "-",
+                _ => "",
+            };
+            note(
+                json,
+                format!(
+                    "New expiry:     {} ({sign}{})",
+                    format_time(&new),
+                    humanize::duration(&delta, Locale::current())
+                ),
+            );
+            if new < current {
+                warnings.warn("this shortens the link's lifetime".to_string())
+            }
+        }
+        None => note(json, format!("New expiry: {}", format_time(&new))),
+    }
+}
+
+/// Find the URL and token to manage, looking up history IDs and stored tokens
+///
+/// A token given on the command line takes precedence over a stored one.
+fn resolve_manage_target(
+    target: ManageTarget,
+    token: Option<Token>,
+) -> Result<(Url, Token, TokenSource)> {
+    let (url, stored) = match (history::find(&target), target) {
+        (Ok(entry), _) => {
+            let token = if entry.keyring {
+                keyring::get(&entry.url)
+                    .inspect_err(|e| eprintln!("Failed to read X-Token from the keyring: {e}"))
+                    .ok()
+                    .map(|t| (t.expose().to_string(), TokenSource::Keyring))
+            } else {
+                entry.token.map(|t| (t, TokenSource::History))
+            };
+            let url = Url::parse(&entry.url).map_err(|_| {
+                EnvshError::Data(format!("invalid URL {:?} in upload history", entry.url))
+            })?;
+            (url, token)
+        }
+        // Unrecorded URLs can still be managed with a token from the command line
+        (Err(_), ManageTarget::Url(url)) => (url, None),
+        (Err(e), _) => return Err(e),
+    };
+
+    let token = token
+        .map(|t| (t, TokenSource::Argument))
+        .or(stored.map(|(t, source)| (Token::new(t), source)));
+    match token {
+        Some((token, source)) => Ok((url, token, source)),
+        None => Err(EnvshError::Usage(format!(
+            "no X-Token is stored for {url}, pass it after the URL"
+        ))),
+    }
+}
+
+/// Modify an existing URL, or only show how with `dry_run`
+///
+/// With `quiet`, success prints nothing and a rejected change only fails.
+#[allow(clippy::too_many_arguments)]
+fn manage_url(
+    url: Url,
+    token: Token,
+    source: TokenSource,
+    options: ManageOpts,
+    retry: Retry,
+    dry_run: bool,
+    json: bool,
+    quiet: bool,
+) -> Result<()> {
+    let mut warnings = Warnings::new(json, "");
+    let expires = options.expires.or(options.expires_at);
+    if let Some(exp) = expires.as_ref().filter(|_| !quiet) {
+        // A dry run sends nothing, not even a request for the current expiry
+        show_expiry_change(&url, exp, !dry_run, json, &mut warnings);
+    }
+    let new_expiry = expires.as_ref().map(|exp| {
+        let now = Timestamp::now().round(Unit::Millisecond).unwrap();
+        exp.resolve(now)
+    });
+    let mut result = json::Manage {
+        url: url.to_string(),
+        action: if options.delete { "delete" } else { "expires" },
+        status: None,
+        expires: new_expiry.map(|ts| json::rfc3339(&ts.to_zoned(TimeZone::system()))),
+        error: None,
+        code: None,
+        warnings: vec![],
+    };
+
+    let change = match &expires {
+        Some(exp) if !options.delete => Change::Expires(exp.clone()),
+        _ => Change::Delete,
+    };
+    if dry_run {
+        let (name, value) = change.field();
+        if json {
+            json::print(&json::DryRun {
+                url: result.url,
+                action: result.action,
+                fields: [(name, value)].into(),
+                token_source: source.name(),
+                expires: result.expires,
+                warnings: warnings.list,
+            });
+        } else {
+            println!("Would send POST {url}");
+            println!("  token=(hidden, from {source})");
+            println!("  {name}={value}");
+        }
+        return Ok(());
+    }
+
+    let envs = EnvsClient::with_http_client(config::get().client()).with_host(config::get().host());
+    let changed = send_change(&envs, &retry, &url, &token, &change, json || quiet)?
+        .context(format!("failed to change {url}"))?;
+    if changed.is_success() {
+        let updated = match (&change, new_expiry) {
+            (Change::Expires(_), Some(ts)) => history::set_expiry(url.as_str(), ts),
+            _ => history::forget(url.as_str()),
+        };
+        if let Err(e) = updated {
+            warnings.warn(e.to_string())
+        }
+    }
+
+    if json {
+        let status = changed.status;
+        result.status = Some(status.as_u16());
+        if !status.is_success() {
+            if let Some(exp) = expires.as_ref().filter(|_| status.is_client_error()) {
+                suggest_expiry(exp, &changed.body, json)
+            }
+            result.error = Some(changed.body.trim().to_string());
+            result.code = Some(error::status_code(status));
+        }
+        result.warnings = warnings.list;
+        json::print(&result);
+    } else if quiet {
+        if !changed.is_success() {
+            return Err(EnvshError::Failed(String::new()));
+        }
+    } else if changed.is_success() {
+        println!("Change accepted!")
+    } else if changed.status == StatusCode::UNAUTHORIZED {
+        println!(
+            "[401] Token rejected. Check that it is the X-Token printed when this URL was created \
+            (with `envsh --display-secret`), see `envsh list --tokens` for the stored ones, or \
+            manage a recorded upload with `envsh manage @ID`"
+        )
+    } else {
+        let status = changed.status;
+        let body = changed.body;
+        println!("[{}] {}", status.as_u16(), body);
+        if let Some(exp) = expires.as_ref().filter(|_| status.is_client_error()) {
+            suggest_expiry(exp, &body, json)
+        }
+    }
+    Ok(())
+}
+
+/// Write a PDF of QR codes for the recorded uploads with all of `tags` to `output`
+fn write_qr_sheet(tags: &[String], columns: usize, output: &Path) -> Result<()> {
+    let history = History::load()?;
+    let now = Timestamp::now();
+    // Uploading the same file twice records its URL twice, but it only needs one code
+    let mut seen = HashSet::new();
+    let items: Vec<_> = history
+        .entries
+        .into_iter()
+        .filter(|e| e.expires.is_none_or(|exp| exp > now))
+        .filter(|e| tags.iter().all(|tag| e.tags.contains(tag)))
+        .filter(|e| seen.insert(e.url.clone()))
+        .filter_map(|e| match QrCode::encode(e.url.as_bytes()) {
+            Some(code) => Some(sheet::Item {
+                code,
+                label: format!("@{} {}", e.id, e.input),
+                url: e.url,
+            }),
+            None => {
+                eprintln!("Warning: {} is too long for a QR code", e.url);
+                None
+            }
+        })
+        .collect();
+    if items.is_empty() {
+        return Err(EnvshError::Failed("no uploads match".to_string()));
+    }
+
+    let pdf = sheet::pdf(&items, columns);
+    let to_stdout = output == Path::new("-");
+    if to_stdout {
+        io::stdout()
+            .write_all(&pdf)
+            .context("failed to write the sheet")?;
+    } else {
+        fs::write(output, &pdf).context(format!("failed to write {}", output.display()))?;
+    }
+    let codes = match items.len() {
+        1 => "1 QR code".to_string(),
+        n => format!("{n} QR codes"),
+    };
+    let destination = if to_stdout {
+        "stdout".into()
+    } else {
+        output.display().to_string()
+    };
+    note(to_stdout, format!("Wrote {codes} to {destination}"));
+    Ok(())
+}
+
+/// Delete the recorded uploads with all of `tags`, made at least `older_than` ago
+///
+/// With `quiet`, only failures are reported.
+fn delete_uploads(
+    tags: &[String],
+    older_than: Option<Duration>,
+    yes: bool,
+    retry: Retry,
+    json: bool,
+    quiet: bool,
+) -> Result<()> {
+    history::check_writable("delete")?;
+    let history = History::load()?;
+    let now = Timestamp::now();
+    let cutoff = older_than
+        .map(|age| now.checked_sub(age))
+        .transpose()
+        .map_err(|e| EnvshError::Usage(format!("invalid --older-than: {e}")))?;
+    let matching: Vec<_> = history
+        .entries
+        .into_iter()
+        .filter(|e| e.token.is_some() || e.keyring)
+        .filter(|e| e.expires.is_none_or(|exp| exp > now))
+        .filter(|e| tags.iter().all(|tag| e.tags.contains(tag)))
+        .filter(|e| cutoff.is_none_or(|cutoff| e.created <= cutoff))
+        .collect();
+    if matching.is_empty() {
+        note(json || quiet, "No uploads match");
+        return Ok(());
+    }
+
+    for entry in &matching {
+        eprintln!("  @{}  {}  {}", entry.id, entry.url, entry.input);
+    }
+    let question = match matching.len() {
+        1 => "Delete this upload?".to_string(),
+        n => format!("Delete these {n} uploads?"),
+    };
+    if !yes && !preview::ask(&question)? {
+        note(json || quiet, "Nothing deleted");
+        return Ok(());
+    }
+
+    let envs = EnvsClient::with_http_client(config::get().client()).with_host(config::get().host());
+    let mut failed = 0;
+    for entry in &matching {
+        let mut warnings = Warnings::new(json, "");
+        // An entry that can't be sent counts as failed, without holding up the others
+        let (status, error, code) = match delete_target(entry) {
+            Ok((url, token)) => {
+                match send_change(&envs, &retry, &url, &token, &Change::Delete, json || quiet)? {
+                    Ok(changed) if changed.is_success() => {
+                        if let Err(e) = history::forget(url.as_str()) {
+                            warnings.warn(e.to_string())
+                        }
+                        (Some(changed.status), None, None)
+                    }
+                    Ok(changed) => (
+                        Some(changed.status),
+                        Some(changed.body.trim().to_string()),
+                        Some(error::status_code(changed.status)),
+                    ),
+                    Err(e) => (None, Some(e.to_string()), Some(error::request_code(&e))),
+                }
+            }
+            Err(e) => (None, Some(e.to_string()), Some(e.code())),
+        };
+        if error.is_some() {
+            failed += 1;
+        }
+
+        if json {
+            json::print(&json::Manage {
+                url: entry.url.clone(),
+                action: "delete",
+                status: status.map(|s| s.as_u16()),
+                expires: None,
+                error,
+                code,
+                warnings: warnings.list,
+            });
+        } else if !quiet {
+            match (status, error) {
+                (_, None) => println!("@{} {} deleted", entry.id, entry.url),
+                (Some(status), Some(body)) => {
+                    println!(
+                        "@{} {} not deleted: [{}] {body}",
+                        entry.id,
+                        entry.url,
+                        status.as_u16()
+                    )
+                }
+                (None, Some(e)) => println!("@{} {} not deleted: {e}", entry.id, entry.url),
+            }
+        }
+    }
+
+    match failed {
+        0 => Ok(()),
+        _ => Err(EnvshError::Failed(format!(
+            "{failed} of {} deletions failed",
+            matching.len()
+        ))),
+    }
+}
+
+/// URL and X-Token for deleting a recorded upload
+fn delete_target(entry: &history::Entry) -> Result<(Url, Token)> {
+    let token = if entry.keyring {
+        keyring::get(&entry.url).context(format!(
+            "failed to read X-Token for {} from the keyring",
+            entry.url
+        ))?
+    } else {
+        Token::new(entry.token.clone().unwrap())
+    };
+    let url = Url::parse(&entry.url).map_err(|e| {
+        EnvshError::Data(format!(
+            "invalid URL {:?} in upload history: {e}",
+            entry.url
+        ))
+    })?;
+    Ok((url, token))
+}
+
+/// Explain accepted `expires` values when the server appears to have rejected one
+fn suggest_expiry(expiry: &Expiry, body: &str, json: bool) {
+    if !body.to_ascii_lowercase().contains("expir") {
+        return;
+    }
+
+    let max = Expiry::MAX_EXPIRY_HOURS;
+    match expiry {
+        Expiry::Hours(h) => note(
+            json,
+            format!(
+                "Hint: {h} hours was rejected. Expiry in hours must be between 1 and {max} ({} days), \
+                e.g. `--expires {max}`",
+                max / 24
+            ),
+        ),
+        Expiry::Timestamp(ts) => {
+            let latest = Timestamp::now()
+                .round(Unit::Millisecond)
+                .and_then(|now| now.checked_add(max.hours()))
+                .unwrap();
+            note(
+                json,
+                format!(
+                    "Hint: {} was rejected. Timestamps are epoch milliseconds, no later than {} \
+                    (`--expires {}`), or use hours (at most {max})",
+                    format_time(&ts.to_zoned(TimeZone::system())),
+                    format_time(&latest.to_zoned(TimeZone::system())),
+                    latest.as_millisecond()
+                ),
+            )
+        }
+    }
+}
+
+/// Open a new temporary file in the user's editor, and upload it with `upload_args` once saved
+fn edit_and_upload(ext: &str, upload_args: impl FnOnce(PathBuf) -> Cli) -> Result<()> {
+    let (temp, _) =
+        TempFile::create(&format!(".{ext}")).context("failed to create temporary file")?;
+
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut editor_args = editor.split_whitespace();
+    let program = editor_args
+        .next()
+        .ok_or_else(|| EnvshError::Usage("$EDITOR is empty".to_string()))?;
+    let status = temp::run_interactive(
+        process::Command::new(program)
+            .args(editor_args)
+            .arg(temp.path()),
+    )
+    .context(format!("failed to launch editor {program:?}"))?;
+
+    let empty = fs::metadata(temp.path())
+        .map(|m| m.len() == 0)
+        .unwrap_or(true);
+    if !status.success() {
+        println!("Editor exited with {status}, not uploading");
+        Ok(())
+    } else if empty {
+        println!("Nothing to upload");
+        Ok(())
+    } else {
+        create_url(upload_args(temp.path().to_path_buf()))
+    }
+}
+
+/// Save the clipboard's contents to a temporary file, then upload it
+fn paste_and_upload(upload_args: impl FnOnce(PathBuf) -> Cli) -> Result<()> {
+    let pasted = clipboard::paste()
+        .context("failed to read the clipboard")?
+        .ok_or_else(|| EnvshError::Usage("the clipboard is empty".to_string()))?;
+    let (temp, mut file) = TempFile::create(&format!(".{}", pasted.extension))
+        .context("failed to create temporary file")?;
+    file.write_all(&pasted.data)
+        .and_then(|()| file.sync_all())
+        .context(format!("failed to write {}", temp.path().display()))?;
+    drop(file);
+    create_url(upload_args(temp.path().to_path_buf()))
+}
+
+/// Print a table of recorded uploads, oldest first
+fn list_uploads(json: bool, tokens: bool) -> Result<()> {
+    let history = History::load()?;
+    let secret = |entry: &history::Entry| -> Option<String> {
+        if !tokens {
+            None
+        } else if entry.keyring {
+            keyring::get(&entry.url)
+                .inspect_err(|e| {
+                    eprintln!(
+                        "Failed to read X-Token for {} from the keyring: {e}",
+                        entry.url
+                    )
+                })
+                .ok()
+                .map(|t| t.expose().to_string())
+        } else {
+            entry.token.clone()
+        }
+    };
+    if json {
+        for entry in &history.entries {
+            json::print(&listed(entry, secret(entry)));
+        }
+        return Ok(());
+    }
+    if history.entries.is_empty() {
+        println!("No uploads recorded yet");
+        return Ok(());
+    }
+
+    let now = Zoned::now();
+    let rows: Vec<[String; 6]> = history
+        .entries
+        .iter()
+        .map(|entry| {
+            [
+                entry.id.to_string(),
+                entry.url.clone(),
+                entry.input.clone(),
+                entry.expires.map_or_else(
+                    || "-".to_string(),
+                    |exp| humanize::relative_time(&now, &exp.to_zoned(TimeZone::system())),
+                ),
+                match (secret(entry), &entry.token, entry.keyring) {
+                    (Some(token), _, _) => token,
+                    (None, _, true) => "keyring".to_string(),
+                    (None, Some(_), false) => "yes".to_string(),
+                    (None, None, false) => "no".to_string(),
+                },
+                entry.tags.join(","),
+            ]
+        })
+        .collect();
+    let header = ["ID", "URL", "NAME", "EXPIRES", "TOKEN", "TAGS"].map(String::from);
+    let widths: Vec<_> = (0..header.len())
+        .map(|col| {
+            rows.iter()
+                .chain([&header])
+                .map(|row| row[col].chars().count())
+                .max()
+                .unwrap()
+        })
+        .collect();
+
+    for row in [&header].into_iter().chain(&rows) {
+        let line: Vec<_> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+    Ok(())
+}
+
+/// `entry` as printed by `list --json` and `info --json`, with the stored X-Token if given
+fn listed(entry: &history::Entry, x_token: Option<String>) -> json::Listed {
+    json::Listed {
+        id: entry.id,
+        url: entry.url.clone(),
+        input: entry.input.clone(),
+        expires: entry
+            .expires
+            .map(|exp| json::rfc3339(&exp.to_zoned(TimeZone::UTC))),
+        token: entry.token.is_some() || entry.keyring,
+        x_token,
+        tags: entry.tags.clone(),
+        created: json::rfc3339(&entry.created.to_zoned(TimeZone::UTC)),
+        size: entry.size,
+        sha256: entry.sha256.clone(),
+        headers: entry.headers.clone(),
+    }
+}
+
+/// Print everything the history records about the upload `target` refers to
+fn show_info(target: &ManageTarget, json: bool) -> Result<()> {
+    let entry = history::find(target)?;
+    if json {
+        json::print(&listed(&entry, None));
+        return Ok(());
+    }
+
+    let tz = TimeZone::system();
+    println!("ID:       @{}", entry.id);
+    println!("URL:      {}", entry.url);
+    println!("Name:     {}", entry.input);
+    println!(
+        "Created:  {}",
+        format_time(&entry.created.to_zoned(tz.clone()))
+    );
+    match entry.expires {
+        Some(exp) => {
+            let exp = exp.to_zoned(tz);
+            println!(
+                "Expires:  {} ({})",
+                format_time(&exp),
+                humanize::relative_time(&Zoned::now(), &exp)
+            )
+        }
+        None => println!("Expires:  -"),
+    }
+    let token = match (&entry.token, entry.keyring) {
+        (_, true) => "in the keyring",
+        (Some(_), false) => "in the history, see `envsh list --tokens`",
+        (None, false) => "not stored",
+    };
+    println!("X-Token:  {token}");
+    if let Some(size) = entry.size {
+        println!("Size:     {size} bytes");
+    }
+    if let Some(sha256) = &entry.sha256 {
+        println!("SHA-256:  {sha256}");
+    }
+    if !entry.tags.is_empty() {
+        println!("Tags:     {}", entry.tags.join(", "));
+    }
+    if !entry.headers.is_empty() {
+        println!("Headers:");
+        for (name, value) in &entry.headers {
+            println!("  {name}: {value}");
+        }
+    }
+    Ok(())
+}
+
+/// Stream the body of a URL to stdout
+fn cat_url(mut url: Url, decrypt: bool) -> Result<()> {
+    let key = match url.fragment() {
+        Some(fragment) if decrypt => Some(fragment.parse::<crypt::Key>().map_err(|e| {
+            EnvshError::Usage(format!("invalid key in the fragment of {url}: {e}"))
+        })?),
+        None if decrypt => {
+            return Err(EnvshError::Usage(format!(
+                "{url} has no #fragment with the key to decrypt it"
+            )));
+        }
+        _ => None,
+    };
+    // Keep the key out of error messages from here on
+    url.set_fragment(None);
+
+    let cat_resp = config::get()
+        .client()
+        .get(url.clone())
+        .send()
+        .and_then(Response::error_for_status)
+        .context(format!("failed to download {url}"))?;
+    let mut contents: Box<dyn io::Read> = match &key {
+        Some(key) => Box::new(crypt::Decrypt::new(cat_resp, key)),
+        None => Box::new(cat_resp),
+    };
+
+    match io::copy(&mut contents, &mut io::stdout().lock()) {
+        Ok(_) => Ok(()),
+        // Reader went away (e.g. `| head`), nothing left to do
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::InvalidData && key.is_some() => {
+            Err(EnvshError::Data(format!("failed to decrypt {url}: {e}")))
+        }
+        Err(e) => Err(e).context("failed to copy URL to stdout"),
+    }
+}
+
+/// Shell functions offering what `envsh complete-stored` prints, falling back to the generated ones
+const BASH_STORED: &str = r#"
+_envsh_stored() {
+    local cur="${COMP_WORDS[COMP_CWORD]}" stored
+    if [[ ${cur} != -* ]]; then
+        stored=$(envsh complete-stored -- "${COMP_WORDS[@]:1:COMP_CWORD-1}" 2>/dev/null | cut -f1)
+        if [[ -n ${stored} ]]; then
+            COMPREPLY=($(compgen -W "${stored}" -- "${cur}"))
+            return 0
+        fi
+    fi
+    _envsh "$@"
+}
+
+if [[ "${BASH_VERSINFO[0]}" -eq 4 && "${BASH_VERSINFO[1]}" -ge 4 || "${BASH_VERSINFO[0]}" -gt 4 ]]; then
+    complete -F _envsh_stored -o nosort -o bashdefault -o default envsh
+else
+    complete -F _envsh_stored -o bashdefault -o default envsh
+fi
+"#;
+
+/// Zsh function offering what `envsh complete-stored` prints, or else completing with its arguments
+const ZSH_STORED: &str = r#"_envsh_stored() {
+    local -a stored
+    stored=(${(f)"$(envsh complete-stored -- ${words[1,CURRENT-1]} 2>/dev/null)"})
+    stored=(${stored//$'\t'/:})
+    _describe 'stored' stored || "$@"
+}
+
+"#;
+
+/// Generate shell completions, adding options for Fish and stored tokens for `manage`
+fn generate_shell_completion(shell: Shell) {
+    let mut completion: Vec<u8> = vec![];
+    clap_complete::generate(shell, &mut Cli::command(), "envsh", &mut completion);
+
+    match shell {
+        Shell::Bash => completion.extend(BASH_STORED.as_bytes()),
+        Shell::Zsh => {
+            let generated = String::from_utf8(completion).unwrap();
+            let manage_target = "`envsh alias`:_urls' \\";
+            let manage_token = "stored in the history:_default' \\";
+            let entry = "if [ \"$funcstack[1]\" = \"_envsh\" ]; then";
+            completion = generated
+                .replacen(manage_target, "`envsh alias`:_envsh_stored _urls' \\", 1)
+                .replacen(manage_token, "stored in the history:_envsh_stored' \\", 1)
+                .replacen(entry, &format!("{ZSH_STORED}{entry}"), 1)
+                .into_bytes();
+        }
+        // Append fish options for generate subcommand
+        Shell::Fish => completion.extend(
+            b"\
+            complete -c envsh -n \"__fish_envsh_using_subcommand completion\" -f -a 'bash'\n\
+            complete -c envsh -n \"__fish_envsh_using_subcommand completion\" -f -a 'elvish'\n\
+            complete -c envsh -n \"__fish_envsh_using_subcommand completion\" -f -a 'fish'\n\
+            complete -c envsh -n \"__fish_envsh_using_subcommand completion\" -f -a 'powershell'\n\
+            complete -c envsh -n \"__fish_envsh_using_subcommand completion\" -f -a 'zsh'\n\
+            complete -c envsh -n \"__fish_envsh_using_subcommand manage\" -f \
+                -a '(envsh complete-stored -- (commandline -opc)[2..-1] 2>/dev/null)'",
+        ),
+        _ => {}
+    }
+
+    io::stdout().write_all(&completion).unwrap()
+}
+
+/// Print what can follow `words` if they end inside `envsh manage`: recorded uploads with a
+/// stored X-Token as `@ID<TAB>URL` lines, or the token stored for the upload already given
+///
+/// Prints nothing on errors, which completion scripts can't show.
+fn complete_stored(words: &[String]) {
+    let Some(start) = words.iter().position(|word| word == "manage") else {
+        return;
+    };
+    let mut cmd = Cli::command();
+    cmd.build();
+    let manage = cmd.find_subcommand("manage").unwrap();
+    // Values of options aren't positional arguments, so skip them while counting those
+    let takes_value = |word: &str| {
+        manage
+            .get_arguments()
+            .filter(|arg| !arg.is_positional() && arg.get_action().takes_values())
+            .any(|arg| {
+                word.strip_prefix("--")
+                    .is_some_and(|long| arg.get_long() == Some(long))
+                    || arg
+                        .get_short()
+                        .is_some_and(|short| word == format!("-{short}"))
+            })
+    };
+    let mut positionals = vec![];
+    let mut rest = words[start + 1..].iter();
+    while let Some(word) = rest.next() {
+        if takes_value(word) {
+            rest.next();
+        } else if !word.starts_with('-') {
+            positionals.push(word.as_str());
+        }
+    }
+
+    match positionals[..] {
+        [] => {
+            let Ok(history) = History::load() else {
+                return;
+            };
+            for entry in history.entries.iter().rev() {
+                if entry.token.is_some() || entry.keyring {
+                    println!("@{}\t{}", entry.id, entry.url)
+                }
+            }
+        }
+        [target] => {
+            let target = match parse::history_ref(target) {
+                Some(Ok(target)) => target,
+                Some(Err(_)) => return,
+                None => match Url::parse(target) {
+                    Ok(url) => ManageTarget::Url(url),
+                    Err(_) => return,
+                },
+            };
+            if let Ok((_, token, _)) = resolve_manage_target(target, None) {
+                println!("{}", token.expose())
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ends_quiet_urls() {
+        let urls = ["https://envs.sh/a.txt", "https://envs.sh/b.txt"];
+        let printed = |ending| -> String {
+            urls.iter()
+                .enumerate()
+                .map(|(i, url)| quiet_url(url, ending, i == 0))
+                .collect()
+        };
+        assert_eq!(
+            printed(Ending::Nul),
+            "https://envs.sh/a.txt\0https://envs.sh/b.txt\0"
+        );
+        assert_eq!(
+            printed(Ending::Separated),
+            "https://envs.sh/a.txt\nhttps://envs.sh/b.txt"
+        );
+        assert_eq!(
+            printed(Ending::Newline),
+            "https://envs.sh/a.txt\nhttps://envs.sh/b.txt\n"
+        );
+        // Both need --quiet, where only URLs go to stdout
+        assert!(parse_args(["envsh", "--print0", "https://example.com/"]).is_err());
+        assert!(
+            parse_args([
+                "envsh",
+                "-q",
+                "--print0",
+                "--no-newline",
+                "https://example.com/"
+            ])
+            .is_err()
+        );
+        assert!(
+            parse_args(["envsh", "-q", "--print0", "https://example.com/"])
+                .unwrap()
+                .print0
+        );
+    }
+
+    #[test]
+    fn parses_globals_around_subcommands() {
+        for line in [
+            &["envsh", "--json", "list"][..],
+            &["envsh", "list", "--json"],
+        ] {
+            let args = parse_args(line).unwrap();
+            assert!(args.json);
+            assert!(matches!(args.subcom, Some(Subcommands::List { .. })));
+        }
+        let args = parse_args(["envsh", "-q", "--retries", "5", "list"]).unwrap();
+        assert!(args.quiet);
+        assert_eq!(args.retries, Some(5));
+
+        let err = parse_args(["envsh", "--secret", "list"]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ArgumentConflict);
+    }
 }
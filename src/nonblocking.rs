@@ -0,0 +1,188 @@
+//! Async variant of [`crate::EnvsClient`], enabled by the `async` feature
+//!
+//! Requests and responses are the same as the blocking client's, except that file contents are
+//! async [`Part`]s.
+//!
+//! ```no_run
+//! use envsh::Expiry;
+//! use envsh::nonblocking::{Content, EnvsClient, Upload};
+//!
+//! async fn share() -> Result<(), Box<dyn std::error::Error>> {
+//!     let envs = EnvsClient::new();
+//!     let created = envs
+//!         .upload(Upload {
+//!             expires: Some(Expiry::Hours(24)),
+//!             ..Upload::new(Content::file("notes.txt".as_ref()).await?)
+//!         })
+//!         .await?;
+//!     println!("{}", created.url().expect("upload failed"));
+//!     Ok(())
+//! }
+//! ```
+
+use std::io;
+use std::path::Path;
+
+use reqwest::multipart::{Form, Part};
+use reqwest::{Body, Client, Url};
+
+use crate::{
+    Change, Changed, Created, ENVS, Expiry, Token, expires_header, rate_limit_headers,
+    recorded_headers,
+};
+
+/// What to send to the host
+#[derive(Debug)]
+pub enum Content {
+    /// File contents, as a named multipart part
+    File(Part),
+    /// A URL for the host to fetch and store
+    Url(Url),
+    /// A URL to shorten
+    Shorten(Url),
+}
+
+impl Content {
+    /// Stream a local file, named after it and with a MIME type guessed from its extension
+    pub async fn file(path: &Path) -> io::Result<Self> {
+        let file = tokio::fs::File::open(path).await?;
+        let len = file.metadata().await?.len();
+        let mime = mime_guess::from_path(path).first_or_octet_stream();
+
+        let part = Part::stream_with_length(Body::from(file), len)
+            .mime_str(mime.as_ref())
+            .unwrap();
+        Ok(Self::File(match path.file_name() {
+            Some(name) => part.file_name(name.to_string_lossy().into_owned()),
+            None => part,
+        }))
+    }
+}
+
+/// A new upload or URL submission
+#[derive(Debug)]
+pub struct Upload {
+    /// What to send
+    pub content: Content,
+    /// Make the resulting URL difficult to guess
+    pub secret: bool,
+    /// When the upload should expire
+    pub expires: Option<Expiry>,
+}
+
+impl Upload {
+    /// Send `content` with the host's default options
+    pub fn new(content: Content) -> Self {
+        Self {
+            content,
+            secret: false,
+            expires: None,
+        }
+    }
+}
+
+/// Async client for an envs.sh host
+#[derive(Clone, Debug)]
+pub struct EnvsClient {
+    /// Underlying HTTP client, shared between requests so connections are reused
+    http: Client,
+    /// Host that uploads are sent to
+    host: Url,
+}
+
+impl Default for EnvsClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EnvsClient {
+    /// Client for [`ENVS`]
+    pub fn new() -> Self {
+        Self::with_http_client(Client::new())
+    }
+
+    /// Client for [`ENVS`], sending requests through `http`
+    pub fn with_http_client(http: Client) -> Self {
+        Self {
+            http,
+            host: Url::parse(ENVS).unwrap(),
+        }
+    }
+
+    /// Send uploads to `host` instead of [`ENVS`]
+    pub fn with_host(self, host: Url) -> Self {
+        Self { host, ..self }
+    }
+
+    /// Host that uploads are sent to
+    pub fn host(&self) -> &Url {
+        &self.host
+    }
+
+    /// Underlying HTTP client
+    pub fn http(&self) -> &Client {
+        &self.http
+    }
+
+    /// Upload a file, or submit or shorten a URL
+    pub async fn upload(&self, upload: Upload) -> reqwest::Result<Created> {
+        let form = [
+            Some(match upload.content {
+                Content::File(part) => ("file", part),
+                Content::Url(url) => ("url", Part::text(url.to_string())),
+                Content::Shorten(url) => ("shorten", Part::text(url.to_string())),
+            }),
+            upload.secret.then_some(("secret", Part::text(""))),
+            upload
+                .expires
+                .map(|time| ("expires", Part::text(time.to_string()))),
+        ]
+        .into_iter()
+        .flatten()
+        .fold(Form::new(), |form, (name, value)| form.part(name, value));
+
+        let resp = self
+            .http
+            .post(self.host.clone())
+            .multipart(form)
+            .send()
+            .await?;
+        let status = resp.status();
+        let token = resp
+            .headers()
+            .get("X-Token")
+            .and_then(|t| t.to_str().map(Token::new).ok());
+        let expires = expires_header(resp.headers());
+        let rate_limit = rate_limit_headers(resp.headers());
+        let headers = recorded_headers(resp.headers());
+
+        Ok(Created {
+            status,
+            body: resp.text().await?,
+            token,
+            expires,
+            rate_limit,
+            headers,
+        })
+    }
+
+    /// Change the expiry of, or delete, the upload at `url`
+    pub async fn change(
+        &self,
+        url: Url,
+        token: &Token,
+        change: &Change,
+    ) -> reqwest::Result<Changed> {
+        let (name, value) = change.field();
+        let form = Form::new()
+            .text("token", token.expose().to_string())
+            .text(name, value);
+
+        let resp = self.http.post(url).multipart(form).send().await?;
+        Ok(Changed {
+            status: resp.status(),
+            body: resp.text().await?,
+        })
+    }
+}
@@ -0,0 +1,60 @@
+//! A reminder about recorded links that expire soon, shown after other commands
+
+use std::fs;
+
+use jiff::{SignedDuration, Timestamp};
+
+use crate::history::History;
+use crate::{config, paths};
+
+/// How far ahead a link counts as expiring soon
+const SOON: SignedDuration = SignedDuration::from_hours(24);
+
+/// Least time between two reminders, so they don't repeat on every command
+const QUIET_FOR: SignedDuration = SignedDuration::from_hours(6);
+
+/// With `expiry_notice` set, mention links in the history expiring within [`SOON`] on stderr,
+/// unless that was done less than [`QUIET_FOR`] ago
+///
+/// When the reminder was last shown is kept in the cache directory. Nothing is shown with
+/// `--read-only-state`, which couldn't keep that, and problems are ignored, since the reminder
+/// isn't what was asked for.
+pub(crate) fn expiring_soon() {
+    let config = config::get();
+    if !config.output.expiry_notice || config.read_only_state {
+        return;
+    }
+    let Ok(stamp) = paths::cache_dir().map(|dir| dir.join("expiry-notice")) else {
+        return;
+    };
+    let now = Timestamp::now();
+    if let Ok(last) = fs::read_to_string(&stamp)
+        && let Ok(last) = last.trim().parse::<Timestamp>()
+        && now.duration_since(last) < QUIET_FOR
+    {
+        return;
+    }
+    let Ok(history) = History::load() else {
+        return;
+    };
+
+    let expiring = history
+        .entries
+        .iter()
+        .filter(|e| {
+            e.expires
+                .is_some_and(|exp| exp > now && exp.duration_since(now) <= SOON)
+        })
+        .count();
+    match expiring {
+        0 => return,
+        1 => eprintln!(
+            "1 link expires within 24 hours, see `envsh list` and extend it with `envsh manage -e`"
+        ),
+        n => eprintln!(
+            "{n} links expire within 24 hours, see `envsh list` and extend them with `envsh manage -e`"
+        ),
+    }
+    let _ = fs::create_dir_all(stamp.parent().unwrap());
+    let _ = fs::write(&stamp, now.to_string());
+}
@@ -0,0 +1,805 @@
+//! Input validation behind the [`crate::cli`] value parsers
+//!
+//! These are kept free of clap types so they can be tested directly.
+
+use std::borrow::Cow;
+use std::net::{IpAddr, SocketAddr};
+use std::num::ParseIntError;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use jiff::civil::{Date, DateTime, Time, Weekday};
+use jiff::{Timestamp, ToSpan, Unit, Zoned};
+use reqwest::{Url, tls};
+
+use crate::{Expiry, ManageTarget, Target, paths};
+
+/// Shortest token accepted; envs.sh issues 43-character tokens
+pub(crate) const MIN_TOKEN_LEN: usize = 16;
+
+/// Why a URL can't be managed
+#[derive(Debug)]
+pub(crate) enum EnvsUrlError {
+    /// Not a URL at all
+    Invalid(url::ParseError),
+    /// A URL for some other host
+    NotEnvs(Url),
+}
+
+/// Characters that make a target a glob pattern, for shells that don't expand them
+const GLOB_CHARS: &[char] = &['*', '?', '['];
+
+/// Interpret a target as stdin (`-`), an existing file or directory path, or a glob matching
+/// some, falling back to a URL
+///
+/// Under Termux, a leading `~` or `$PREFIX` is expanded first.
+pub(crate) fn target(target: &str) -> Result<Target, url::ParseError> {
+    let expanded = paths::expand_termux(target).map(|path| path.to_string_lossy().into_owned());
+    let target = expanded.as_deref().unwrap_or(target);
+    if target == "-" {
+        Ok(Target::Stdin)
+    } else if let Some(path) = PathBuf::from_str(target).ok().filter(|p| p.is_dir()) {
+        Ok(Target::Dir(path))
+    } else if let Some(path) = PathBuf::from_str(target).ok().filter(|p| p.is_file()) {
+        Ok(Target::File(path))
+    } else if strip_verbatim(target).contains(GLOB_CHARS)
+        && let Some(matches) = glob_matches(&strip_verbatim(target))
+    {
+        Ok(Target::Glob {
+            pattern: target.to_string(),
+            matches,
+        })
+    } else {
+        Url::from_str(target).map(Target::Url)
+    }
+}
+
+/// Strip Windows verbatim prefixes (`\\?\C:\...`, `\\?\UNC\server\share\...`), which
+/// std doesn't need to open long paths and whose `?` would otherwise read as a glob
+pub(crate) fn strip_verbatim(path: &str) -> Cow<'_, str> {
+    if let Some(share) = path.strip_prefix(r"\\?\UNC\") {
+        Cow::Owned(format!(r"\\{share}"))
+    } else if let Some(disk) = path.strip_prefix(r"\\?\")
+        && disk.as_bytes().get(1) == Some(&b':')
+    {
+        Cow::Borrowed(disk)
+    } else {
+        Cow::Borrowed(path)
+    }
+}
+
+/// Expand a glob pattern, returning its matches in order, or `None` if nothing matched
+fn glob_matches(pattern: &str) -> Option<Vec<Target>> {
+    let matches: Vec<_> = glob::glob(pattern)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter_map(|path| path.to_str().and_then(|p| target(p).ok()))
+        .collect();
+    (!matches.is_empty()).then_some(matches)
+}
+
+/// Split a list of targets on newlines, or on NUL bytes if `null` is set, skipping empty entries
+pub(crate) fn target_list(list: &str, null: bool) -> impl Iterator<Item = &str> {
+    list.split(if null { '\0' } else { '\n' })
+        .map(move |entry| {
+            if null {
+                entry
+            } else {
+                entry.trim_end_matches('\r')
+            }
+        })
+        .filter(|entry| !entry.is_empty())
+}
+
+/// Interpret an expiry as hours if it is at most [`Expiry::MAX_EXPIRY_HOURS`], otherwise as
+/// epoch milliseconds
+pub(crate) fn expiry(expiry: i64) -> Result<Expiry, String> {
+    if expiry < 0 {
+        Err(format!("expiry cannot be negative, got {expiry}"))
+    } else if expiry <= Expiry::MAX_EXPIRY_HOURS {
+        Ok(Expiry::Hours(expiry))
+    } else {
+        Timestamp::from_millisecond(expiry)
+            .map(Expiry::Timestamp)
+            .map_err(|e| format!("invalid timestamp {expiry}: {e}"))
+    }
+}
+
+/// Turn a duration from [`duration`] into an expiry that far after `now`
+///
+/// Whole hours stay hours; anything else becomes a timestamp. Durations past
+/// [`Expiry::MAX_EXPIRY_HOURS`] are rejected, since the host would refuse them.
+pub(crate) fn expiry_in(duration: Duration, now: Timestamp) -> Result<Expiry, String> {
+    let max = Expiry::MAX_EXPIRY_HOURS;
+    let secs = duration.as_secs();
+    if secs > max as u64 * 3600 {
+        return Err(format!(
+            "expiry is longer than the host maximum of {max} hours ({} days)",
+            max / 24
+        ));
+    }
+    if secs.is_multiple_of(3600) {
+        Ok(Expiry::Hours((secs / 3600) as i64))
+    } else {
+        now.round(Unit::Millisecond)
+            .and_then(|now| now.checked_add(duration))
+            .map(Expiry::Timestamp)
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Parse an RFC 3339 timestamp, or a local date with an optional time, as an expiry after `now`
+///
+/// Accepts e.g. `2025-07-01T18:00:00Z`, `2025-07-01T18:00` or `2025-07-01`, which means midnight
+/// at its start. The result is in milliseconds, like timestamps given as numbers.
+pub(crate) fn expiry_date(date: &str, now: &Zoned) -> Result<Expiry, String> {
+    let tz = now.time_zone().clone();
+    let at = if let Ok(ts) = date.parse::<Timestamp>() {
+        ts
+    } else if let Ok(dt) = date.parse::<DateTime>() {
+        dt.to_zoned(tz).map_err(|e| e.to_string())?.timestamp()
+    } else if let Ok(d) = date.parse::<Date>() {
+        d.to_zoned(tz).map_err(|e| e.to_string())?.timestamp()
+    } else {
+        return Err(format!(
+            "invalid date \"{date}\", expected e.g. 2025-07-01, 2025-07-01T18:00 or \
+             2025-07-01T18:00:00Z"
+        ));
+    };
+
+    if at <= now.timestamp() {
+        return Err(format!("{date} is in the past"));
+    }
+    at.round(Unit::Millisecond)
+        .map(Expiry::Timestamp)
+        .map_err(|e| e.to_string())
+}
+
+/// Find the next instant after `now` matching `when`
+///
+/// Accepts `[today|tomorrow|<weekday>] [HH:MM[:SS]]`, with at least one part present. A
+/// missing time defaults to midnight, and a missing day to the next time the clock reads `HH:MM`.
+pub(crate) fn expires_at(when: &str, now: &Zoned) -> Result<Zoned, String> {
+    let mut parts = when.split_whitespace();
+    let (day, time) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(first), None, None) => match first.parse::<Time>() {
+            Ok(time) => (None, Some(time)),
+            Err(_) => (Some(first), None),
+        },
+        (Some(day), Some(time), None) => (
+            Some(day),
+            Some(
+                time.parse::<Time>()
+                    .map_err(|e| format!("invalid time \"{time}\": {e}"))?,
+            ),
+        ),
+        _ => return Err("expected \"[DAY] [HH:MM]\"".to_string()),
+    };
+
+    let today = now.date();
+    let time = time.unwrap_or(Time::midnight());
+    let (date, repeat_days) = match day.map(str::to_ascii_lowercase).as_deref() {
+        None => (today, 1),
+        Some("today") => (today, 0),
+        Some("tomorrow") => (today.tomorrow().map_err(|e| e.to_string())?, 0),
+        Some(weekday) => {
+            let weekday = [
+                (&["mon", "monday"][..], Weekday::Monday),
+                (&["tue", "tues", "tuesday"], Weekday::Tuesday),
+                (&["wed", "wednesday"], Weekday::Wednesday),
+                (&["thu", "thur", "thurs", "thursday"], Weekday::Thursday),
+                (&["fri", "friday"], Weekday::Friday),
+                (&["sat", "saturday"], Weekday::Saturday),
+                (&["sun", "sunday"], Weekday::Sunday),
+            ]
+            .into_iter()
+            .find_map(|(names, wd)| names.contains(&weekday).then_some(wd))
+            .ok_or_else(|| format!("unknown day \"{weekday}\""))?;
+            let days_ahead = weekday.since(today.weekday());
+            (
+                today
+                    .checked_add(days_ahead.days())
+                    .map_err(|e| e.to_string())?,
+                7,
+            )
+        }
+    };
+
+    let mut at = date
+        .to_datetime(time)
+        .to_zoned(now.time_zone().clone())
+        .map_err(|e| e.to_string())?;
+    if at <= *now {
+        if repeat_days == 0 {
+            return Err(format!("{} is in the past", at.strftime("%F %T")));
+        }
+        at = at
+            .checked_add(repeat_days.days())
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(at)
+}
+
+/// Parse a non-zero size in bytes, with an optional binary `K`/`M`/`G` suffix
+pub(crate) fn byte_size(size: &str) -> Option<u64> {
+    let unit_start = size
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(size.len());
+    let (digits, unit) = size.split_at(unit_start);
+    let multiplier = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => Some(1),
+        "K" | "KB" | "KIB" => Some(1 << 10),
+        "M" | "MB" | "MIB" => Some(1 << 20),
+        "G" | "GB" | "GIB" => Some(1 << 30),
+        _ => None,
+    };
+
+    multiplier
+        .zip(digits.parse::<u64>().ok())
+        .and_then(|(multiplier, n)| n.checked_mul(multiplier))
+        .filter(|&bytes| bytes > 0)
+}
+
+/// Parse a non-negative number of seconds, which may be fractional
+pub(crate) fn seconds(secs: &str) -> Option<Duration> {
+    secs.parse::<f64>()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+}
+
+/// Parse a non-zero duration in whole minutes, hours, days or weeks, e.g. `90m`, `3d` or `2w`
+pub(crate) fn duration(duration: &str) -> Option<Duration> {
+    let unit_start = duration
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(duration.len());
+    let (digits, unit) = duration.split_at(unit_start);
+    let secs_per_unit = match unit.to_ascii_lowercase().as_str() {
+        "m" | "min" => Some(60),
+        "h" => Some(60 * 60),
+        "d" => Some(24 * 60 * 60),
+        "w" => Some(7 * 24 * 60 * 60),
+        _ => None,
+    };
+
+    secs_per_unit
+        .zip(digits.parse::<u64>().ok())
+        .and_then(|(secs_per_unit, n)| n.checked_mul(secs_per_unit))
+        .filter(|&secs| secs > 0)
+        .map(Duration::from_secs)
+}
+
+/// Parse a curl-style `HOST:PORT:ADDR` address override, with IPv6 addresses optionally in
+/// brackets
+pub(crate) fn resolve(resolve: &str) -> Result<(String, SocketAddr), String> {
+    let mut parts = resolve.splitn(3, ':');
+    let (Some(host), Some(port), Some(addr)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(format!(
+            "invalid address override '{resolve}', expected HOST:PORT:ADDR"
+        ));
+    };
+    if host.is_empty() {
+        return Err(format!("address override '{resolve}' has no host"));
+    }
+    let port: u16 = port
+        .parse()
+        .map_err(|e| format!("invalid port in address override '{resolve}': {e}"))?;
+    let addr = addr
+        .strip_prefix('[')
+        .and_then(|a| a.strip_suffix(']'))
+        .unwrap_or(addr);
+    let addr: IpAddr = addr
+        .parse()
+        .map_err(|e| format!("invalid address in address override '{resolve}': {e}"))?;
+    Ok((host.to_ascii_lowercase(), SocketAddr::new(addr, port)))
+}
+
+/// Parse a `KEY=VALUE` configuration override, with a dotted key such as `output.json`
+///
+/// The value is TOML (`true`, `24`, `["a", "b"]`, `"quoted"`), or else taken as a string.
+pub(crate) fn with(with: &str) -> Result<(Vec<String>, toml::Value), String> {
+    let Some((key, value)) = with.split_once('=') else {
+        return Err(format!("invalid override '{with}', expected KEY=VALUE"));
+    };
+    let key: Vec<String> = key.split('.').map(|part| part.trim().to_string()).collect();
+    if key.iter().any(String::is_empty) {
+        return Err(format!("invalid key in override '{with}'"));
+    }
+    let parsed = toml::from_str::<toml::Table>(&format!("value = {value}"))
+        .ok()
+        .filter(|table| table.len() == 1)
+        .and_then(|mut table| table.remove("value"));
+    // No setting is a float or a date, so `1.2` is a TLS version rather than a number
+    let value = match parsed {
+        Some(toml::Value::Float(_) | toml::Value::Datetime(_)) | None => {
+            toml::Value::String(value.to_string())
+        }
+        Some(parsed) => parsed,
+    };
+    Ok((key, value))
+}
+
+/// Check a file extension, given with or without its leading dot, returning it without
+pub(crate) fn extension(ext: &str) -> Result<String, String> {
+    let bare = ext.strip_prefix('.').unwrap_or(ext);
+    if bare.is_empty() || bare.starts_with('.') || bare.ends_with('.') {
+        Err(format!("invalid extension '{ext}'"))
+    } else if bare.contains(['/', '\\']) {
+        Err(format!("extension '{ext}' contains a path separator"))
+    } else {
+        Ok(bare.to_string())
+    }
+}
+
+/// Parse a TLS version such as `1.2`
+pub(crate) fn tls_version(version: &str) -> Result<tls::Version, String> {
+    match version.strip_prefix("TLSv").unwrap_or(version) {
+        "1.0" => Ok(tls::Version::TLS_1_0),
+        "1.1" => Ok(tls::Version::TLS_1_1),
+        "1.2" => Ok(tls::Version::TLS_1_2),
+        "1.3" => Ok(tls::Version::TLS_1_3),
+        _ => Err(format!(
+            "invalid TLS version {version:?}, expected 1.0, 1.1, 1.2 or 1.3"
+        )),
+    }
+}
+
+/// Check that a token looks like an X-Token (URL-safe base64), describing the problem if not
+pub(crate) fn token(token: &str) -> Result<(), String> {
+    if let Some(c) = token
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || *c == '-' || *c == '_'))
+    {
+        Err(format!("unexpected character {c:?}"))
+    } else if token.len() < MIN_TOKEN_LEN {
+        Err(format!(
+            "expected at least {MIN_TOKEN_LEN} characters, got {}",
+            token.len()
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Parse a history reference like `@3` or `@notes`, or `None` if `target` isn't one
+pub(crate) fn history_ref(target: &str) -> Option<Result<ManageTarget, String>> {
+    let name = target.strip_prefix('@')?;
+    Some(
+        if !name.is_empty() && name.bytes().all(|b| b.is_ascii_digit()) {
+            name.parse()
+                .map(ManageTarget::Id)
+                .map_err(|e: ParseIntError| e.to_string())
+        } else {
+            alias(name).map(|()| ManageTarget::Alias(name.to_string()))
+        },
+    )
+}
+
+/// Check that `name` can be used as an alias, describing the problem if not
+pub(crate) fn alias(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        Err("alias is empty".to_string())
+    } else if name.bytes().all(|b| b.is_ascii_digit()) {
+        Err("alias cannot be a number, since @N refers to history IDs".to_string())
+    } else if name.starts_with('-') {
+        Err("alias cannot start with '-'".to_string())
+    } else if let Some(c) = name
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || *c == '-' || *c == '_'))
+    {
+        Err(format!("unexpected character {c:?}"))
+    } else {
+        Ok(())
+    }
+}
+
+/// Parse a URL, requiring that it belongs to `host`
+pub(crate) fn envs_url(url: &str, host: &Url) -> Result<Url, EnvsUrlError> {
+    let url = Url::from_str(url).map_err(EnvsUrlError::Invalid)?;
+    if url.scheme() == host.scheme()
+        && url.host_str() == host.host_str()
+        && url.port_or_known_default() == host.port_or_known_default()
+    {
+        Ok(url)
+    } else {
+        Err(EnvsUrlError::NotEnvs(url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jiff::civil::date;
+    use jiff::tz::{TimeZone, offset};
+    use proptest::prelude::*;
+
+    use super::*;
+
+    /// The default host
+    fn envs() -> Url {
+        Url::parse(crate::ENVS).unwrap()
+    }
+
+    /// A fixed "now": Wednesday 2025-02-12, 10:30 at UTC-5
+    fn now() -> Zoned {
+        date(2025, 2, 12)
+            .at(10, 30, 0, 0)
+            .to_zoned(TimeZone::fixed(offset(-5)))
+            .unwrap()
+    }
+
+    #[test]
+    fn existing_file_is_file_target() {
+        assert!(matches!(target("Cargo.toml"), Ok(Target::File(_))));
+    }
+
+    #[test]
+    fn existing_dir_is_dir_target() {
+        assert!(matches!(target("src"), Ok(Target::Dir(_))));
+    }
+
+    #[test]
+    fn dash_is_stdin_target() {
+        assert!(matches!(target("-"), Ok(Target::Stdin)));
+    }
+
+    #[test]
+    fn target_list_keeps_newlines_when_nul_delimited() {
+        let list = "a b.txt\0with\nnewline\0\0";
+        assert_eq!(
+            target_list(list, true).collect::<Vec<_>>(),
+            ["a b.txt", "with\nnewline"]
+        );
+        assert_eq!(
+            target_list("one\r\ntwo\n\n", false).collect::<Vec<_>>(),
+            ["one", "two"]
+        );
+    }
+
+    #[test]
+    fn glob_expands_to_matches() {
+        let Ok(Target::Glob { matches, .. }) = target("src/*.rs") else {
+            panic!("expected glob target")
+        };
+        assert!(
+            matches
+                .iter()
+                .any(|m| matches!(m, Target::File(f) if f.ends_with("main.rs")))
+        );
+        assert!(target("src/*.nothing").is_err());
+    }
+
+    #[test]
+    fn verbatim_prefixes_are_stripped() {
+        assert_eq!(strip_verbatim(r"\\?\C:\logs\a.txt"), r"C:\logs\a.txt");
+        assert_eq!(
+            strip_verbatim(r"\\?\UNC\server\share\a.txt"),
+            r"\\server\share\a.txt"
+        );
+        assert_eq!(
+            strip_verbatim(r"\\server\share\a.txt"),
+            r"\\server\share\a.txt"
+        );
+        // Other verbatim forms (e.g. volume GUIDs) have no simpler equivalent
+        assert_eq!(strip_verbatim(r"\\?\Volume{x}\a"), r"\\?\Volume{x}\a");
+    }
+
+    #[test]
+    fn missing_verbatim_path_is_not_a_glob() {
+        assert!(target(r"\\?\C:\does\not\exist.txt").is_err());
+        assert!(target(r"\\?\UNC\server\share\exist.txt").is_err());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn long_and_verbatim_paths_are_files() {
+        let dir = std::env::temp_dir().join("envsh-".to_string() + &"d".repeat(120));
+        let nested = dir.join("e".repeat(120));
+        std::fs::create_dir_all(&nested).unwrap();
+        let file = nested.join("long.txt");
+        std::fs::write(&file, "x").unwrap();
+        assert!(file.as_os_str().len() > 260);
+
+        let plain = file.to_str().unwrap();
+        assert!(matches!(target(plain), Ok(Target::File(_))));
+        let verbatim = file.canonicalize().unwrap();
+        assert!(matches!(
+            target(verbatim.to_str().unwrap()),
+            Ok(Target::File(_))
+        ));
+        let glob = nested.join("*.txt");
+        assert!(matches!(
+            target(glob.to_str().unwrap()),
+            Ok(Target::Glob { .. })
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn missing_relative_path_is_not_a_url() {
+        assert!(target("does/not/exist.txt").is_err());
+    }
+
+    #[test]
+    fn unicode_domain_is_punycoded() {
+        let Ok(Target::Url(url)) = target("https://bücher.example/straße?q=ü") else {
+            panic!("expected URL target")
+        };
+        assert_eq!(url.host_str(), Some("xn--bcher-kva.example"));
+        assert_eq!(url.path(), "/stra%C3%9Fe");
+    }
+
+    #[test]
+    fn expiry_boundary() {
+        assert!(matches!(
+            expiry(Expiry::MAX_EXPIRY_HOURS),
+            Ok(Expiry::Hours(Expiry::MAX_EXPIRY_HOURS))
+        ));
+        assert!(matches!(
+            expiry(Expiry::MAX_EXPIRY_HOURS + 1),
+            Ok(Expiry::Timestamp(_))
+        ));
+        assert!(expiry(-1).is_err());
+        assert!(expiry(i64::MAX).is_err());
+    }
+
+    #[test]
+    fn durations_become_expiries() {
+        let now = Timestamp::from_second(1_700_000_000).unwrap();
+        let hours = |h: u64| Duration::from_secs(h * 3600);
+        assert!(matches!(expiry_in(hours(12), now), Ok(Expiry::Hours(12))));
+        assert!(matches!(
+            expiry_in(Duration::from_secs(90 * 60), now),
+            Ok(Expiry::Timestamp(ts)) if ts == now + 90.minutes()
+        ));
+        assert!(matches!(expiry_in(hours(720), now), Ok(Expiry::Hours(720))));
+        assert!(expiry_in(hours(721), now).is_err());
+    }
+
+    #[test]
+    fn expires_at_weekday_later_this_week() {
+        let at = expires_at("friday 09:00", &now()).unwrap();
+        assert_eq!(at.datetime(), date(2025, 2, 14).at(9, 0, 0, 0));
+    }
+
+    #[test]
+    fn expires_at_same_weekday_passed_is_next_week() {
+        let at = expires_at("wed 09:00", &now()).unwrap();
+        assert_eq!(at.date(), date(2025, 2, 19));
+    }
+
+    #[test]
+    fn expires_at_time_only_rolls_over() {
+        assert_eq!(
+            expires_at("11:00", &now()).unwrap().date(),
+            date(2025, 2, 12)
+        );
+        assert_eq!(
+            expires_at("10:00", &now()).unwrap().date(),
+            date(2025, 2, 13)
+        );
+    }
+
+    #[test]
+    fn expiry_dates() {
+        let at = |date| match expiry_date(date, &now()) {
+            Ok(Expiry::Timestamp(ts)) => ts.to_string(),
+            other => panic!("expected a timestamp, got {other:?}"),
+        };
+        assert_eq!(at("2025-07-01T18:00:00Z"), "2025-07-01T18:00:00Z");
+        assert_eq!(at("2025-02-12T12:00"), "2025-02-12T17:00:00Z");
+        assert_eq!(at("2025-03-01"), "2025-03-01T05:00:00Z");
+        assert!(expiry_date("2025-02-12", &now()).is_err());
+        assert!(expiry_date("2025-13-01", &now()).is_err());
+    }
+
+    #[test]
+    fn expires_at_rejects_past_and_garbage() {
+        assert!(expires_at("today 08:00", &now()).is_err());
+        assert!(expires_at("someday", &now()).is_err());
+        assert!(expires_at("sunflower 09:00", &now()).is_err());
+        assert!(expires_at("mond", &now()).is_err());
+        assert!(expires_at("mon 25:00", &now()).is_err());
+        assert!(expires_at("mon 09:00 extra", &now()).is_err());
+    }
+
+    #[test]
+    fn byte_size_units() {
+        assert_eq!(byte_size("512"), Some(512));
+        assert_eq!(byte_size("64K"), Some(64 << 10));
+        assert_eq!(byte_size("1MiB"), Some(1 << 20));
+        assert_eq!(byte_size("2gb"), Some(2 << 30));
+        assert_eq!(byte_size("0"), None);
+        assert_eq!(byte_size("4X"), None);
+        assert_eq!(byte_size("M"), None);
+    }
+
+    #[test]
+    fn seconds_allow_fractions() {
+        assert_eq!(seconds("2"), Some(Duration::from_secs(2)));
+        assert_eq!(seconds("0.25"), Some(Duration::from_millis(250)));
+        assert_eq!(seconds("0"), Some(Duration::ZERO));
+        assert_eq!(seconds("-1"), None);
+        assert_eq!(seconds("inf"), None);
+        assert_eq!(seconds("1s"), None);
+    }
+
+    #[test]
+    fn durations_need_a_unit() {
+        assert_eq!(duration("90m"), Some(Duration::from_secs(90 * 60)));
+        assert_eq!(duration("12H"), Some(Duration::from_secs(12 * 3600)));
+        assert_eq!(duration("2w"), Some(Duration::from_secs(14 * 86400)));
+        assert_eq!(duration("7"), None);
+        assert_eq!(duration("0d"), None);
+        assert_eq!(duration("1.5h"), None);
+    }
+
+    #[test]
+    fn resolve_overrides() {
+        assert_eq!(
+            resolve("Envs.sh:443:192.0.2.7"),
+            Ok(("envs.sh".to_string(), "192.0.2.7:443".parse().unwrap()))
+        );
+        assert_eq!(
+            resolve("envs.sh:443:[2001:db8::1]"),
+            Ok(("envs.sh".to_string(), "[2001:db8::1]:443".parse().unwrap()))
+        );
+        assert_eq!(resolve("envs.sh:443:2001:db8::1").unwrap().1.port(), 443);
+        assert!(resolve("envs.sh:192.0.2.7").is_err());
+        assert!(resolve("envs.sh:https:192.0.2.7").is_err());
+        assert!(resolve(":443:192.0.2.7").is_err());
+        assert!(resolve("envs.sh:443:staging.envs.sh").is_err());
+    }
+
+    #[test]
+    fn config_overrides() {
+        let string = |s: &str| toml::Value::String(s.to_string());
+        assert_eq!(
+            with("host=https://paste.example.org"),
+            Ok((
+                vec!["host".to_string()],
+                string("https://paste.example.org")
+            ))
+        );
+        assert_eq!(
+            with("output.json=true"),
+            Ok((
+                vec!["output".to_string(), "json".to_string()],
+                toml::Value::Boolean(true)
+            ))
+        );
+        assert_eq!(with("expires=24").unwrap().1, toml::Value::Integer(24));
+        assert_eq!(with("tls.min_version=1.2").unwrap().1, string("1.2"));
+        assert_eq!(with("sound.success=").unwrap().1, string(""));
+        assert_eq!(
+            with(r#"resolve=["envs.sh:443:192.0.2.7"]"#).unwrap().1,
+            toml::Value::Array(vec![string("envs.sh:443:192.0.2.7")])
+        );
+        assert!(with("host").is_err());
+        assert!(with("output..json=true").is_err());
+        assert!(with("=true").is_err());
+    }
+
+    #[test]
+    fn extensions() {
+        assert_eq!(extension("txt"), Ok("txt".to_string()));
+        assert_eq!(extension(".txt"), Ok("txt".to_string()));
+        assert_eq!(extension("tar.gz"), Ok("tar.gz".to_string()));
+        assert!(extension(".").is_err());
+        assert!(extension("..txt").is_err());
+        assert!(extension("txt/../x").is_err());
+    }
+
+    #[test]
+    fn tls_versions() {
+        assert_eq!(tls_version("1.2"), Ok(tls::Version::TLS_1_2));
+        assert_eq!(tls_version("TLSv1.3"), Ok(tls::Version::TLS_1_3));
+        assert!(tls_version("1.4").is_err());
+        assert!(tls_version("1").is_err());
+        assert!(tls_version("SSLv3").is_err());
+    }
+
+    #[test]
+    fn history_refs() {
+        assert!(matches!(history_ref("@3"), Some(Ok(ManageTarget::Id(3)))));
+        assert!(matches!(history_ref("@12"), Some(Ok(ManageTarget::Id(12)))));
+        assert!(matches!(
+            history_ref("@notes"),
+            Some(Ok(ManageTarget::Alias(name))) if name == "notes"
+        ));
+        assert!(matches!(history_ref("@"), Some(Err(_))));
+        assert!(matches!(history_ref("@no/pe"), Some(Err(_))));
+        assert!(matches!(history_ref("@99999999999999999999"), Some(Err(_))));
+        assert!(history_ref("3").is_none());
+        assert!(history_ref("https://envs.sh/@3").is_none());
+    }
+
+    #[test]
+    fn alias_names() {
+        assert!(alias("notes").is_ok());
+        assert!(alias("build-log_2").is_ok());
+        assert!(alias("2fa").is_ok());
+        assert!(alias("").is_err());
+        assert!(alias("42").is_err());
+        assert!(alias("-v").is_err());
+        assert!(alias("a b").is_err());
+    }
+
+    #[test]
+    fn envs_url_requires_https_envs() {
+        assert!(envs_url("https://envs.sh/abc.txt", &envs()).is_ok());
+        assert!(matches!(
+            envs_url("http://envs.sh/abc.txt", &envs()),
+            Err(EnvsUrlError::NotEnvs(_))
+        ));
+        assert!(matches!(
+            envs_url("https://example.com/abc.txt", &envs()),
+            Err(EnvsUrlError::NotEnvs(_))
+        ));
+        assert!(matches!(
+            envs_url("envs.sh/abc.txt", &envs()),
+            Err(EnvsUrlError::Invalid(_))
+        ));
+    }
+
+    proptest! {
+        #[test]
+        fn hours_up_to_max(h in 0..=Expiry::MAX_EXPIRY_HOURS) {
+            prop_assert!(matches!(expiry(h), Ok(Expiry::Hours(hours)) if hours == h));
+        }
+
+        #[test]
+        fn larger_values_are_millis(
+            ms in (Expiry::MAX_EXPIRY_HOURS + 1)..=Timestamp::MAX.as_millisecond()
+        ) {
+            prop_assert!(
+                matches!(expiry(ms), Ok(Expiry::Timestamp(ts)) if ts.as_millisecond() == ms)
+            );
+        }
+
+        #[test]
+        fn expires_at_is_within_a_week(
+            day in prop::sample::select(&["mon", "tuesday", "Wed", "thu", "fri", "sat", "sunday"][..]),
+            hour in 0..24i8,
+            minute in 0..60i8,
+        ) {
+            let now = now();
+            let when = format!("{day} {hour:02}:{minute:02}");
+            let at = expires_at(&when, &now).unwrap();
+            prop_assert!(at > now);
+            prop_assert!(now.until(&at).unwrap().total(jiff::Unit::Hour).unwrap() <= 7.0 * 24.0);
+            prop_assert_eq!((at.hour(), at.minute()), (hour, minute));
+        }
+
+        #[test]
+        fn byte_size_kibibytes(n in 1u64..(1 << 40)) {
+            prop_assert_eq!(byte_size(&format!("{n}K")), Some(n << 10));
+        }
+
+        #[test]
+        fn urlsafe_tokens_accepted(t in "[A-Za-z0-9_-]{16,64}") {
+            prop_assert!(token(&t).is_ok());
+        }
+
+        #[test]
+        fn tokens_with_other_chars_rejected(
+            prefix in "[A-Za-z0-9]{8}",
+            bad in "[^A-Za-z0-9_-]",
+            suffix in "[A-Za-z0-9]{8}",
+        ) {
+            let t = format!("{prefix}{bad}{suffix}");
+            prop_assert!(token(&t).is_err());
+        }
+
+        #[test]
+        fn weird_targets_never_panic(s in "\\PC*") {
+            let _ = target(&s);
+        }
+
+        #[test]
+        fn unicode_paths_survive_as_urls(path in "[\\p{L}\\p{N}/_.~-]{0,40}") {
+            let url = envs_url(&format!("https://envs.sh/{path}"), &envs()).unwrap();
+            prop_assert_eq!(url.domain(), Some("envs.sh"));
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! XDG base directories used by envsh, and the paths Termux on Android adds
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use crate::error::{EnvshError, Result};
+
+/// Whether envsh is running inside Termux on Android
+pub(crate) fn is_termux() -> bool {
+    env::var_os("TERMUX_VERSION").is_some()
+        || env::var("PREFIX").is_ok_and(|prefix| prefix.contains("com.termux"))
+}
+
+/// `$HOME`, or under Termux the app's home directory next to `$PREFIX`, since programs started
+/// by Android (like the share sheet hook) may run without it
+fn home() -> Option<PathBuf> {
+    env::var_os("HOME").map(PathBuf::from).or_else(|| {
+        is_termux()
+            .then(|| env::var_os("PREFIX"))
+            .flatten()
+            .map(|prefix| Path::new(&prefix).with_file_name("home"))
+    })
+}
+
+/// `$XDG_DATA_HOME`, defaulting to `~/.local/share`
+pub(crate) fn data_home() -> Result<PathBuf> {
+    env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home().map(|home| home.join(".local/share")))
+        .ok_or_else(|| {
+            EnvshError::Usage(
+                "could not determine data directory, set $HOME or $XDG_DATA_HOME".to_string(),
+            )
+        })
+}
+
+/// `$XDG_CONFIG_HOME`, defaulting to `~/.config`
+pub(crate) fn config_home() -> Result<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home().map(|home| home.join(".config")))
+        .ok_or_else(|| {
+            EnvshError::Usage(
+                "could not determine config directory, set $HOME or $XDG_CONFIG_HOME".to_string(),
+            )
+        })
+}
+
+/// `$XDG_CACHE_HOME`, defaulting to `~/.cache`
+pub(crate) fn cache_home() -> Result<PathBuf> {
+    env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home().map(|home| home.join(".cache")))
+        .ok_or_else(|| {
+            EnvshError::Usage(
+                "could not determine cache directory, set $HOME or $XDG_CACHE_HOME".to_string(),
+            )
+        })
+}
+
+/// Directory for envsh's configuration
+pub(crate) fn config_dir() -> Result<PathBuf> {
+    Ok(config_home()?.join("envsh"))
+}
+
+/// Directory for files envsh can recreate, like when it last showed a notice
+pub(crate) fn cache_dir() -> Result<PathBuf> {
+    Ok(cache_home()?.join("envsh"))
+}
+
+/// Directory for envsh's own data files
+pub(crate) fn data_dir() -> Result<PathBuf> {
+    Ok(data_home()?.join("envsh"))
+}
+
+/// Under Termux, `path` with a leading `~` or `$PREFIX` expanded, as Android shortcuts and
+/// widgets pass paths without a shell to expand them
+pub(crate) fn expand_termux(path: &str) -> Option<PathBuf> {
+    if !is_termux() {
+        return None;
+    }
+    let (base, rest) = if let Some(rest) = path.strip_prefix('~') {
+        (home()?, rest)
+    } else if let Some(rest) = path.strip_prefix("$PREFIX") {
+        (PathBuf::from(env::var_os("PREFIX")?), rest)
+    } else {
+        return None;
+    };
+    match rest.strip_prefix('/') {
+        Some(rest) => Some(base.join(rest)),
+        None if rest.is_empty() => Some(base),
+        // `~user` isn't supported, and `$PREFIXES` is another variable
+        None => None,
+    }
+}
+
+/// Whether `path` is in Android's shared storage, which Termux can only read after
+/// `termux-setup-storage` was run
+pub(crate) fn is_shared_storage(path: &Path) -> bool {
+    path.starts_with("/storage")
+        || path.starts_with("/sdcard")
+        || home().is_some_and(|home| path.starts_with(home.join("storage")))
+}
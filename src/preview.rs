@@ -0,0 +1,75 @@
+//! Preview text before uploading it
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Write};
+use std::path::Path;
+
+use crate::error::{Context, Result};
+
+/// How much of a file to inspect when deciding whether it is text
+const SNIFF_BYTES: u64 = 8192;
+
+/// Words that suggest a line contains something that shouldn't be shared
+const SENSITIVE_MARKERS: &[&str] = &[
+    "password",
+    "passwd",
+    "secret",
+    "token",
+    "api_key",
+    "apikey",
+    "private key",
+    "authorization:",
+];
+
+/// Show the first `lines` lines of `file` and ask whether to continue uploading
+///
+/// Lines that look like they contain credentials are highlighted when stdout is a terminal.
+pub(crate) fn confirm_upload(file: &Path, lines: usize) -> Result<bool> {
+    let failed = || format!("failed to read {} for preview", file.display());
+    let mut sniff = vec![];
+    File::open(file)
+        .and_then(|f| f.take(SNIFF_BYTES).read_to_end(&mut sniff))
+        .context(failed())?;
+
+    let color = io::stdout().is_terminal();
+    if sniff.contains(&0) {
+        let len = file.metadata().context(failed())?.len();
+        println!("<binary file, {len} bytes>");
+    } else {
+        let reader = BufReader::new(File::open(file).context(failed())?);
+        let mut shown = 0;
+        for (n, line) in reader
+            .lines()
+            .map_while(io::Result::ok)
+            .take(lines)
+            .enumerate()
+        {
+            shown += 1;
+            let lower = line.to_ascii_lowercase();
+            let sensitive = SENSITIVE_MARKERS.iter().any(|m| lower.contains(m));
+            match (color, sensitive) {
+                (true, true) => println!("\x1b[2m{:>4} │\x1b[0m \x1b[1;31m{line}\x1b[0m", n + 1),
+                (true, false) => println!("\x1b[2m{:>4} │\x1b[0m {line}", n + 1),
+                (false, true) => println!("{:>4} ! {line}", n + 1),
+                (false, false) => println!("{:>4} │ {line}", n + 1),
+            }
+        }
+        if shown == lines {
+            println!("     ┆ …");
+        }
+    }
+
+    ask(&format!("Upload {}?", file.display()))
+}
+
+/// Ask a yes/no `question` on stderr, taking anything but yes from stdin as no
+pub(crate) fn ask(question: &str) -> Result<bool> {
+    eprint!("{question} [y/N] ");
+    io::stderr().flush().unwrap();
+    let mut answer = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .context("failed to read answer")?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
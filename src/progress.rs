@@ -0,0 +1,195 @@
+//! Progress line for uploads, drawn on stderr while the file is read into the request
+
+use std::io::{self, IsTerminal, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::bench::format_bytes;
+
+/// How long an upload runs before the bar appears, so small uploads don't flicker
+const SHOW_AFTER: Duration = Duration::from_millis(500);
+
+/// Minimum time between redraws
+const REDRAW_EVERY: Duration = Duration::from_millis(100);
+
+/// Width of the bar itself, in characters
+const BAR_WIDTH: usize = 24;
+
+/// Shows how far through its stages an upload is, if enabled
+///
+/// Contents pass through stages (compressing, encrypting, ..., uploading) as they stream, each
+/// wrapping the reader of the one before. The line describes the first stage that hasn't
+/// reached the end of its input, since that is the one holding the rest up.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Progress(Option<Arc<Mutex<State>>>);
+
+/// Progress through the current contents
+#[derive(Debug)]
+struct State {
+    /// Stages, in the order contents pass through them
+    stages: Vec<Stage>,
+    /// Incremented on every restart, so readers of earlier contents are ignored
+    generation: u64,
+    /// When reading started
+    start: Instant,
+    /// When the line was last drawn, if it was
+    drawn: Option<Instant>,
+}
+
+/// One step contents pass through
+#[derive(Debug)]
+struct Stage {
+    /// What the stage does, e.g. "compressing"
+    label: &'static str,
+    /// Bytes read into it so far
+    read: u64,
+    /// Size of its input, if known
+    total: Option<u64>,
+    /// Whether its input ended
+    done: bool,
+}
+
+impl Progress {
+    /// A progress line, drawn only if `enabled` and both stdout and stderr are terminals
+    pub(crate) fn new(enabled: bool) -> Self {
+        let enabled = enabled && io::stdout().is_terminal() && io::stderr().is_terminal();
+        Self(enabled.then(|| {
+            Arc::new(Mutex::new(State {
+                stages: vec![],
+                generation: 0,
+                start: Instant::now(),
+                drawn: None,
+            }))
+        }))
+    }
+
+    /// Forget the stages of earlier contents, before new contents are read
+    pub(crate) fn restart(&self) {
+        if let Some(state) = &self.0 {
+            let mut state = state.lock().unwrap();
+            state.stages.clear();
+            state.generation += 1;
+            state.start = Instant::now();
+        }
+    }
+
+    /// Wrap `reader`, the input of stage `label` of `total` bytes if known, so reading from it
+    /// advances that stage
+    pub(crate) fn stage(
+        &self,
+        label: &'static str,
+        reader: Box<dyn Read + Send>,
+        total: Option<u64>,
+    ) -> Box<dyn Read + Send> {
+        let Some(state) = &self.0 else {
+            return reader;
+        };
+        let mut locked = state.lock().unwrap();
+        locked.stages.push(Stage {
+            label,
+            read: 0,
+            total,
+            done: false,
+        });
+        Box::new(Tracked {
+            inner: reader,
+            state: state.clone(),
+            generation: locked.generation,
+            stage: locked.stages.len() - 1,
+        })
+    }
+
+    /// Erase the line, once the upload is done
+    pub(crate) fn finish(&self) {
+        if let Some(state) = &self.0
+            && state.lock().unwrap().drawn.take().is_some()
+        {
+            eprint!("\r\x1b[K");
+        }
+    }
+}
+
+impl State {
+    /// Redraw the line if it is due
+    fn tick(&mut self) {
+        let now = Instant::now();
+        let elapsed = now - self.start;
+        if elapsed < SHOW_AFTER || self.drawn.is_some_and(|at| now - at < REDRAW_EVERY) {
+            return;
+        }
+        let Some(stage) = self
+            .stages
+            .iter()
+            .find(|stage| !stage.done)
+            .or(self.stages.last())
+        else {
+            return;
+        };
+        self.drawn = Some(now);
+
+        let speed = stage.read as f64 / elapsed.as_secs_f64();
+        let mut line = format!("{}… ", stage.label);
+        if let Some(total) = stage.total.filter(|&t| t > 0) {
+            let fraction = (stage.read as f64 / total as f64).min(1.0);
+            let filled = (fraction * BAR_WIDTH as f64) as usize;
+            line.push_str(&format!(
+                "{:>3.0}% [{}{}]  {} / {}",
+                fraction * 100.0,
+                "#".repeat(filled),
+                "-".repeat(BAR_WIDTH - filled),
+                format_bytes(stage.read as f64),
+                format_bytes(total as f64)
+            ));
+            line.push_str(&format!("  {}/s", format_bytes(speed)));
+            if speed > 0.0 {
+                let eta = total.saturating_sub(stage.read) as f64 / speed;
+                line.push_str(&format!("  ETA {}", format_eta(eta)));
+            }
+        } else {
+            line.push_str(&format!(
+                "{}  {}/s",
+                format_bytes(stage.read as f64),
+                format_bytes(speed)
+            ));
+        }
+        eprint!("\r{line}\x1b[K");
+        let _ = io::stderr().flush();
+    }
+}
+
+/// Format a number of seconds left as e.g. "42s" or "3m05s"
+fn format_eta(secs: f64) -> String {
+    let secs = secs.ceil() as u64;
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+/// Reader advancing one stage of a [`Progress`]
+struct Tracked {
+    /// Wrapped reader
+    inner: Box<dyn Read + Send>,
+    /// Shared progress
+    state: Arc<Mutex<State>>,
+    /// Generation of `state` the stage belongs to
+    generation: u64,
+    /// Index of the stage in `state`
+    stage: usize,
+}
+
+impl Read for Tracked {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        let mut state = self.state.lock().unwrap();
+        if state.generation != self.generation {
+            return Ok(n);
+        }
+        let stage = &mut state.stages[self.stage];
+        stage.read += n as u64;
+        stage.done |= n == 0 && !buf.is_empty();
+        state.tick();
+        Ok(n)
+    }
+}
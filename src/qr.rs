@@ -0,0 +1,520 @@
+//! QR codes of resulting URLs, for `--qr` and `envsh qr-sheet`
+//!
+//! A minimal encoder: byte mode at error correction level M, in the smallest version that fits,
+//! with the mask chosen by the standard penalty rules. Codes are drawn with half-block characters
+//! so each line of text holds two rows of modules.
+
+/// Error correction codewords per block at level M, by version
+const ECC_PER_BLOCK: [usize; 41] = [
+    0, 10, 16, 26, 18, 24, 16, 18, 22, 22, 26, 30, 22, 22, 24, 24, 28, 28, 26, 26, 26, 26, 28, 28,
+    28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28,
+];
+
+/// Error correction blocks at level M, by version
+const BLOCKS: [usize; 41] = [
+    0, 1, 1, 1, 2, 2, 4, 4, 4, 5, 5, 5, 8, 9, 9, 10, 10, 11, 13, 14, 16, 17, 17, 18, 20, 21, 23,
+    25, 26, 28, 29, 31, 33, 35, 37, 38, 40, 43, 45, 47, 49,
+];
+
+/// Format bits identifying level M, before the mask number is appended
+const LEVEL_M: u32 = 0b00;
+
+/// Light modules kept around the code so scanners can find its edges
+const QUIET_ZONE: usize = 2;
+
+/// An encoded QR code
+#[derive(Debug)]
+pub(crate) struct QrCode {
+    /// Width and height in modules
+    size: usize,
+    /// Whether each module is dark, row by row
+    modules: Vec<bool>,
+    /// Whether each module belongs to a function pattern, which masks leave alone
+    function: Vec<bool>,
+}
+
+impl QrCode {
+    /// Encode `data`, or `None` if it is too long for any version
+    pub(crate) fn encode(data: &[u8]) -> Option<Self> {
+        let version = (1..=40).find(|&v| {
+            let count_bits = if v <= 9 { 8 } else { 16 };
+            4 + count_bits + data.len() * 8 <= data_codewords(v) * 8
+        })?;
+
+        let mut bits = Bits::default();
+        bits.push(0b0100, 4);
+        bits.push(data.len() as u32, if version <= 9 { 8 } else { 16 });
+        for &byte in data {
+            bits.push(byte.into(), 8);
+        }
+        let capacity = data_codewords(version) * 8;
+        bits.push(0, (capacity - bits.len).min(4));
+        bits.push(0, (8 - bits.len % 8) % 8);
+        for pad in [0xEC, 0x11].into_iter().cycle() {
+            if bits.len == capacity {
+                break;
+            }
+            bits.push(pad, 8);
+        }
+
+        let size = version * 4 + 17;
+        let mut code = Self {
+            size,
+            modules: vec![false; size * size],
+            function: vec![false; size * size],
+        };
+        code.draw_function_patterns(version);
+        code.draw_codewords(&add_ecc_and_interleave(&bits.bytes, version));
+
+        let mask = (0..8)
+            .min_by_key(|&mask| {
+                code.apply_mask(mask);
+                code.draw_format(mask);
+                let penalty = code.penalty();
+                code.apply_mask(mask);
+                penalty
+            })
+            .unwrap();
+        code.apply_mask(mask);
+        code.draw_format(mask);
+        Some(code)
+    }
+
+    /// Width and height in modules, without the quiet zone
+    pub(crate) fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Whether the module at column `x`, row `y` is dark
+    pub(crate) fn get(&self, x: usize, y: usize) -> bool {
+        self.modules[y * self.size + x]
+    }
+
+    /// Set the module at column `x`, row `y` as part of a function pattern
+    fn set_function(&mut self, x: usize, y: usize, dark: bool) {
+        self.modules[y * self.size + x] = dark;
+        self.function[y * self.size + x] = true;
+    }
+
+    /// Draw the finder, timing and alignment patterns and the version information, and reserve
+    /// the format information area
+    fn draw_function_patterns(&mut self, version: usize) {
+        let size = self.size;
+        for i in 0..size {
+            self.set_function(6, i, i % 2 == 0);
+            self.set_function(i, 6, i % 2 == 0);
+        }
+
+        for (x, y) in [(3, 3), (size - 4, 3), (3, size - 4)] {
+            for dy in -4..=4_isize {
+                for dx in -4..=4_isize {
+                    let (Some(x), Some(y)) = (x.checked_add_signed(dx), y.checked_add_signed(dy))
+                    else {
+                        continue;
+                    };
+                    if x < size && y < size {
+                        let distance = dx.abs().max(dy.abs());
+                        self.set_function(x, y, distance != 2 && distance != 4);
+                    }
+                }
+            }
+        }
+
+        let positions = alignment_positions(version);
+        let last = positions.len().saturating_sub(1);
+        for (i, &x) in positions.iter().enumerate() {
+            for (j, &y) in positions.iter().enumerate() {
+                // These would overlap the finder patterns
+                if (i, j) == (0, 0) || (i, j) == (0, last) || (i, j) == (last, 0) {
+                    continue;
+                }
+                for dy in -2..=2_isize {
+                    for dx in -2..=2_isize {
+                        let distance = dx.abs().max(dy.abs());
+                        self.set_function(
+                            x.wrapping_add_signed(dx),
+                            y.wrapping_add_signed(dy),
+                            distance != 1,
+                        );
+                    }
+                }
+            }
+        }
+
+        // Drawn for real once the mask is chosen
+        self.draw_format(0);
+
+        if version >= 7 {
+            let mut rem = version as u32;
+            for _ in 0..12 {
+                rem = (rem << 1) ^ ((rem >> 11) * 0x1F25);
+            }
+            let bits = (version as u32) << 12 | rem;
+            for i in 0..18 {
+                let dark = bit(bits, i);
+                let (a, b) = (size - 11 + i % 3, i / 3);
+                self.set_function(a, b, dark);
+                self.set_function(b, a, dark);
+            }
+        }
+    }
+
+    /// Draw both copies of the format information for `mask`
+    fn draw_format(&mut self, mask: u32) {
+        let size = self.size;
+        let bits = format_bits(mask);
+        for i in 0..6 {
+            self.set_function(8, i, bit(bits, i));
+        }
+        self.set_function(8, 7, bit(bits, 6));
+        self.set_function(8, 8, bit(bits, 7));
+        self.set_function(7, 8, bit(bits, 8));
+        for i in 9..15 {
+            self.set_function(14 - i, 8, bit(bits, i));
+        }
+
+        for i in 0..8 {
+            self.set_function(size - 1 - i, 8, bit(bits, i));
+        }
+        for i in 8..15 {
+            self.set_function(8, size - 15 + i, bit(bits, i));
+        }
+        self.set_function(8, size - 8, true);
+    }
+
+    /// Place `codewords` in the zigzag order, skipping function patterns
+    fn draw_codewords(&mut self, codewords: &[u8]) {
+        let size = self.size;
+        let mut i = 0;
+        let mut right = size - 1;
+        loop {
+            // The vertical timing pattern shifts the columns left of it
+            if right == 6 {
+                right = 5;
+            }
+            let upward = (right + 1) & 2 == 0;
+            for vertical in 0..size {
+                let y = if upward {
+                    size - 1 - vertical
+                } else {
+                    vertical
+                };
+                for x in [right, right - 1] {
+                    if !self.function[y * size + x] && i < codewords.len() * 8 {
+                        self.modules[y * size + x] = bit(codewords[i / 8].into(), 7 - i % 8);
+                        i += 1;
+                    }
+                }
+            }
+            if right < 2 {
+                break;
+            }
+            right -= 2;
+        }
+    }
+
+    /// Invert the data modules selected by `mask`, which undoes itself when applied again
+    fn apply_mask(&mut self, mask: u32) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let invert = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                let index = y * self.size + x;
+                self.modules[index] ^= invert && !self.function[index];
+            }
+        }
+    }
+
+    /// Penalty score of the current modules; masks with lower scores scan more reliably
+    fn penalty(&self) -> usize {
+        let size = self.size;
+        let mut penalty = 0;
+        // Runs of five or more same-colored modules, and finder-like patterns, in rows and columns
+        for transposed in [false, true] {
+            for a in 0..size {
+                let line: Vec<bool> = (0..size)
+                    .map(|b| {
+                        if transposed {
+                            self.get(a, b)
+                        } else {
+                            self.get(b, a)
+                        }
+                    })
+                    .collect();
+                let mut run = 1;
+                for b in 1..=size {
+                    if b < size && line[b] == line[b - 1] {
+                        run += 1;
+                    } else {
+                        if run >= 5 {
+                            penalty += run - 2;
+                        }
+                        run = 1;
+                    }
+                }
+                penalty += 40 * finder_like(&line);
+            }
+        }
+        // 2x2 blocks of the same color
+        for y in 1..size {
+            for x in 1..size {
+                let dark = self.get(x, y);
+                if dark == self.get(x - 1, y)
+                    && dark == self.get(x, y - 1)
+                    && dark == self.get(x - 1, y - 1)
+                {
+                    penalty += 3;
+                }
+            }
+        }
+        // Imbalance between dark and light modules, in steps of 5%
+        let total = size * size;
+        let dark = self.modules.iter().filter(|&&dark| dark).count();
+        let k = (dark * 20)
+            .abs_diff(total * 10)
+            .div_ceil(total)
+            .saturating_sub(1);
+        penalty + k * 10
+    }
+
+    /// Draw the code with half blocks, two rows of modules per line
+    ///
+    /// With `ansi`, colors are set explicitly so the code reads as dark on light whatever the
+    /// terminal's theme; otherwise dark modules are drawn and light ones left blank.
+    pub(crate) fn render(&self, ansi: bool) -> String {
+        let size = self.size as isize;
+        let quiet = QUIET_ZONE as isize;
+        let dark = |x: isize, y: isize| {
+            (0..size).contains(&x) && (0..size).contains(&y) && self.get(x as usize, y as usize)
+        };
+        let mut out = String::new();
+        for y in (-quiet..size + quiet).step_by(2) {
+            if ansi {
+                out.push_str("\x1b[30;107m");
+            }
+            for x in -quiet..size + quiet {
+                out.push(match (dark(x, y), dark(x, y + 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                });
+            }
+            if ansi {
+                out.push_str("\x1b[0m");
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Bits being packed into codewords, most significant first
+#[derive(Default)]
+struct Bits {
+    /// Codewords so far, the last possibly partial
+    bytes: Vec<u8>,
+    /// Number of bits pushed
+    len: usize,
+}
+
+impl Bits {
+    /// Append the low `count` bits of `value`
+    fn push(&mut self, value: u32, count: usize) {
+        for i in (0..count).rev() {
+            if self.len.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            if bit(value, i) {
+                *self.bytes.last_mut().unwrap() |= 0x80 >> (self.len % 8);
+            }
+            self.len += 1;
+        }
+    }
+}
+
+/// Whether bit `i` of `value` is set
+fn bit(value: u32, i: usize) -> bool {
+    value >> i & 1 != 0
+}
+
+/// Modules available for data and error correction in `version`
+fn raw_data_modules(version: usize) -> usize {
+    let mut modules = (16 * version + 128) * version + 64;
+    if version >= 2 {
+        let alignments = version / 7 + 2;
+        modules -= (25 * alignments - 10) * alignments - 55;
+        if version >= 7 {
+            modules -= 36;
+        }
+    }
+    modules
+}
+
+/// Data codewords `version` holds at level M
+fn data_codewords(version: usize) -> usize {
+    raw_data_modules(version) / 8 - ECC_PER_BLOCK[version] * BLOCKS[version]
+}
+
+/// Centers of the alignment patterns in `version`, along either axis
+fn alignment_positions(version: usize) -> Vec<usize> {
+    if version == 1 {
+        return vec![];
+    }
+    let count = version / 7 + 2;
+    let step = (version * 8 + count * 3 + 5) / (count * 4 - 4) * 2;
+    let mut positions = vec![6];
+    let mut position = version * 4 + 17 - 7;
+    for _ in 1..count {
+        positions.insert(1, position);
+        position -= step;
+    }
+    positions
+}
+
+/// Format information for level M and `mask`, with its error correction and mask applied
+fn format_bits(mask: u32) -> u32 {
+    let data = LEVEL_M << 3 | mask;
+    let mut rem = data;
+    for _ in 0..10 {
+        rem = (rem << 1) ^ ((rem >> 9) * 0x537);
+    }
+    (data << 10 | rem) ^ 0x5412
+}
+
+/// Split `data` into blocks, append each block's error correction, and interleave them
+fn add_ecc_and_interleave(data: &[u8], version: usize) -> Vec<u8> {
+    let blocks = BLOCKS[version];
+    let ecc_len = ECC_PER_BLOCK[version];
+    let raw_codewords = raw_data_modules(version) / 8;
+    let short_blocks = blocks - raw_codewords % blocks;
+    let short_len = raw_codewords / blocks;
+
+    let divisor = rs_divisor(ecc_len);
+    let mut rest = data;
+    let split: Vec<(&[u8], Vec<u8>)> = (0..blocks)
+        .map(|i| {
+            let len = short_len - ecc_len + usize::from(i >= short_blocks);
+            let (block, after) = rest.split_at(len);
+            rest = after;
+            (block, rs_remainder(block, &divisor))
+        })
+        .collect();
+
+    let mut out = Vec::with_capacity(raw_codewords);
+    for i in 0..=short_len - ecc_len {
+        for (block, _) in &split {
+            if let Some(&byte) = block.get(i) {
+                out.push(byte);
+            }
+        }
+    }
+    for i in 0..ecc_len {
+        out.extend(split.iter().map(|(_, ecc)| ecc[i]));
+    }
+    out
+}
+
+/// Multiply in GF(2^8) modulo the QR polynomial 0x11D
+fn gf_mul(x: u8, y: u8) -> u8 {
+    let mut z: u16 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x11D);
+        z ^= u16::from(y >> i & 1) * u16::from(x);
+    }
+    z as u8
+}
+
+/// Reed-Solomon generator polynomial of `degree`, highest coefficient (always 1) dropped
+fn rs_divisor(degree: usize) -> Vec<u8> {
+    let mut result = vec![0; degree];
+    result[degree - 1] = 1;
+    let mut root = 1;
+    for _ in 0..degree {
+        for j in 0..degree {
+            result[j] = gf_mul(result[j], root);
+            if j + 1 < degree {
+                result[j] ^= result[j + 1];
+            }
+        }
+        root = gf_mul(root, 0x02);
+    }
+    result
+}
+
+/// Error correction codewords for `data`
+fn rs_remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut result = vec![0; divisor.len()];
+    for &byte in data {
+        let factor = byte ^ result.remove(0);
+        result.push(0);
+        for (r, &d) in result.iter_mut().zip(divisor) {
+            *r ^= gf_mul(d, factor);
+        }
+    }
+    result
+}
+
+/// Number of finder-like patterns (dark-light-dark-dark-dark-light-dark with four light modules
+/// on one side) in `line`, counting the light area beyond the edges
+fn finder_like(line: &[bool]) -> usize {
+    const PATTERN: [bool; 7] = [true, false, true, true, true, false, true];
+    let light = |i: isize| i < 0 || i >= line.len() as isize || !line[i as usize];
+    (0..=line.len().saturating_sub(7))
+        .filter(|&start| line[start..start + 7] == PATTERN)
+        .map(|start| {
+            let start = start as isize;
+            let before = (start - 4..start).all(light);
+            let after = (start + 7..start + 11).all(light);
+            usize::from(before) + usize::from(after)
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_correction_and_format() {
+        // "HELLO WORLD" at 1-M, from the worked example in the standard
+        let data = [
+            32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17,
+        ];
+        assert_eq!(
+            rs_remainder(&data, &rs_divisor(10)),
+            [196, 35, 39, 119, 235, 215, 231, 226, 93, 23]
+        );
+        assert_eq!(format_bits(0), 0b101010000010010);
+        assert_eq!(format_bits(5), 0b100000011001110);
+        assert_eq!(alignment_positions(7), [6, 22, 38]);
+        assert_eq!(alignment_positions(32), [6, 34, 60, 86, 112, 138]);
+        assert_eq!(data_codewords(1), 16);
+        assert_eq!(data_codewords(40), 2334);
+    }
+
+    #[test]
+    fn picks_smallest_version() {
+        // 1-M holds 14 bytes
+        assert_eq!(QrCode::encode(&[b'a'; 14]).unwrap().size, 21);
+        assert_eq!(QrCode::encode(&[b'a'; 15]).unwrap().size, 25);
+        assert!(QrCode::encode(&[b'a'; 2332]).is_none());
+
+        let code = QrCode::encode(b"https://envs.sh/aB3.txt").unwrap();
+        let lines = code.render(false);
+        assert_eq!(
+            lines.lines().count(),
+            (code.size + 2 * QUIET_ZONE).div_ceil(2)
+        );
+        // The top-left finder pattern starts with a full row of seven dark modules
+        assert!((0..7).all(|x| code.get(x, 0)) && !code.get(7, 0));
+    }
+}
@@ -0,0 +1,109 @@
+//! Signed local receipts of uploads, an audit trail of what left the machine
+//!
+//! Enabled by the `[receipts]` config table. Each accepted upload gets a JSON receipt in its
+//! `dir`, next to a detached minisign signature made with its `key`, so
+//! `minisign -V -p KEY.pub -m RECEIPT.json` can check it later.
+
+use std::fs;
+use std::io::Cursor;
+use std::path::PathBuf;
+
+use jiff::Timestamp;
+use minisign::{PublicKey, SecretKey};
+use serde::Serialize;
+
+use crate::config;
+use crate::error::{Context, EnvshError, Result};
+use crate::sign;
+
+/// Trusted comment of every receipt signature
+const TRUSTED_COMMENT: &str = "envsh upload receipt";
+
+/// What a receipt records about one upload
+#[derive(Debug, Serialize)]
+pub(crate) struct Receipt<'a> {
+    /// Resulting URL
+    pub(crate) url: &'a str,
+    /// Target as given on the command line
+    pub(crate) input: &'a str,
+    /// Host the upload was sent to
+    pub(crate) host: &'a str,
+    /// Bytes sent, for file uploads
+    pub(crate) size: Option<u64>,
+    /// Hex SHA-256 of the bytes sent, for file uploads
+    pub(crate) sha256: Option<&'a str>,
+    /// When the host accepted the upload
+    pub(crate) created: Timestamp,
+}
+
+/// Writes and signs receipts
+pub(crate) struct Writer {
+    /// Directory receipts go in
+    dir: PathBuf,
+    /// Key signing them
+    secret_key: SecretKey,
+    /// Matching public key
+    public_key: PublicKey,
+}
+
+impl Writer {
+    /// Load the configured signing key if receipts are enabled and `--read-only-state` is off,
+    /// prompting for its password if encrypted
+    ///
+    /// Loaded before uploading, so a wrong password doesn't leave uploads without receipts.
+    pub(crate) fn load() -> Result<Option<Self>> {
+        let receipts = &config::get().receipts;
+        if config::get().read_only_state {
+            return Ok(None);
+        }
+        let (Some(dir), Some(key)) = (&receipts.dir, &receipts.key) else {
+            return Ok(None);
+        };
+        let (secret_key, public_key) = sign::load_key(key)?;
+        Ok(Some(Self {
+            dir: dir.clone(),
+            secret_key,
+            public_key,
+        }))
+    }
+
+    /// Write `receipt` and its signature, returning the receipt's path
+    pub(crate) fn write(&self, receipt: &Receipt) -> Result<PathBuf> {
+        fs::create_dir_all(&self.dir)
+            .context(format!("failed to create {}", self.dir.display()))?;
+
+        let mut contents = serde_json::to_vec_pretty(receipt).unwrap();
+        contents.push(b'\n');
+        // The URL's last segment tells receipts apart at a glance, and the time keeps them sorted
+        let name: String = receipt
+            .url
+            .rsplit('/')
+            .next()
+            .filter(|name| !name.is_empty())
+            .unwrap_or("upload")
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_' => c,
+                _ => '_',
+            })
+            .collect();
+        let path = self.dir.join(format!(
+            "{}-{name}.json",
+            receipt.created.strftime("%Y%m%dT%H%M%S%3fZ")
+        ));
+        fs::write(&path, &contents).context(format!("failed to write {}", path.display()))?;
+
+        let signature = minisign::sign(
+            Some(&self.public_key),
+            &self.secret_key,
+            Cursor::new(&contents),
+            Some(TRUSTED_COMMENT),
+            None,
+        )
+        .map_err(|e| EnvshError::Data(format!("failed to sign receipt: {e}")))?;
+        let sig_path = path.with_extension("json.minisig");
+        fs::write(&sig_path, signature.into_string())
+            .context(format!("failed to write {}", sig_path.display()))?;
+        Ok(path)
+    }
+}
@@ -0,0 +1,42 @@
+//! Build and environment report for bug reports
+
+use std::path::PathBuf;
+
+use crate::error::Result;
+use crate::{config, paths};
+
+/// TLS implementation reqwest was built with
+const TLS_BACKEND: &str = "native-tls";
+
+/// Cargo features enabled in this build
+const FEATURES: &[&str] = &[
+    #[cfg(feature = "async")]
+    "async",
+];
+
+/// Print build information and resolved paths
+pub(crate) fn print_environment() {
+    println!(
+        "envsh {} ({} {})",
+        env!("CARGO_PKG_VERSION"),
+        env!("ENVSH_GIT_HASH"),
+        env!("ENVSH_TARGET")
+    );
+    println!("TLS backend:    {TLS_BACKEND}");
+    if FEATURES.is_empty() {
+        println!("Features:       (none)");
+    } else {
+        println!("Features:       {}", FEATURES.join(", "));
+    }
+    println!("Host:           {}", config::get().host);
+    println!("Config file:    {}", shown(config::path()));
+    println!("Data directory: {}", shown(paths::data_dir()));
+}
+
+/// `path` for display, or why it couldn't be determined
+fn shown(path: Result<PathBuf>) -> String {
+    path.map_or_else(
+        |e| format!("(unknown, {e})"),
+        |path| path.display().to_string(),
+    )
+}
@@ -0,0 +1,103 @@
+//! Re-sending requests that failed for reasons likely to go away
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::thread;
+use std::time::Duration;
+
+use envsh::{Changed, Created};
+use reqwest::StatusCode;
+
+use crate::error::Result;
+
+/// Retries used when `--retries` isn't given
+const DEFAULT_RETRIES: u32 = 2;
+
+/// Delay before the first retry when `--retry-delay` isn't given
+const DEFAULT_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// A response whose status tells whether it is worth retrying
+pub(crate) trait Status {
+    /// HTTP status of the response
+    fn status(&self) -> StatusCode;
+}
+
+impl Status for Created {
+    fn status(&self) -> StatusCode {
+        self.status
+    }
+}
+
+impl Status for Changed {
+    fn status(&self) -> StatusCode {
+        self.status
+    }
+}
+
+/// How often, and how patiently, to re-send failed requests
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Retry {
+    /// Attempts after the first
+    retries: u32,
+    /// Delay before the first retry, doubled for each one after it
+    delay: Duration,
+}
+
+impl Retry {
+    /// Retry up to `retries` times, starting `delay` after the first failure
+    pub(crate) fn new(retries: Option<u32>, delay: Option<Duration>) -> Self {
+        Self {
+            retries: retries.unwrap_or(DEFAULT_RETRIES),
+            delay: delay.unwrap_or(DEFAULT_RETRY_DELAY),
+        }
+    }
+
+    /// Send a request with `attempt` until it gets a response that isn't a server error, or
+    /// retries run out
+    ///
+    /// Only connection problems, timeouts and 5xx responses are retried. `attempt` is called once if
+    /// the request can't be `replayable`, e.g. because its body was read from stdin.
+    pub(crate) fn send<T: Status>(
+        &self,
+        what: &str,
+        replayable: bool,
+        json: bool,
+        mut attempt: impl FnMut(u32) -> Result<reqwest::Result<T>>,
+    ) -> Result<reqwest::Result<T>> {
+        let retries = if replayable { self.retries } else { 0 };
+        let mut n = 0;
+        loop {
+            let result = attempt(n)?;
+            let reason = match &result {
+                Ok(resp) if resp.status().is_server_error() => resp.status().to_string(),
+                Err(e) if e.is_connect() || e.is_timeout() || e.is_request() || e.is_body() => {
+                    e.to_string()
+                }
+                _ => return Ok(result),
+            };
+            if n == retries {
+                return Ok(result);
+            }
+
+            n += 1;
+            // Vary each delay by up to half, so clients that failed together don't retry together
+            let delay = self
+                .delay
+                .mul_f64(2f64.powi(n as i32 - 1) * (0.5 + jitter()));
+            crate::note(
+                json,
+                format!(
+                    "{what} failed ({reason}), retrying in {:.1}s ({n} of {retries})",
+                    delay.as_secs_f64()
+                ),
+            );
+            thread::sleep(delay);
+        }
+    }
+}
+
+/// A random number in `[0, 1)`, good enough for spreading out requests
+pub(crate) fn jitter() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
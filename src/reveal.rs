@@ -0,0 +1,107 @@
+//! Showing X-Tokens without leaving them in terminal scrollback
+
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+use envsh::Token;
+
+/// ANSI: move the cursor up one line
+const CURSOR_UP: &str = "\x1b[1A";
+
+/// ANSI: return to column 0 and clear the line
+const CLEAR_LINE: &str = "\r\x1b[2K";
+
+/// Enter presses read from stdin by one thread for every token shown, since a blocked read
+/// can't be abandoned when a token times out
+struct Presses {
+    /// Asks the thread to read a line
+    requests: Sender<()>,
+    /// A line was read
+    presses: Receiver<()>,
+    /// Whether a requested line wasn't received yet
+    pending: bool,
+}
+
+/// The shared reader, started on first use
+fn presses() -> &'static Mutex<Presses> {
+    static PRESSES: OnceLock<Mutex<Presses>> = OnceLock::new();
+    PRESSES.get_or_init(|| {
+        let (requests, requested) = mpsc::channel();
+        let (pressed, presses) = mpsc::channel();
+        // Lines are only read on request, so stdin is left alone between tokens
+        thread::spawn(move || {
+            for () in requested {
+                let read = io::stdin().lock().read_line(&mut String::new());
+                if !read.is_ok_and(|n| n > 0) || pressed.send(()).is_err() {
+                    break;
+                }
+            }
+        });
+        Mutex::new(Presses {
+            requests,
+            presses,
+            pending: false,
+        })
+    })
+}
+
+/// Wait up to `timeout` for Enter to be pressed, returning whether it was
+fn wait_for_enter(timeout: Duration) -> bool {
+    let mut presses = presses().lock().unwrap();
+    // A press answering an earlier token's timed-out wait came before this token was shown
+    if presses.pending && presses.presses.try_recv().is_ok() {
+        presses.pending = false;
+    }
+    if !presses.pending {
+        if presses.requests.send(()).is_err() {
+            thread::sleep(timeout);
+            return false;
+        }
+        presses.pending = true;
+    }
+    let pressed = match presses.presses.recv_timeout(timeout) {
+        Ok(()) => true,
+        Err(RecvTimeoutError::Timeout) => false,
+        // stdin was closed, leaving only the timeout
+        Err(RecvTimeoutError::Disconnected) => {
+            thread::sleep(timeout);
+            false
+        }
+    };
+    presses.pending = !pressed;
+    pressed
+}
+
+/// Print `token`, erasing it after `hide_after` or once Enter is pressed if stdout and stdin are
+/// terminals
+///
+/// Without a terminal to press Enter in, waiting would only stall every upload for the full
+/// timeout, so the token is printed as usual.
+pub(crate) fn show_token(token: &Token, hide_after: Option<Duration>) {
+    let interactive = io::stdout().is_terminal() && io::stdin().is_terminal();
+    let Some(hide_after) = hide_after.filter(|_| interactive) else {
+        println!("X-Token: {}", token.expose());
+        return;
+    };
+
+    let mut stdout = io::stdout().lock();
+    let _ = write!(
+        stdout,
+        "X-Token: {}\n(hidden in {}s, or press Enter) ",
+        token.expose(),
+        hide_after.as_secs()
+    );
+    let _ = stdout.flush();
+
+    let pressed = wait_for_enter(hide_after);
+    // Enter echoes a newline, leaving the cursor one line further down
+    let _ = writeln!(
+        stdout,
+        "{}{CLEAR_LINE}{CURSOR_UP}{CLEAR_LINE}X-Token: (hidden)",
+        if pressed { CURSOR_UP } else { "" }
+    );
+    let _ = stdout.flush();
+}
@@ -0,0 +1,264 @@
+//! Printable PDF sheets of QR codes, for `envsh qr-sheet`
+//!
+//! Codes are laid out in a grid on A4 pages, each with its label and URL below it in Courier,
+//! which every PDF reader has built in, so no font is embedded. Dashed lines around the cells
+//! show where to cut.
+
+use std::io::Write;
+
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+
+use crate::qr::QrCode;
+
+/// A4 page width, in points
+const PAGE_WIDTH: f64 = 595.0;
+
+/// A4 page height, in points
+const PAGE_HEIGHT: f64 = 842.0;
+
+/// Blank border around the grid
+const MARGIN: f64 = 36.0;
+
+/// Space between a cell's edges and its contents
+const PADDING: f64 = 8.0;
+
+/// Size of label text
+const FONT_SIZE: f64 = 8.0;
+
+/// Distance between label baselines
+const LINE_HEIGHT: f64 = 10.0;
+
+/// Width of every Courier character, as a fraction of the font size
+const CHAR_WIDTH: f64 = 0.6;
+
+/// Light modules around each code; print gets the full margin the standard asks for
+const QUIET_ZONE: usize = 4;
+
+/// One code on the sheet
+pub(crate) struct Item {
+    /// The encoded URL
+    pub(crate) code: QrCode,
+    /// The URL, printed under the label
+    pub(crate) url: String,
+    /// What the URL is, printed under the code
+    pub(crate) label: String,
+}
+
+/// Lay out `items` in a grid `columns` wide, as a PDF with as many pages as they need
+pub(crate) fn pdf(items: &[Item], columns: usize) -> Vec<u8> {
+    let cell_width = (PAGE_WIDTH - 2.0 * MARGIN) / columns as f64;
+    let code_size = cell_width - 2.0 * PADDING;
+    let cell_height = code_size + 2.0 * PADDING + 2.0 * LINE_HEIGHT;
+    let rows = (((PAGE_HEIGHT - 2.0 * MARGIN) / cell_height) as usize).max(1);
+
+    let mut objects = vec![
+        b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+        // The page tree, filled in once every page has a number
+        vec![],
+        b"<< /Type /Font /Subtype /Type1 /BaseFont /Courier /Encoding /WinAnsiEncoding >>".to_vec(),
+    ];
+    let mut pages = vec![];
+    for page in items.chunks(rows * columns) {
+        let mut content = String::from("0.6 G 0.5 w [3 3] 0 d\n");
+        let cells: Vec<_> = page
+            .iter()
+            .enumerate()
+            .map(|(i, item)| {
+                let left = MARGIN + (i % columns) as f64 * cell_width;
+                let top = PAGE_HEIGHT - MARGIN - (i / columns) as f64 * cell_height;
+                (item, left, top)
+            })
+            .collect();
+        for &(_, left, top) in &cells {
+            content.push_str(&format!(
+                "{left:.2} {:.2} {cell_width:.2} {cell_height:.2} re S\n",
+                top - cell_height
+            ));
+        }
+
+        content.push_str("0 g\n");
+        for &(item, left, top) in &cells {
+            draw_code(
+                &mut content,
+                &item.code,
+                left + PADDING,
+                top - PADDING,
+                code_size,
+            );
+        }
+        content.push_str("f\n");
+
+        let max_chars = ((cell_width - 2.0 * PADDING) / (FONT_SIZE * CHAR_WIDTH)) as usize;
+        content.push_str(&format!("BT /F1 {FONT_SIZE} Tf\n"));
+        for &(item, left, top) in &cells {
+            let baseline = top - PADDING - code_size - FONT_SIZE;
+            for (text, y) in [(&item.label, baseline), (&item.url, baseline - LINE_HEIGHT)] {
+                let fitted = fit(text, max_chars);
+                // Courier is monospaced, so centering only needs the character count
+                let width = fitted.chars().count() as f64 * FONT_SIZE * CHAR_WIDTH;
+                let x = left + (cell_width - width) / 2.0;
+                content.push_str(&format!(
+                    "1 0 0 1 {x:.2} {y:.2} Tm {} Tj\n",
+                    string(&fitted)
+                ));
+            }
+        }
+        content.push_str("ET\n");
+
+        let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+        encoder.write_all(content.as_bytes()).unwrap();
+        let stream = encoder.finish().unwrap();
+        objects.push(
+            [
+                format!(
+                    "<< /Length {} /Filter /FlateDecode >>\nstream\n",
+                    stream.len()
+                )
+                .as_bytes(),
+                &stream,
+                b"\nendstream",
+            ]
+            .concat(),
+        );
+        let contents = objects.len();
+        objects.push(
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {PAGE_WIDTH} {PAGE_HEIGHT}] \
+                /Resources << /Font << /F1 3 0 R >> >> /Contents {contents} 0 R >>"
+            )
+            .into_bytes(),
+        );
+        pages.push(format!("{} 0 R", objects.len()));
+    }
+    objects[1] = format!(
+        "<< /Type /Pages /Kids [{}] /Count {} >>",
+        pages.join(" "),
+        pages.len()
+    )
+    .into_bytes();
+
+    // A binary comment after the header tells transfer tools the file isn't text
+    let mut out = b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec();
+    let mut offsets = vec![];
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(out.len());
+        out.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+        out.extend_from_slice(object);
+        out.extend_from_slice(b"\nendobj\n");
+    }
+    let xref = out.len();
+    out.extend_from_slice(
+        format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes(),
+    );
+    for offset in offsets {
+        out.extend_from_slice(format!("{offset:010} 00000 n \n").as_bytes());
+    }
+    out.extend_from_slice(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+            objects.len() + 1
+        )
+        .as_bytes(),
+    );
+    out
+}
+
+/// Add rectangles for the dark modules of `code` to `content`, in a square of `size` points
+/// whose top left corner is at `left`, `top`
+///
+/// Each run of dark modules in a row is one rectangle, which keeps pages small.
+fn draw_code(content: &mut String, code: &QrCode, left: f64, top: f64, size: f64) {
+    let module = size / (code.size() + 2 * QUIET_ZONE) as f64;
+    for y in 0..code.size() {
+        let mut x = 0;
+        while x < code.size() {
+            if !code.get(x, y) {
+                x += 1;
+                continue;
+            }
+            let start = x;
+            while x < code.size() && code.get(x, y) {
+                x += 1;
+            }
+            content.push_str(&format!(
+                "{:.3} {:.3} {:.3} {module:.3} re\n",
+                left + (QUIET_ZONE + start) as f64 * module,
+                top - (QUIET_ZONE + y + 1) as f64 * module,
+                (x - start) as f64 * module,
+            ));
+        }
+    }
+}
+
+/// Shorten `text` to at most `max` characters, marking the cut with "..."
+fn fit(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut fitted: String = text.chars().take(max.saturating_sub(3)).collect();
+    fitted.push_str("...");
+    fitted
+}
+
+/// `text` as a PDF string, with characters the font's encoding lacks replaced by `?`
+fn string(text: &str) -> String {
+    let mut out = String::from("(");
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            ' '..='~' => out.push(c),
+            _ => out.push('?'),
+        }
+    }
+    out.push(')');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cross_references_objects() {
+        let items: Vec<_> = (0..10)
+            .map(|i| {
+                let url = format!("https://envs.sh/a{i}.txt");
+                Item {
+                    code: QrCode::encode(url.as_bytes()).unwrap(),
+                    url,
+                    label: format!("@{i} notes.txt"),
+                }
+            })
+            .collect();
+        let pdf = pdf(&items, 3);
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.starts_with("%PDF-1.4\n"));
+        assert!(text.ends_with("%%EOF\n"));
+        // Three rows of three fit a page, so the tenth code starts another
+        assert!(text.contains("/Count 2 >>"));
+
+        let startxref = text.rfind("startxref\n").unwrap() + "startxref\n".len();
+        let xref: usize = text[startxref..].lines().next().unwrap().parse().unwrap();
+        assert!(pdf[xref..].starts_with(b"xref\n"));
+        let entries = text[xref..]
+            .lines()
+            .skip(3)
+            .take_while(|l| l.ends_with(" n "));
+        for (i, entry) in entries.enumerate() {
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(pdf[offset..].starts_with(format!("{} 0 obj\n", i + 1).as_bytes()));
+        }
+    }
+
+    #[test]
+    fn escapes_labels() {
+        assert_eq!(string(r"a(b)\c"), r"(a\(b\)\\c)");
+        assert_eq!(string("café"), "(caf?)");
+        assert_eq!(fit("notes.txt", 20), "notes.txt");
+        assert_eq!(fit("a-very-long-file-name.txt", 10), "a-very-...");
+    }
+}
@@ -0,0 +1,150 @@
+//! Detached minisign signatures for uploads
+
+use std::fs::{self, File};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use minisign::{PublicKey, SecretKey, SignatureBox};
+use reqwest::Url;
+use reqwest::blocking::Response;
+
+use crate::config;
+use crate::error::{Context, EnvshError, Result};
+
+/// Prefix of the trusted comment field recording which URL a signature covers
+const URL_COMMENT: &str = "url:";
+
+/// A loaded minisign key pair, ready to sign a file once it has been uploaded
+pub(crate) struct Signer {
+    /// Key used to sign
+    secret_key: SecretKey,
+    /// Matching public key, printed for recipients
+    public_key: PublicKey,
+    /// The file to be signed
+    file: PathBuf,
+}
+
+/// A detached signature for an uploaded file
+pub(crate) struct Signed {
+    /// File name of the signed file
+    pub(crate) file_name: String,
+    /// Contents of the `.minisig` signature file
+    pub(crate) signature: String,
+    /// Base64 public key recipients can verify the signature with
+    pub(crate) public_key: String,
+}
+
+impl Signer {
+    /// Load the minisign secret key at `seckey`, prompting for its password if encrypted
+    ///
+    /// Keys are loaded before uploading so a wrong password doesn't waste an upload.
+    pub(crate) fn load(seckey: &Path, file: &Path) -> Result<Self> {
+        let (secret_key, public_key) = load_key(seckey)?;
+        Ok(Self {
+            secret_key,
+            public_key,
+            file: file.to_path_buf(),
+        })
+    }
+
+    /// Sign the file, recording its name and uploaded URL in the trusted comment
+    pub(crate) fn sign(self, url: &str) -> Result<Signed> {
+        let file_name = self
+            .file
+            .file_name()
+            .ok_or_else(|| {
+                EnvshError::Usage(format!(
+                    "cannot sign {}, it has no file name",
+                    self.file.display()
+                ))
+            })?
+            .to_string_lossy()
+            .into_owned();
+
+        let file = File::open(&self.file).context(format!(
+            "failed to open {} for signing",
+            self.file.display()
+        ))?;
+        let signature = minisign::sign(
+            Some(&self.public_key),
+            &self.secret_key,
+            file,
+            Some(&format!("file:{file_name}\t{URL_COMMENT}{url}")),
+            None,
+        )
+        .map_err(|e| EnvshError::Data(format!("failed to sign {file_name}: {e}")))?;
+
+        Ok(Signed {
+            file_name,
+            signature: signature.into_string(),
+            public_key: self.public_key.to_base64(),
+        })
+    }
+}
+
+/// Load the minisign secret key at `seckey` and derive its public key, prompting for the
+/// password if the key is encrypted
+pub(crate) fn load_key(seckey: &Path) -> Result<(SecretKey, PublicKey)> {
+    let key_box = fs::read_to_string(seckey)
+        .context(format!("failed to read secret key {}", seckey.display()))?;
+    let secret_key = SecretKey::from_unencrypted_box(key_box.clone().into())
+        .or_else(|_| SecretKey::from_box(key_box.into(), None))
+        .map_err(|e| EnvshError::Data(format!("failed to load secret key: {e}")))?;
+    let public_key = PublicKey::from_secret_key(&secret_key)
+        .map_err(|e| EnvshError::Data(format!("failed to derive public key: {e}")))?;
+    Ok((secret_key, public_key))
+}
+
+/// Download a signature produced by `--sign` and the file it refers to, and check it
+///
+/// Fails after printing the result if verification fails.
+pub(crate) fn verify_url(sig_url: Url, public_key: &str) -> Result<()> {
+    let public_key = PublicKey::from_base64(public_key)
+        .map_err(|e| EnvshError::Usage(format!("invalid public key: {e}")))?;
+    let client = config::get().client();
+
+    let sig_text = client
+        .get(sig_url.clone())
+        .send()
+        .and_then(Response::error_for_status)
+        .and_then(Response::text)
+        .context(format!("failed to download {sig_url}"))?;
+    let signature = SignatureBox::from_string(&sig_text)
+        .map_err(|_| EnvshError::Data(format!("{sig_url} is not a minisign signature")))?;
+
+    // The comment is only trusted after verification, but tells us what to fetch
+    let file_url = signature
+        .trusted_comment()
+        .ok()
+        .and_then(|comment| {
+            comment
+                .split('\t')
+                .find_map(|field| field.strip_prefix(URL_COMMENT).map(ToString::to_string))
+        })
+        .ok_or_else(|| EnvshError::Data(format!("{sig_url} was not created by `envsh --sign`")))?;
+
+    let file = client
+        .get(&file_url)
+        .send()
+        .and_then(Response::error_for_status)
+        .and_then(Response::bytes)
+        .context(format!("failed to download {file_url}"))?;
+
+    match minisign::verify(
+        &public_key,
+        &signature,
+        Cursor::new(file),
+        true,
+        false,
+        false,
+    ) {
+        Ok(()) => {
+            println!("PASS {file_url}");
+            Ok(())
+        }
+        Err(e) => {
+            println!("FAIL {file_url}: {e}");
+            Err(EnvshError::Failed(String::new()))
+        }
+    }
+}
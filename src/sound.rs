@@ -0,0 +1,65 @@
+//! Sounds played when uploads or changes finish, for ones left running in a background terminal
+
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::config;
+
+/// Config value for ringing the terminal bell instead of playing a file
+pub(crate) const BELL: &str = "bell";
+
+/// Players tried in order for sound files
+const PLAYERS: &[&str] = if cfg!(target_os = "macos") {
+    &["afplay"]
+} else {
+    &["paplay", "pw-play", "aplay"]
+};
+
+/// Play the configured sound for success or failure, if there is one
+///
+/// A sound file that doesn't exist (for example on a removable drive) only gets a warning and
+/// the bell, instead of making the whole config invalid.
+pub(crate) fn play(success: bool) {
+    let sound = &config::get().sound;
+    let Some(sound) = (if success {
+        &sound.success
+    } else {
+        &sound.failure
+    }) else {
+        return;
+    };
+
+    if sound == BELL {
+        return ring_bell();
+    }
+    let file = Path::new(sound);
+    if !file.is_file() {
+        eprintln!("Warning: sound file {sound:?} does not exist");
+        ring_bell()
+    } else if !play_file(file) {
+        ring_bell()
+    }
+}
+
+/// Play a sound file with the first available player, returning whether one ran
+fn play_file(file: &Path) -> bool {
+    PLAYERS.iter().any(|player| {
+        Command::new(player)
+            .arg(file)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    })
+}
+
+/// Ring the terminal bell, if stderr is a terminal to ring it in
+fn ring_bell() {
+    let mut stderr = io::stderr();
+    if stderr.is_terminal() {
+        let _ = stderr.write_all(b"\x07");
+        let _ = stderr.flush();
+    }
+}
@@ -0,0 +1,98 @@
+//! Temporary files that clean up after themselves
+//!
+//! Files are created with owner-only permissions, preferably on tmpfs (`$XDG_RUNTIME_DIR`), and
+//! are removed when dropped, when a panic unwinds past them, or when envsh is interrupted.
+
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, ExitStatus};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, Once};
+
+/// Paths of all live temporary files, removed by the interrupt handler
+static LIVE: Mutex<Vec<PathBuf>> = Mutex::new(vec![]);
+
+/// Set while an interactive child (e.g. an editor) owns the terminal and handles Ctrl-C itself
+static CHILD_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Distinguishes temporary files created by the same process
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Installs the interrupt handler once
+static HANDLER: Once = Once::new();
+
+/// A temporary file, deleted on drop
+#[derive(Debug)]
+pub(crate) struct TempFile {
+    /// Location of the file
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// Create a new empty file whose name ends with `suffix`, returning it opened for writing
+    pub(crate) fn create(suffix: &str) -> io::Result<(Self, File)> {
+        HANDLER.call_once(|| {
+            let _ = ctrlc::set_handler(|| {
+                if !CHILD_ACTIVE.load(Ordering::SeqCst) {
+                    remove_live();
+                    process::exit(130)
+                }
+            });
+        });
+
+        let path = temp_dir().join(format!(
+            "envsh-{}-{}{suffix}",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let file = options.open(&path)?;
+
+        LIVE.lock().unwrap().push(path.clone());
+        Ok((Self { path }, file))
+    }
+
+    /// Location of the file
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+        if let Ok(mut live) = LIVE.lock() {
+            live.retain(|p| p != &self.path);
+        }
+    }
+}
+
+/// Run an interactive child process, letting it handle Ctrl-C instead of envsh
+pub(crate) fn run_interactive(command: &mut Command) -> io::Result<ExitStatus> {
+    CHILD_ACTIVE.store(true, Ordering::SeqCst);
+    let status = command.status();
+    CHILD_ACTIVE.store(false, Ordering::SeqCst);
+    status
+}
+
+/// Prefer a tmpfs-backed runtime directory so temporary contents never touch disk
+fn temp_dir() -> PathBuf {
+    env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_dir())
+        .unwrap_or_else(env::temp_dir)
+}
+
+/// Remove every live temporary file
+fn remove_live() {
+    if let Ok(live) = LIVE.lock() {
+        for path in live.iter() {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! `--format` templates for the line printed after each successful upload
+//!
+//! Placeholders are `{url}`, `{token}`, `{input}` and `{expires}`, which takes an optional strftime
+//! format like `{expires:%F}`. `{{` and `}}` are literal braces, and `\n` and `\t` a newline and a
+//! tab.
+
+use jiff::Zoned;
+
+use crate::config;
+
+/// Part of a template
+#[derive(Clone, Debug, PartialEq)]
+enum Piece {
+    /// Text printed as is
+    Text(String),
+    /// The resulting URL
+    Url,
+    /// The X-Token, or nothing if the host didn't send one
+    Token,
+    /// The target as given on the command line
+    Input,
+    /// The expiry in a strftime format, or the configured `time_format` if `None`, or nothing if
+    /// the URL doesn't expire
+    Expires(Option<String>),
+}
+
+/// A parsed `--format` template
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Template(Vec<Piece>);
+
+/// Values filled into a template
+pub(crate) struct Fields<'a> {
+    /// Resulting URL
+    pub(crate) url: &'a str,
+    /// X-Token for managing the URL
+    pub(crate) token: Option<&'a str>,
+    /// Target as given on the command line
+    pub(crate) input: &'a str,
+    /// When the URL expires
+    pub(crate) expires: Option<&'a Zoned>,
+}
+
+impl Template {
+    /// Parse a template, describing the problem if it is invalid
+    pub(crate) fn parse(template: &str) -> Result<Self, String> {
+        let mut pieces = vec![];
+        let mut text = String::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '}' => return Err("unmatched '}' in format, write '}}' for a brace".to_string()),
+                '\\' if chars.as_str().starts_with(['n', 't', '\\']) => {
+                    text.push(match chars.next() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        _ => '\\',
+                    });
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let Some(end) = rest.find('}') else {
+                        return Err("unclosed '{' in format, write '{{' for a brace".to_string());
+                    };
+                    let placeholder = &rest[..end];
+                    chars = rest[end + 1..].chars();
+                    if !text.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    }
+                    pieces.push(Self::placeholder(placeholder)?);
+                }
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text));
+        }
+        Ok(Self(pieces))
+    }
+
+    /// Parse the inside of a `{placeholder}`
+    fn placeholder(placeholder: &str) -> Result<Piece, String> {
+        match placeholder.split_once(':') {
+            None if placeholder == "url" => Ok(Piece::Url),
+            None if placeholder == "token" => Ok(Piece::Token),
+            None if placeholder == "input" => Ok(Piece::Input),
+            None if placeholder == "expires" => Ok(Piece::Expires(None)),
+            Some(("expires", format)) => jiff::fmt::strtime::format(format, &Zoned::now())
+                .map(|_| Piece::Expires(Some(format.to_string())))
+                .map_err(|e| format!("invalid time format {format:?} in {{expires}}: {e}")),
+            _ => Err(format!(
+                "unknown placeholder {{{placeholder}}}, expected {{url}}, {{token}}, {{input}} or \
+                {{expires}}"
+            )),
+        }
+    }
+
+    /// Fill in the template
+    pub(crate) fn render(&self, fields: &Fields) -> String {
+        let mut out = String::new();
+        for piece in &self.0 {
+            match piece {
+                Piece::Text(text) => out.push_str(text),
+                Piece::Url => out.push_str(fields.url),
+                Piece::Token => out.push_str(fields.token.unwrap_or_default()),
+                Piece::Input => out.push_str(fields.input),
+                Piece::Expires(format) => {
+                    if let Some(expires) = fields.expires {
+                        let format = format
+                            .as_deref()
+                            .unwrap_or(&config::get().output.time_format);
+                        out.push_str(&expires.strftime(format).to_string())
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_fields() {
+        let expires: Zoned = "2025-07-01T12:30:00+00:00[UTC]".parse().unwrap();
+        let fields = Fields {
+            url: "https://envs.sh/aK2.txt",
+            token: Some("tok"),
+            input: "notes.txt",
+            expires: Some(&expires),
+        };
+        let render = |template: &str| Template::parse(template).unwrap().render(&fields);
+        assert_eq!(
+            render("{url} token={token} expires={expires:%F}"),
+            "https://envs.sh/aK2.txt token=tok expires=2025-07-01"
+        );
+        assert_eq!(
+            render("[[{url}][{input}]]"),
+            "[[https://envs.sh/aK2.txt][notes.txt]]"
+        );
+        assert_eq!(
+            render("{{url}}\\t{url}\\n"),
+            "{url}\thttps://envs.sh/aK2.txt\n"
+        );
+        assert_eq!(render("C:\\dir {input}"), "C:\\dir notes.txt");
+
+        let bare = Fields {
+            token: None,
+            expires: None,
+            ..fields
+        };
+        let template = Template::parse("{url} {token}{expires:%F}").unwrap();
+        assert_eq!(template.render(&bare), "https://envs.sh/aK2.txt ");
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        assert!(Template::parse("{link}").is_err());
+        assert!(Template::parse("{url").is_err());
+        assert!(Template::parse("url}").is_err());
+        assert!(Template::parse("{token:%F}").is_err());
+        assert!(Template::parse("{expires:%}").is_err());
+    }
+}
@@ -0,0 +1,65 @@
+//! Pausing batches while the host's rate limit is used up, or to spread them out
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use envsh::RateLimit;
+
+use crate::retry;
+
+/// When the next request may be sent, fed by the quota reported with each response
+#[derive(Debug, Default)]
+pub(crate) struct Throttle {
+    /// Earliest time to send the next request, if the quota ran out
+    resume_at: Option<Instant>,
+    /// Longest random pause between requests, from `--spread`
+    spread: Option<Duration>,
+    /// Whether a request was already sent, so the next one is spread out from it
+    sent: bool,
+}
+
+impl Throttle {
+    /// Pause for a random time up to `spread` between requests, if given
+    pub(crate) fn new(spread: Option<Duration>) -> Self {
+        Self {
+            spread,
+            ..Self::default()
+        }
+    }
+
+    /// Note the quota the host reported with its last response
+    pub(crate) fn update(&mut self, rate_limit: Option<&RateLimit>) {
+        self.resume_at = rate_limit
+            .filter(|rl| rl.is_exhausted())
+            .and_then(|rl| rl.reset)
+            .map(|reset| Instant::now() + reset);
+    }
+
+    /// Sleep until the host should accept another request, and for any spread after the last one
+    pub(crate) fn wait(&mut self, json: bool) {
+        let spread = self
+            .spread
+            .filter(|_| self.sent)
+            .map_or(Duration::ZERO, |spread| spread.mul_f64(retry::jitter()));
+        self.sent = true;
+        let rate_limited = self.resume_at.take().map_or(Duration::ZERO, |at| {
+            at.saturating_duration_since(Instant::now())
+        });
+
+        if rate_limited > Duration::ZERO {
+            // Round up so "waiting 0s" is never printed for a real pause
+            let secs = rate_limited.as_secs() + u64::from(rate_limited.subsec_nanos() > 0);
+            crate::note(json, format!("Rate limit reached, waiting {secs}s"));
+            thread::sleep(rate_limited.max(spread));
+        } else if spread > Duration::ZERO {
+            crate::note(
+                json,
+                format!(
+                    "Waiting {:.1}s before the next upload",
+                    spread.as_secs_f64()
+                ),
+            );
+            thread::sleep(spread);
+        }
+    }
+}
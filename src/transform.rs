@@ -0,0 +1,600 @@
+//! Streaming transforms applied to file contents before upload
+//!
+//! Each transform wraps the previous [`Read`], so a file is never buffered in full no matter how
+//! many transforms are enabled. They always run in [`Transform`] declaration order.
+
+use std::io::{self, BufRead, BufReader, Read};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use flate2::Compression;
+use flate2::read::GzEncoder;
+
+use crate::crypt::{self, Encrypt, Key};
+use crate::progress::Progress;
+
+/// Replacement for redacted values
+const REDACTED: &[u8] = b"[REDACTED]";
+
+/// Keys whose values are redacted by [`Transform::Redact`], matched case-insensitively
+const SECRET_KEYS: &[&[u8]] = &[
+    b"password",
+    b"passwd",
+    b"secret",
+    b"token",
+    b"api_key",
+    b"apikey",
+    b"access_key",
+    b"authorization",
+];
+
+/// A single transform step
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Transform {
+    /// Remove EXIF/XMP/text metadata from JPEG and PNG images
+    StripMetadata,
+    /// Replace values of secret-looking `key=value` / `key: value` pairs
+    Redact,
+    /// Gzip the contents
+    Compress,
+    /// Encrypt the contents with a new random key, last since ciphertext doesn't compress
+    Encrypt,
+}
+
+/// File contents, as they will be uploaded
+pub(crate) struct Stream {
+    /// The contents
+    pub(crate) reader: Box<dyn Read + Send>,
+    /// Length, if it is still known after transforming
+    pub(crate) len: Option<u64>,
+    /// File name sent to the host
+    pub(crate) file_name: Option<String>,
+    /// MIME type sent to the host
+    pub(crate) mime: Option<String>,
+}
+
+/// An ordered, deduplicated set of transforms
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Pipeline {
+    /// MIME type replacing the detected one, before any transform
+    content_type: Option<String>,
+    /// Extension replacing the file name's, before any transform
+    extension: Option<String>,
+    /// Enabled transforms, in order
+    transforms: Vec<Transform>,
+    /// Counts of what the transforms removed from streams read so far
+    removed: Removed,
+    /// Key for [`Transform::Encrypt`], generated with the pipeline
+    key: Option<Key>,
+}
+
+/// Counts shared with the transforming readers
+#[derive(Clone, Debug, Default)]
+struct Removed {
+    /// Metadata segments and chunks dropped by [`Transform::StripMetadata`]
+    metadata: Arc<AtomicUsize>,
+    /// Lines changed by [`Transform::Redact`]
+    secrets: Arc<AtomicUsize>,
+}
+
+impl Pipeline {
+    /// Build a pipeline from the enabled transforms, in their defined order
+    pub(crate) fn new(transforms: impl IntoIterator<Item = Transform>) -> Self {
+        let mut transforms: Vec<_> = transforms.into_iter().collect();
+        transforms.sort();
+        transforms.dedup();
+        let key = transforms.contains(&Transform::Encrypt).then(Key::generate);
+        Self {
+            transforms,
+            key,
+            ..Self::default()
+        }
+    }
+
+    /// Describe the contents as `content_type` and/or give them the extension `extension`
+    /// (without a dot), as `--content-type` and `--ext` do
+    ///
+    /// This happens before transforming, so compressed or encrypted contents keep their own type.
+    /// A new extension without a type also changes the type to the extension's, if it is known.
+    pub(crate) fn retyped(self, content_type: Option<String>, extension: Option<String>) -> Self {
+        Self {
+            content_type,
+            extension,
+            ..self
+        }
+    }
+
+    /// Run `stream` through every transform, reporting each as a stage of `progress`
+    pub(crate) fn apply(&self, mut stream: Stream, progress: &Progress) -> Stream {
+        if let Some(ext) = &self.extension {
+            let name = stream.file_name.as_deref().unwrap_or("file");
+            stream.file_name = Some(with_extension(name, ext));
+            if let Some(mime) = mime_guess::from_ext(ext).first() {
+                stream.mime = Some(mime.to_string());
+            }
+        }
+        if let Some(content_type) = &self.content_type {
+            stream.mime = Some(content_type.clone());
+        }
+
+        progress.restart();
+        self.transforms.iter().fold(stream, |stream, t| {
+            let stream = Stream {
+                reader: progress.stage(t.label(), stream.reader, stream.len),
+                ..stream
+            };
+            t.apply(stream, &self.removed, self.key.as_ref())
+        })
+    }
+
+    /// Key the contents were encrypted with, if they were
+    pub(crate) fn key(&self) -> Option<&Key> {
+        self.key.as_ref()
+    }
+
+    /// Number of metadata segments stripped from streams read so far
+    pub(crate) fn stripped_metadata(&self) -> usize {
+        self.removed.metadata.load(Ordering::Relaxed)
+    }
+
+    /// Number of lines with redacted values in streams read so far
+    pub(crate) fn redacted_lines(&self) -> usize {
+        self.removed.secrets.load(Ordering::Relaxed)
+    }
+}
+
+/// Replace the extension of `name` with `ext`, or add it if there is none
+fn with_extension(name: &str, ext: &str) -> String {
+    // A leading dot starts a hidden file's name, not an extension
+    let stem = match name.rfind('.') {
+        Some(dot) if dot > 0 => &name[..dot],
+        _ => name,
+    };
+    format!("{stem}.{ext}")
+}
+
+impl Transform {
+    /// What the transform does, for progress output
+    fn label(self) -> &'static str {
+        match self {
+            Self::StripMetadata => "stripping metadata",
+            Self::Redact => "redacting",
+            Self::Compress => "compressing",
+            Self::Encrypt => "encrypting",
+        }
+    }
+
+    /// Wrap `stream` in this transform, counting removals in `removed`
+    fn apply(self, stream: Stream, removed: &Removed, key: Option<&Key>) -> Stream {
+        match self {
+            Self::StripMetadata => Stream {
+                reader: Box::new(StripMetadata::new(stream.reader, removed.metadata.clone())),
+                len: None,
+                ..stream
+            },
+            Self::Redact => Stream {
+                reader: Box::new(Redact {
+                    inner: BufReader::new(stream.reader),
+                    line: vec![],
+                    pos: 0,
+                    redacted: removed.secrets.clone(),
+                }),
+                len: None,
+                ..stream
+            },
+            Self::Compress => Stream {
+                reader: Box::new(GzEncoder::new(stream.reader, Compression::default())),
+                len: None,
+                file_name: stream.file_name.map(|name| format!("{name}.gz")),
+                mime: Some("application/gzip".to_string()),
+            },
+            Self::Encrypt => Stream {
+                reader: Box::new(Encrypt::new(stream.reader, key.unwrap())),
+                len: stream.len.map(crypt::encrypted_len),
+                file_name: stream.file_name.map(|name| format!("{name}.enc")),
+                mime: Some("application/octet-stream".to_string()),
+            },
+        }
+    }
+}
+
+/// Redacts secret values line by line
+struct Redact<R> {
+    /// Source of lines
+    inner: BufReader<R>,
+    /// Current redacted line
+    line: Vec<u8>,
+    /// Bytes of `line` already returned
+    pos: usize,
+    /// Count of lines that had values redacted
+    redacted: Arc<AtomicUsize>,
+}
+
+impl<R: Read> Read for Redact<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.line.len() {
+            self.line.clear();
+            self.pos = 0;
+            if self.inner.read_until(b'\n', &mut self.line)? == 0 {
+                return Ok(0);
+            }
+            let redacted = redact_line(&self.line);
+            if redacted != self.line {
+                self.redacted.fetch_add(1, Ordering::Relaxed);
+            }
+            self.line = redacted;
+        }
+
+        let n = buf.len().min(self.line.len() - self.pos);
+        buf[..n].copy_from_slice(&self.line[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Replace the value following any [`SECRET_KEYS`] key and `=`/`:` separator
+fn redact_line(line: &[u8]) -> Vec<u8> {
+    let lower = line.to_ascii_lowercase();
+    let mut out = Vec::with_capacity(line.len());
+    let mut i = 0;
+
+    while i < line.len() {
+        // Keys must start a word, but `_` and `-` join words, e.g. `DB_PASSWORD`
+        let starts_word = i == 0 || !line[i - 1].is_ascii_alphanumeric();
+        let key_end = SECRET_KEYS
+            .iter()
+            .find(|key| starts_word && lower[i..].starts_with(key))
+            .map(|key| i + key.len());
+        let Some(key_end) = key_end else {
+            out.push(line[i]);
+            i += 1;
+            continue;
+        };
+
+        // Allow suffixes like `_hash`, then quotes or spaces before the separator, e.g. `"token":`
+        let mut name_end = key_end;
+        while matches!(line.get(name_end), Some(b'_' | b'-'))
+            && line
+                .get(name_end + 1)
+                .is_some_and(|b| b.is_ascii_alphanumeric())
+        {
+            name_end += 1 + line[name_end + 1..]
+                .iter()
+                .take_while(|b| b.is_ascii_alphanumeric())
+                .count();
+        }
+        let sep = name_end
+            + line[name_end..]
+                .iter()
+                .take_while(|&&b| b"\"' \t".contains(&b))
+                .count();
+        let sep = Some(sep).filter(|&p| matches!(line.get(p), Some(b'=' | b':')));
+        let Some(sep) = sep else {
+            out.extend_from_slice(&line[i..key_end]);
+            i = key_end;
+            continue;
+        };
+
+        let value_start = sep
+            + 1
+            + line[sep + 1..]
+                .iter()
+                .take_while(|&&b| b == b' ' || b == b'\t' || b == b'"' || b == b'\'')
+                .count();
+        // Keep auth schemes (`Authorization: Bearer <token>`) readable
+        let value_start = if lower[value_start..].starts_with(b"bearer ")
+            || lower[value_start..].starts_with(b"basic ")
+        {
+            value_start + line[value_start..].iter().position(|&b| b == b' ').unwrap() + 1
+        } else {
+            value_start
+        };
+        let value_end = value_start
+            + line[value_start..]
+                .iter()
+                .take_while(|&&b| !b" \t\r\n\"',;".contains(&b))
+                .count();
+
+        out.extend_from_slice(&line[i..value_start]);
+        if value_end > value_start {
+            out.extend_from_slice(REDACTED);
+        }
+        i = value_end;
+    }
+    out
+}
+
+/// Image formats [`StripMetadata`] understands
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Image {
+    /// Not yet known
+    Unknown,
+    /// JPEG, inside the header segments
+    Jpeg,
+    /// PNG, inside the chunk stream
+    Png,
+    /// Image data or an unsupported format, passed through unchanged
+    Passthrough,
+}
+
+/// PNG file signature
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// PNG chunks carrying metadata rather than image data
+const PNG_METADATA_CHUNKS: &[&[u8]] = &[b"tEXt", b"zTXt", b"iTXt", b"eXIf", b"tIME"];
+
+/// Drops metadata segments from JPEG and PNG streams, passing anything else through
+struct StripMetadata<R> {
+    /// Source image
+    inner: R,
+    /// Detected format and position in it
+    state: Image,
+    /// Bytes ready to be returned
+    pending: Vec<u8>,
+    /// Bytes of `pending` already returned
+    pos: usize,
+    /// Count of segments and chunks dropped
+    stripped: Arc<AtomicUsize>,
+}
+
+impl<R: Read> StripMetadata<R> {
+    /// Strip metadata from `inner`, counting what is dropped in `stripped`
+    fn new(inner: R, stripped: Arc<AtomicUsize>) -> Self {
+        Self {
+            inner,
+            state: Image::Unknown,
+            pending: vec![],
+            pos: 0,
+            stripped,
+        }
+    }
+
+    /// Drop the current segment or chunk
+    fn strip(&mut self) -> Vec<u8> {
+        self.stripped.fetch_add(1, Ordering::Relaxed);
+        vec![]
+    }
+
+    /// Read exactly `n` bytes, or fewer at end of input
+    fn take(&mut self, n: usize) -> io::Result<Vec<u8>> {
+        let mut buf = vec![];
+        (&mut self.inner).take(n as u64).read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Queue the next structural unit (signature, segment or chunk) into `pending`
+    fn fill(&mut self) -> io::Result<()> {
+        self.pos = 0;
+
+        match self.state {
+            Image::Unknown => {
+                self.pending = self.take(2)?;
+                if self.pending == b"\xFF\xD8" {
+                    self.state = Image::Jpeg;
+                    return Ok(());
+                }
+                let rest = self.take(PNG_SIGNATURE.len() - 2)?;
+                self.pending.extend_from_slice(&rest);
+                self.state = if self.pending == PNG_SIGNATURE {
+                    Image::Png
+                } else {
+                    Image::Passthrough
+                };
+            }
+            Image::Jpeg => {
+                let marker = self.take(2)?;
+                if marker.len() < 2 || marker[0] != 0xFF {
+                    self.pending = marker;
+                    self.state = Image::Passthrough;
+                    return Ok(());
+                }
+                // Standalone markers have no length
+                if matches!(marker[1], 0x01 | 0xD0..=0xD9) {
+                    self.pending = marker;
+                    return Ok(());
+                }
+                let len_bytes = self.take(2)?;
+                let len = match *len_bytes {
+                    [hi, lo] => u16::from_be_bytes([hi, lo]).max(2),
+                    _ => 2,
+                };
+                let body = self.take(usize::from(len) - 2)?;
+                self.pending = match marker[1] {
+                    // APP1 (EXIF/XMP), APP13 (IPTC), COM; APP0, APP2 (ICC) and APP14 are kept
+                    0xE1 | 0xED | 0xFE => self.strip(),
+                    // Start of scan: the image data follows
+                    0xDA => {
+                        self.state = Image::Passthrough;
+                        [marker, len_bytes, body].concat()
+                    }
+                    _ => [marker, len_bytes, body].concat(),
+                };
+            }
+            Image::Png => {
+                let header = self.take(8)?;
+                if header.len() < 8 {
+                    self.pending = header;
+                    self.state = Image::Passthrough;
+                    return Ok(());
+                }
+                let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+                // Data plus CRC
+                let body = self.take(len as usize + 4)?;
+                self.pending = if PNG_METADATA_CHUNKS.contains(&&header[4..8]) {
+                    self.strip()
+                } else {
+                    [header, body].concat()
+                };
+            }
+            Image::Passthrough => {
+                self.pending.resize(64 * 1024, 0);
+                let n = self.inner.read(&mut self.pending)?;
+                self.pending.truncate(n);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for StripMetadata<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.pending.len() {
+            self.fill()?;
+            // Skipped segments leave nothing pending without reaching the end of input
+            if self.pending.is_empty() && self.state == Image::Passthrough {
+                return Ok(0);
+            }
+        }
+
+        let n = buf.len().min(self.pending.len() - self.pos);
+        buf[..n].copy_from_slice(&self.pending[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(transform: Transform, input: &[u8]) -> Vec<u8> {
+        let stream = Pipeline::new([transform]).apply(
+            Stream {
+                reader: Box::new(io::Cursor::new(input.to_vec())),
+                len: Some(input.len() as u64),
+                file_name: Some("file".to_string()),
+                mime: Some("text/plain".to_string()),
+            },
+            &Progress::default(),
+        );
+        let mut out = vec![];
+        { stream.reader }.read_to_end(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn redacts_secret_values() {
+        let input =
+            b"user=alice\nPASSWORD=hunter2\nDB_PASSWORD_HASH = x1\n\"api_key\": \"abc123\",\nAuthorization: Bearer xyz\n";
+        assert_eq!(
+            String::from_utf8(run(Transform::Redact, input)).unwrap(),
+            "user=alice\nPASSWORD=[REDACTED]\nDB_PASSWORD_HASH = [REDACTED]\n\"api_key\": \"[REDACTED]\",\nAuthorization: Bearer [REDACTED]\n"
+        );
+    }
+
+    #[test]
+    fn redact_leaves_prose_alone() {
+        let input = b"the token is rotated daily\nthe token is rotated daily: yes\ntokenizer: bpe\nHi secretary: Ann\nmytoken=abc\n";
+        assert_eq!(run(Transform::Redact, input), input);
+    }
+
+    #[test]
+    fn strips_jpeg_exif() {
+        let app0 = b"\xFF\xE0\x00\x04ab";
+        let app1 = b"\xFF\xE1\x00\x06Exif";
+        let sos = b"\xFF\xDA\x00\x02\x12\x34\xFF\xD9";
+        let input = [&b"\xFF\xD8"[..], app0, app1, sos].concat();
+        let expected = [&b"\xFF\xD8"[..], app0, sos].concat();
+        assert_eq!(run(Transform::StripMetadata, &input), expected);
+    }
+
+    #[test]
+    fn strips_png_text() {
+        let ihdr = b"\x00\x00\x00\x01IHDRx\x00\x00\x00\x00";
+        let text = b"\x00\x00\x00\x02tEXtab\x00\x00\x00\x00";
+        let iend = b"\x00\x00\x00\x00IEND\x00\x00\x00\x00";
+        let input = [PNG_SIGNATURE, ihdr, text, iend].concat();
+        let expected = [PNG_SIGNATURE, ihdr, iend].concat();
+        assert_eq!(run(Transform::StripMetadata, &input), expected);
+    }
+
+    #[test]
+    fn passes_other_files_through() {
+        let input = b"plain text, not an image";
+        assert_eq!(run(Transform::StripMetadata, input), input);
+    }
+
+    #[test]
+    fn counts_removals() {
+        let pipeline = Pipeline::new([Transform::StripMetadata, Transform::Redact]);
+        let app1 = b"\xFF\xE1\x00\x06Exif";
+        let sos = b"\xFF\xDA\x00\x02\x12\x34\xFF\xD9";
+        let jpeg = [&b"\xFF\xD8"[..], app1, app1, sos].concat();
+        let text = b"user=alice\ntoken=abc secret=def\npassword=x\n";
+        for input in [&jpeg[..], text] {
+            let stream = pipeline.apply(
+                Stream {
+                    reader: Box::new(io::Cursor::new(input.to_vec())),
+                    len: None,
+                    file_name: None,
+                    mime: None,
+                },
+                &Progress::default(),
+            );
+            io::copy(&mut { stream.reader }, &mut io::sink()).unwrap();
+        }
+        assert_eq!(pipeline.stripped_metadata(), 2);
+        assert_eq!(pipeline.redacted_lines(), 2);
+    }
+
+    #[test]
+    fn compress_renames() {
+        let stream = Pipeline::new([Transform::Compress, Transform::Redact]).apply(
+            Stream {
+                reader: Box::new(io::empty()),
+                len: Some(0),
+                file_name: Some("notes.txt".to_string()),
+                mime: Some("text/plain".to_string()),
+            },
+            &Progress::default(),
+        );
+        assert_eq!(stream.file_name.as_deref(), Some("notes.txt.gz"));
+        assert_eq!(stream.mime.as_deref(), Some("application/gzip"));
+        assert_eq!(stream.len, None);
+    }
+
+    #[test]
+    fn retypes_before_transforms() {
+        let retype = |name: &str, content_type: Option<&str>, ext: Option<&str>| {
+            let stream = Pipeline::default()
+                .retyped(content_type.map(Into::into), ext.map(Into::into))
+                .apply(
+                    Stream {
+                        reader: Box::new(io::empty()),
+                        len: Some(0),
+                        file_name: Some(name.to_string()),
+                        mime: Some("application/json".to_string()),
+                    },
+                    &Progress::default(),
+                );
+            (stream.file_name.unwrap(), stream.mime.unwrap())
+        };
+        assert_eq!(
+            retype("app.json", Some("text/plain"), None),
+            ("app.json".to_string(), "text/plain".to_string())
+        );
+        assert_eq!(
+            retype("app.json", None, Some("txt")),
+            ("app.txt".to_string(), "text/plain".to_string())
+        );
+        assert_eq!(retype(".env", None, Some("log")).0, ".env.log".to_string());
+        assert_eq!(
+            retype("app.json", None, Some("unknownext")).1,
+            "application/json".to_string()
+        );
+
+        let stream = Pipeline::new([Transform::Compress])
+            .retyped(Some("text/plain".to_string()), Some("txt".to_string()))
+            .apply(
+                Stream {
+                    reader: Box::new(io::empty()),
+                    len: Some(0),
+                    file_name: Some("app.json".to_string()),
+                    mime: None,
+                },
+                &Progress::default(),
+            );
+        assert_eq!(stream.file_name.as_deref(), Some("app.txt.gz"));
+        assert_eq!(stream.mime.as_deref(), Some("application/gzip"));
+    }
+}
@@ -0,0 +1,115 @@
+//! Entries of deleted uploads, kept in the history file until purged
+
+use jiff::tz::TimeZone;
+
+use crate::error::{EnvshError, Result};
+use crate::history::{self, History};
+use crate::{config, json, keyring, preview};
+
+/// Print each entry in the trash with when its upload was deleted
+pub(crate) fn list(json: bool) -> Result<()> {
+    let history = History::load()?;
+    if json {
+        for trashed in &history.trash {
+            json::print(&json::Trashed {
+                id: trashed.entry.id,
+                url: trashed.entry.url.clone(),
+                input: trashed.entry.input.clone(),
+                token: trashed.entry.token.is_some() || trashed.entry.keyring,
+                deleted: json::rfc3339(&trashed.deleted.to_zoned(TimeZone::UTC)),
+            });
+        }
+    } else if history.trash.is_empty() {
+        println!("The trash is empty");
+    } else {
+        let time_format = &config::get().output.time_format;
+        for trashed in &history.trash {
+            let deleted = trashed
+                .deleted
+                .to_zoned(TimeZone::system())
+                .strftime(time_format);
+            println!(
+                "@{}  {}  {}  (deleted {deleted})",
+                trashed.entry.id, trashed.entry.url, trashed.entry.input
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Move the entry with `id` back into the history
+pub(crate) fn restore(id: u64) -> Result<()> {
+    history::check_writable("trash restore")?;
+    let mut history = History::load()?;
+    let Some(entry) = history.restore(id) else {
+        return Err(missing(id));
+    };
+    println!("Restored @{id} ({})", entry.url);
+    history.save()
+}
+
+/// Drop the entries with `ids` from the trash for good, or all of them, with their X-Tokens
+/// in the keyring
+pub(crate) fn purge(ids: &[u64], yes: bool) -> Result<()> {
+    history::check_writable("trash purge")?;
+    let mut history = History::load()?;
+    if let Some(&id) = ids
+        .iter()
+        .find(|&&id| !history.trash.iter().any(|t| t.entry.id == id))
+    {
+        return Err(missing(id));
+    }
+    let (purged, kept): (Vec<_>, _) = std::mem::take(&mut history.trash)
+        .into_iter()
+        .partition(|t| ids.is_empty() || ids.contains(&t.entry.id));
+    if purged.is_empty() {
+        println!("The trash is empty");
+        return Ok(());
+    }
+    if ids.is_empty() && !yes {
+        let question = match purged.len() {
+            1 => "Purge the upload in the trash?".to_string(),
+            n => format!("Purge all {n} uploads in the trash?"),
+        };
+        if !preview::ask(&question)? {
+            println!("Nothing purged");
+            return Ok(());
+        }
+    }
+
+    // An entry whose token can't be removed stays in the trash, to purge again later
+    let total = purged.len();
+    let mut failed = 0;
+    history.trash = kept;
+    for trashed in purged {
+        let url = &trashed.entry.url;
+        // A later upload of the same file shares its keyring entry
+        let shared = history.entries.iter().any(|e| &e.url == url);
+        if trashed.entry.keyring
+            && !shared
+            && let Err(e) = keyring::delete(url)
+        {
+            eprintln!("Failed to remove X-Token for {url} from the keyring: {e}");
+            history.trash.push(trashed);
+            failed += 1;
+        }
+    }
+    history.save()?;
+    match (total - failed, failed) {
+        (_, 0) if total == 1 => println!("Purged 1 upload"),
+        (purged, 0) => println!("Purged {purged} uploads"),
+        (purged, failed) => {
+            return Err(EnvshError::Failed(format!(
+                "purged {purged} of {total} uploads, {failed} stayed in the trash"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Error for an ID that isn't in the trash
+fn missing(id: u64) -> EnvshError {
+    EnvshError::Usage(format!(
+        "no upload with ID @{id} in the trash, see `envsh trash list`"
+    ))
+}